@@ -1,4 +1,5 @@
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
 use std::vec::Vec;
 
 use byteordered::Endianness;
@@ -10,6 +11,7 @@ use crate::bbi::{
     CIR_TREE_MAGIC,
 };
 use crate::bed::bedparser::BedValueError;
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::reopen::SeekableRead;
 
 #[derive(Copy, Clone, Debug)]
@@ -63,6 +65,8 @@ pub struct BBIFileInfo {
     pub zoom_headers: Vec<ZoomHeader>,
     /// The chromosome info the bbi file is based on
     pub chrom_info: Vec<ChromInfo>,
+    /// Alternate names for chromosomes, used when querying
+    pub(crate) chrom_aliases: Option<Arc<ChromAliases>>,
 }
 
 pub(crate) struct ChromIdNotFound(pub(crate) String);
@@ -76,7 +80,10 @@ impl From<ChromIdNotFound> for BBIReadError {
 impl BBIFileInfo {
     pub(crate) fn chrom_id(&self, chrom_name: &str) -> Result<u32, ChromIdNotFound> {
         let chrom_info = &self.chrom_info;
-        let chrom = chrom_info.iter().find(|&x| x.name == chrom_name);
+        let chrom = match &self.chrom_aliases {
+            Some(aliases) => aliases.resolve(chrom_name, chrom_info),
+            None => chrom_info.iter().find(|&x| x.name == chrom_name),
+        };
         match chrom {
             Some(c) => Ok(c.id),
             None => Err(ChromIdNotFound(chrom_name.to_owned())),
@@ -160,18 +167,10 @@ pub(crate) trait BBIReadInternal: BBIRead {
         end: u32,
     ) -> Result<Vec<Block>, CirTreeSearchError> {
         // TODO: Move anything relying on self out to separate method
-        let chrom_ix = {
-            let chrom_info = &self.get_info().chrom_info;
-            let chrom = chrom_info.iter().find(|&x| x.name == chrom_name);
-            match chrom {
-                Some(c) => c.id,
-                None => {
-                    return Err(CirTreeSearchError::InvalidChromosome(
-                        chrom_name.to_string(),
-                    ));
-                }
-            }
-        };
+        let chrom_ix = self
+            .get_info()
+            .chrom_id(chrom_name)
+            .map_err(|e| CirTreeSearchError::InvalidChromosome(e.0))?;
 
         let endianness = self.get_info().header.endianness;
         let mut file = self.reader();
@@ -393,6 +392,7 @@ pub(crate) fn read_info<R: SeekableRead>(
         header,
         zoom_headers,
         chrom_info,
+        chrom_aliases: None,
     };
 
     Ok(info)
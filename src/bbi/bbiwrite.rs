@@ -1,13 +1,15 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::iter::Flatten;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::vec;
 
-use byteorder::{NativeEndian, WriteBytesExt};
+use byteorder::{ByteOrder, NativeEndian, WriteBytesExt};
 use crossbeam_channel::unbounded;
-use thiserror::Error;
 
 use futures::channel::mpsc as futures_mpsc;
 use futures::channel::mpsc::channel;
@@ -17,6 +19,7 @@ use futures::stream::StreamExt;
 
 use serde::{Deserialize, Serialize};
 
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::chromvalues::ChromValues;
 use crate::utils::idmap::IdMap;
 use crate::utils::tell::Tell;
@@ -73,48 +76,358 @@ pub enum InputSortType {
     //NONE,
 }
 
+/// What to do with input chromosomes that aren't in the chromosome sizes
+/// (after any aliases have been applied)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnknownChroms {
+    /// Stop writing with `ProcessChromError::InvalidChromosome`
+    Error,
+    /// Skip the chromosome's values. Each dropped chromosome is reported to
+    /// `WriteProgress::chrom_dropped`.
+    Drop,
+}
+
+/// How overlapping values within a chromosome are handled when writing a
+/// bigWig. Overlaps are resolved by splitting the values where they overlap,
+/// so the written values never overlap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Stop writing with `ProcessChromError::InvalidInput`
+    Error,
+    /// Use the sum of the overlapping values
+    Sum,
+    /// Use the maximum of the overlapping values
+    Max,
+    /// Use the mean of the overlapping values
+    Mean,
+    /// Use the value that comes last in the input
+    Last,
+}
+
+/// How the data and zoom sections of a bbi file are compressed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Sections are written uncompressed
+    None,
+    /// Sections are compressed with zlib at `level` (`0`-`12`). Lower levels
+    /// are faster, higher levels give smaller files. Levels above `9` are only
+    /// supported by libdeflate, but the output is still standard zlib.
+    Zlib { level: u8 },
+}
+
 /// The default block size used when writing a bbi file
 pub const DEFAULT_BLOCK_SIZE: u32 = 256;
 /// The default items per slot used when writing a bbi file
 pub const DEFAULT_ITEMS_PER_SLOT: u32 = 1024;
+/// The default zlib compression level used when writing a bbi file
+pub const DEFAULT_COMPRESSION_LEVEL: u8 = 6;
+/// The maximum zlib compression level supported when writing a bbi file
+pub const MAX_COMPRESSION_LEVEL: u8 = 12;
 
 /// Options for writing to a bbi file
 #[derive(Copy, Clone)]
 pub struct BBIWriteOptions {
-    pub compress: bool,
+    pub compression: Compression,
     pub items_per_slot: u32,
     pub block_size: u32,
     pub initial_zoom_size: u32,
     pub max_zooms: u32,
     pub input_sort_type: InputSortType,
     pub channel_size: usize,
+    pub unknown_chroms: UnknownChroms,
+    /// Only used for bigWigs
+    pub overlap_policy: OverlapPolicy,
 }
 
 impl Default for BBIWriteOptions {
     fn default() -> Self {
         BBIWriteOptions {
-            compress: true,
+            compression: Compression::Zlib {
+                level: DEFAULT_COMPRESSION_LEVEL,
+            },
             items_per_slot: DEFAULT_ITEMS_PER_SLOT,
             block_size: DEFAULT_BLOCK_SIZE,
             initial_zoom_size: 160,
             max_zooms: 10,
             input_sort_type: InputSortType::ALL,
             channel_size: 100,
+            unknown_chroms: UnknownChroms::Error,
+            overlap_policy: OverlapPolicy::Error,
+        }
+    }
+}
+
+impl BBIWriteOptions {
+    pub(crate) fn validate<E>(&self) -> Result<(), ProcessChromError<E>> {
+        if let Compression::Zlib { level } = self.compression {
+            if level > MAX_COMPRESSION_LEVEL {
+                return Err(ProcessChromError::InvalidInput(format!(
+                    "Invalid compression level: {} (must be at most {})",
+                    level, MAX_COMPRESSION_LEVEL
+                )));
+            }
         }
+        Ok(())
     }
 }
 
 /// Possible errors encountered when processing a chromosome when writing a bbi file
-#[derive(Error, Debug)]
+#[derive(Debug)]
 pub enum ProcessChromError<SourceError> {
-    #[error("{}", .0)]
     InvalidInput(String),
-    #[error("{}", .0)]
     InvalidChromosome(String),
-    #[error("{}", .0)]
-    IoError(#[from] io::Error),
-    #[error("SourceError")]
+    IoError(io::Error),
     SourceError(SourceError),
+    Cancelled,
+    /// An error while processing a specific chromosome
+    InChrom {
+        chrom: String,
+        error: Box<ProcessChromError<SourceError>>,
+    },
+}
+
+// Implemented by hand, since the `Display` bound on `SourceError` isn't
+// inferred by `thiserror`
+impl<SourceError: fmt::Display> fmt::Display for ProcessChromError<SourceError> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessChromError::InvalidInput(e) => write!(f, "{}", e),
+            ProcessChromError::InvalidChromosome(e) => write!(f, "{}", e),
+            ProcessChromError::IoError(e) => write!(f, "{}", e),
+            ProcessChromError::SourceError(e) => write!(f, "{}", e),
+            ProcessChromError::Cancelled => write!(f, "Writing was cancelled."),
+            ProcessChromError::InChrom { chrom, error } => {
+                write!(f, "Error while processing chromosome {}: {}", chrom, error)
+            }
+        }
+    }
+}
+
+impl<SourceError: fmt::Debug + fmt::Display> std::error::Error for ProcessChromError<SourceError> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProcessChromError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<SourceError> From<io::Error> for ProcessChromError<SourceError> {
+    fn from(e: io::Error) -> Self {
+        ProcessChromError::IoError(e)
+    }
+}
+
+impl<SourceError> ProcessChromError<SourceError> {
+    /// Adds the chromosome being processed to the error. Cancellation and
+    /// errors that already have a chromosome are left as-is.
+    pub fn in_chrom(self, chrom: &str) -> Self {
+        match self {
+            e @ (ProcessChromError::Cancelled | ProcessChromError::InChrom { .. }) => e,
+            e => ProcessChromError::InChrom {
+                chrom: chrom.to_string(),
+                error: Box::new(e),
+            },
+        }
+    }
+}
+
+/// The phases of writing a bbi file. Depending on how the file is written,
+/// phases may be entered in a different order, or more than once.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WritePhase {
+    /// Reading the input and writing the data sections
+    Data,
+    /// Writing the zoom sections and their indices
+    Zooms,
+    /// Writing the chromosome tree and the data index
+    Index,
+}
+
+/// An observer of the progress of writing a bbi file. All methods do nothing by
+/// default, and may be called concurrently from multiple threads.
+///
+/// Chromosomes and items are reported for every pass over the input, so with
+/// `BigWigWrite::write_multipass` they are reported once during
+/// `WritePhase::Data` and again during `WritePhase::Zooms`.
+pub trait WriteProgress: Send + Sync {
+    /// A new phase of writing has started
+    fn phase_started(&self, _phase: WritePhase) {}
+    /// Processing of a chromosome has started
+    fn chrom_started(&self, _chrom: &str) {}
+    /// Processing of a chromosome has finished
+    fn chrom_finished(&self, _chrom: &str) {}
+    /// An input chromosome that isn't in the chromosome sizes was dropped
+    /// (with `UnknownChroms::Drop`)
+    fn chrom_dropped(&self, _chrom: &str) {}
+    /// Additional input items have been processed
+    fn items_processed(&self, _items: u64) {}
+    /// Additional bytes of encoded (and possibly compressed) data or zoom
+    /// sections have been written to the output. These are output bytes, so
+    /// they can't be compared to the size of the input: use `items_processed`
+    /// for progress through the input. This includes zooms that are later
+    /// discarded for being too large.
+    fn encoded_bytes_written(&self, _bytes: u64) {}
+}
+
+/// A token that can be used to cancel writing a bbi file, possibly from another
+/// thread. When cancelled, writing stops with `ProcessChromError::Cancelled`
+/// and any partially-written output file is removed.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Requests that any writes using this token stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The progress observer and cancellation token of a single write
+#[derive(Clone, Default)]
+pub(crate) struct WriteObserver {
+    pub(crate) progress: Option<Arc<dyn WriteProgress>>,
+    pub(crate) cancellation: Option<CancellationToken>,
+}
+
+impl WriteObserver {
+    pub(crate) fn new(
+        progress: Option<Arc<dyn WriteProgress>>,
+        cancellation: Option<CancellationToken>,
+    ) -> Self {
+        WriteObserver {
+            progress,
+            cancellation,
+        }
+    }
+
+    pub(crate) fn phase_started(&self, phase: WritePhase) {
+        if let Some(progress) = &self.progress {
+            progress.phase_started(phase);
+        }
+    }
+
+    fn chrom_started(&self, chrom: &str) {
+        if let Some(progress) = &self.progress {
+            progress.chrom_started(chrom);
+        }
+    }
+
+    fn chrom_finished(&self, chrom: &str) {
+        if let Some(progress) = &self.progress {
+            progress.chrom_finished(chrom);
+        }
+    }
+
+    fn chrom_dropped(&self, chrom: &str) {
+        if let Some(progress) = &self.progress {
+            progress.chrom_dropped(chrom);
+        }
+    }
+
+    fn encoded_bytes_written(&self, bytes: u64) {
+        if let Some(progress) = &self.progress {
+            progress.encoded_bytes_written(bytes);
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .map(|c| c.is_cancelled())
+            .unwrap_or(false)
+    }
+
+    /// Returns `ProcessChromError::Cancelled` if the write was cancelled
+    pub(crate) fn check_cancelled<E>(&self) -> Result<(), ProcessChromError<E>> {
+        match self.is_cancelled() {
+            true => Err(ProcessChromError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// If `result` is `ProcessChromError::Cancelled`, removes the partial output at `path`
+    pub(crate) fn cleanup<T, E>(
+        path: &str,
+        result: Result<T, ProcessChromError<E>>,
+    ) -> Result<T, ProcessChromError<E>> {
+        if let Err(ProcessChromError::Cancelled) = &result {
+            // The output may not have been created yet
+            let _ = std::fs::remove_file(path);
+        }
+        result
+    }
+}
+
+/// The number of items to process before reporting them to a `WriteProgress`
+const ITEMS_REPORT_INTERVAL: u64 = 1 << 14;
+
+/// Wraps the values of a chromosome being written, reporting the number of
+/// items processed and ending the values early if the write is cancelled.
+pub(crate) struct ObservedValues<I> {
+    inner: I,
+    observer: WriteObserver,
+    unreported_items: u64,
+}
+
+impl<I> ObservedValues<I> {
+    fn new(inner: I, observer: WriteObserver) -> Self {
+        ObservedValues {
+            inner,
+            observer,
+            unreported_items: 0,
+        }
+    }
+
+    fn report_items(&mut self) {
+        if self.unreported_items == 0 {
+            return;
+        }
+        if let Some(progress) = &self.observer.progress {
+            progress.items_processed(self.unreported_items);
+        }
+        self.unreported_items = 0;
+    }
+}
+
+impl<I: ChromValues> ChromValues for ObservedValues<I> {
+    type Value = I::Value;
+    type Error = I::Error;
+
+    fn next(&mut self) -> Option<Result<Self::Value, Self::Error>> {
+        if self.observer.is_cancelled() {
+            return None;
+        }
+        let next = self.inner.next();
+        if next.is_some() {
+            self.unreported_items += 1;
+            if self.unreported_items >= ITEMS_REPORT_INTERVAL {
+                self.report_items();
+            }
+        }
+        next
+    }
+
+    fn peek(&mut self) -> Option<Result<&Self::Value, &Self::Error>> {
+        if self.observer.is_cancelled() {
+            return None;
+        }
+        self.inner.peek()
+    }
+}
+
+impl<I> Drop for ObservedValues<I> {
+    fn drop(&mut self) {
+        self.report_items();
+    }
 }
 
 pub(crate) struct TempZoomInfo<SourceError> {
@@ -197,8 +510,8 @@ pub(crate) fn write_info<T>(
     Ok(())
 }
 
-pub(crate) fn write_chrom_tree(
-    file: &mut BufWriter<File>,
+pub(crate) fn write_chrom_tree<E: ByteOrder, W: Write>(
+    file: &mut W,
     chrom_sizes: std::collections::HashMap<String, u32>,
     chrom_ids: &std::collections::HashMap<String, u32>,
 ) -> io::Result<()> {
@@ -212,18 +525,18 @@ pub(crate) fn write_chrom_tree(
     let block_size = std::cmp::max(256, item_count) as u32;
     let max_bytes = chroms.iter().map(|a| a.len() as u32).fold(0, u32::max);
 
-    file.write_u32::<NativeEndian>(CHROM_TREE_MAGIC)?;
-    file.write_u32::<NativeEndian>(block_size)?;
-    file.write_u32::<NativeEndian>(max_bytes)?;
-    file.write_u32::<NativeEndian>(8)?; // size of Id (u32) + Size (u32)
-    file.write_u64::<NativeEndian>(item_count)?;
-    file.write_u64::<NativeEndian>(0)?; // Reserved
+    file.write_u32::<E>(CHROM_TREE_MAGIC)?;
+    file.write_u32::<E>(block_size)?;
+    file.write_u32::<E>(max_bytes)?;
+    file.write_u32::<E>(8)?; // size of Id (u32) + Size (u32)
+    file.write_u64::<E>(item_count)?;
+    file.write_u64::<E>(0)?; // Reserved
 
     // Assuming this is all one block right now
     // TODO: add non-leaf nodes and split blocks
     file.write_u8(1)?;
     file.write_u8(0)?;
-    file.write_u16::<NativeEndian>(item_count as u16)?;
+    file.write_u16::<E>(item_count as u16)?;
     for chrom in chroms {
         let key_bytes = &mut vec![0u8; max_bytes as usize];
         let chrom_bytes = chrom.as_bytes();
@@ -232,24 +545,54 @@ pub(crate) fn write_chrom_tree(
         let id = *chrom_ids
             .get(chrom)
             .expect("Internal error. (Chrom not found).");
-        file.write_u32::<NativeEndian>(id)?;
+        file.write_u32::<E>(id)?;
         let length = chrom_sizes.get(&chrom[..]);
         match length {
             None => panic!("Expected length for chrom: {}", chrom),
             Some(l) => {
-                file.write_u32::<NativeEndian>(*l)?;
+                file.write_u32::<E>(*l)?;
             }
         }
     }
     Ok(())
 }
 
+/// Compresses the bytes of a single data or zoom section according to
+/// `options`. Returns the bytes to write and the uncompressed size of the
+/// section, which is `0` if the section is left uncompressed.
+pub(crate) fn compress_section(
+    bytes: Vec<u8>,
+    options: BBIWriteOptions,
+) -> io::Result<(Vec<u8>, usize)> {
+    use libdeflater::{CompressionLvl, Compressor};
+
+    let level = match options.compression {
+        Compression::None => return Ok((bytes, 0)),
+        Compression::Zlib { level } => level,
+    };
+    let level = CompressionLvl::new(i32::from(level)).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid compression level: {} (must be at most {})",
+                level, MAX_COMPRESSION_LEVEL
+            ),
+        )
+    })?;
+    let mut compressor = Compressor::new(level);
+    let max_sz = compressor.zlib_compress_bound(bytes.len());
+    let mut compressed_data = vec![0; max_sz];
+    let actual_sz = compressor
+        .zlib_compress(&bytes, &mut compressed_data)
+        .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+    compressed_data.resize(actual_sz, 0);
+    Ok((compressed_data, bytes.len()))
+}
+
 pub(crate) async fn encode_zoom_section(
-    compress: bool,
+    options: BBIWriteOptions,
     items_in_section: Vec<ZoomRecord>,
 ) -> io::Result<(SectionData, usize)> {
-    use libdeflater::{CompressionLvl, Compressor};
-
     let mut bytes = Vec::with_capacity(items_in_section.len() * 32);
 
     let start = items_in_section[0].start;
@@ -267,18 +610,7 @@ pub(crate) async fn encode_zoom_section(
         bytes.write_f32::<NativeEndian>(item.summary.sum_squares as f32)?;
     }
 
-    let (out_bytes, uncompressed_buf_size) = if compress {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let max_sz = compressor.zlib_compress_bound(bytes.len());
-        let mut compressed_data = vec![0; max_sz];
-        let actual_sz = compressor
-            .zlib_compress(&bytes, &mut compressed_data)
-            .unwrap();
-        compressed_data.resize(actual_sz, 0);
-        (compressed_data, bytes.len())
-    } else {
-        (bytes, 0)
-    };
+    let (out_bytes, uncompressed_buf_size) = compress_section(bytes, options)?;
 
     Ok((
         SectionData {
@@ -577,6 +909,164 @@ pub trait ChromData: Sized {
     >;
 }
 
+/// `ChromData` from an iterator of chromosomes and their values, where an
+/// `Err` is reported as a source error. The next few chromosomes (up to
+/// `parallel_chroms` in total) are started before the current one is
+/// returned, so that they are processed in parallel.
+pub struct QueuedChromData<I, V: ChromValues> {
+    chroms: I,
+    parallel_chroms: usize,
+    queued_reads: VecDeque<QueuedRead<V::Error>>,
+}
+
+type QueuedRead<E> = Result<ChromDataState<ChromProcessingKey, E>, ProcessChromError<E>>;
+
+impl<I, V> QueuedChromData<I, V>
+where
+    I: Iterator<Item = Result<(String, V), V::Error>>,
+    V: ChromValues,
+{
+    pub fn new(chroms: I, parallel_chroms: usize) -> Self {
+        QueuedChromData {
+            chroms,
+            parallel_chroms: parallel_chroms.max(1),
+            queued_reads: VecDeque::new(),
+        }
+    }
+}
+
+impl<I, V> ChromData for QueuedChromData<I, V>
+where
+    I: Iterator<Item = Result<(String, V), V::Error>>,
+    V: ChromValues,
+{
+    type Values = V;
+
+    fn advance<
+        State,
+        F: FnMut(String, V, &mut State) -> Result<ChromProcessingKey, ProcessChromError<V::Error>>,
+    >(
+        &mut self,
+        do_read: &mut F,
+        state: &mut State,
+    ) -> Result<ChromDataState<ChromProcessingKey, V::Error>, ProcessChromError<V::Error>> {
+        while self.queued_reads.len() < self.parallel_chroms
+            && matches!(
+                self.queued_reads.back(),
+                None | Some(Ok(ChromDataState::NewChrom(..)))
+            )
+        {
+            let next = match self.chroms.next() {
+                Some(Ok((chrom, values))) => {
+                    do_read(chrom, values, state).map(ChromDataState::NewChrom)
+                }
+                Some(Err(e)) => Ok(ChromDataState::Error(e)),
+                None => Ok(ChromDataState::Finished),
+            };
+            self.queued_reads.push_back(next);
+        }
+        self.queued_reads.pop_front().unwrap()
+    }
+}
+
+// Returned by `do_read` for chromosomes that were dropped. These are never
+// passed to the writing functions.
+const DROPPED_CHROM: ChromProcessingKey = ChromProcessingKey(u32::MAX);
+
+/// Wraps the input of a write, renaming chromosomes that aren't in the
+/// chromosome sizes to one of their aliases, and dropping any that are still
+/// unknown if `UnknownChroms::Drop` is set.
+pub(crate) struct AliasedChromData<'a, V> {
+    inner: V,
+    aliases: Option<&'a ChromAliases>,
+    chrom_sizes: &'a HashMap<String, u32>,
+    unknown_chroms: UnknownChroms,
+    observer: WriteObserver,
+    renamed: HashMap<String, String>,
+}
+
+impl<'a, V> AliasedChromData<'a, V> {
+    pub(crate) fn new(
+        inner: V,
+        aliases: Option<&'a ChromAliases>,
+        chrom_sizes: &'a HashMap<String, u32>,
+        options: BBIWriteOptions,
+        observer: WriteObserver,
+    ) -> Self {
+        AliasedChromData {
+            inner,
+            aliases,
+            chrom_sizes,
+            unknown_chroms: options.unknown_chroms,
+            observer,
+            renamed: HashMap::new(),
+        }
+    }
+}
+
+impl<'a, V: ChromData> ChromData for AliasedChromData<'a, V> {
+    type Values = V::Values;
+
+    fn advance<
+        State,
+        F: FnMut(
+            String,
+            Self::Values,
+            &mut State,
+        ) -> Result<
+            ChromProcessingKey,
+            ProcessChromError<<Self::Values as ChromValues>::Error>,
+        >,
+    >(
+        &mut self,
+        do_read: &mut F,
+        state: &mut State,
+    ) -> Result<
+        ChromDataState<ChromProcessingKey, <Self::Values as ChromValues>::Error>,
+        ProcessChromError<<Self::Values as ChromValues>::Error>,
+    > {
+        let chrom_sizes = self.chrom_sizes;
+        let aliases = self.aliases;
+        let unknown_chroms = self.unknown_chroms;
+        let renamed = &mut self.renamed;
+        let observer = &self.observer;
+        let mut rename = |chrom: String, mut data: Self::Values, state: &mut State| {
+            let name = match aliases {
+                _ if chrom_sizes.contains_key(&chrom) => Some(chrom.clone()),
+                Some(aliases) => aliases.find(&chrom, |n| chrom_sizes.contains_key(n)),
+                None => None,
+            };
+            match name {
+                Some(name) => {
+                    // Two input chromosomes can't be written as one
+                    if let Some(prev) = renamed.insert(name.clone(), chrom.clone()) {
+                        return Err(ProcessChromError::InvalidChromosome(format!(
+                            "Input chromosomes {} and {} are both aliases of {}",
+                            prev, chrom, name
+                        )));
+                    }
+                    do_read(name, data, state)
+                }
+                None if unknown_chroms == UnknownChroms::Drop => {
+                    while let Some(value) = data.next() {
+                        value.map_err(ProcessChromError::SourceError)?;
+                    }
+                    observer.chrom_dropped(&chrom);
+                    Ok(DROPPED_CHROM)
+                }
+                // Reported when looking up the length
+                None => do_read(chrom, data, state),
+            }
+        };
+        loop {
+            match self.inner.advance(&mut rename, state)? {
+                ChromDataState::NewChrom(key) if key.0 == DROPPED_CHROM.0 => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+}
+
 // Zooms have to be double-buffered: first because chroms could be processed in parallel and second because we don't know the offset of each zoom immediately
 type ZoomValue = (
     Vec<crossbeam_channel::IntoIter<Section>>,
@@ -701,7 +1191,7 @@ pub(crate) async fn write_vals<
         u32,
         BBIWriteOptions,
         ThreadPool,
-        Values,
+        ObservedValues<Values>,
         String,
         u32,
     ) -> Fut,
@@ -712,6 +1202,7 @@ pub(crate) async fn write_vals<
     process_chrom: G,
     pool: ThreadPool,
     chrom_sizes: HashMap<String, u32>,
+    observer: WriteObserver,
 ) -> Result<
     (
         IdMap,
@@ -723,6 +1214,9 @@ pub(crate) async fn write_vals<
     ),
     ProcessChromError<Values::Error>,
 > {
+    options.validate()?;
+    observer.phase_started(WritePhase::Data);
+
     let zooms_map: BTreeMap<u32, ZoomValue> =
         std::iter::successors(Some(options.initial_zoom_size), |z| Some(z * 4))
             .take(options.max_zooms as usize)
@@ -746,7 +1240,7 @@ pub(crate) async fn write_vals<
 
     let setup_chrom = || {
         let (ftx, sections_handle, buf, section_receiver) =
-            future_channel(options.channel_size, &pool);
+            future_channel(options.channel_size, &pool, observer.clone());
 
         let (zoom_infos, zooms_channels) = {
             let mut zoom_infos = Vec::with_capacity(options.max_zooms as usize);
@@ -757,7 +1251,7 @@ pub(crate) async fn write_vals<
                     .take(options.max_zooms as usize);
             for size in zoom_sizes {
                 let (ftx, handle, buf, section_receiver) =
-                    future_channel(options.channel_size, &pool);
+                    future_channel(options.channel_size, &pool, observer.clone());
                 let zoom_info = TempZoomInfo {
                     resolution: size,
                     data_write_future: Box::new(handle),
@@ -795,21 +1289,22 @@ pub(crate) async fn write_vals<
 
         let (zooms_channels, ftx) = setup_chrom();
 
+        observer.chrom_started(&chrom);
         let fut = process_chrom(
             zooms_channels,
             ftx,
             chrom_id,
             options,
             pool.clone(),
-            data,
-            chrom,
+            ObservedValues::new(data, observer.clone()),
+            chrom.clone(),
             length,
         );
 
         let curr_key = key;
         key += 1;
 
-        output.insert(curr_key, fut);
+        output.insert(curr_key, (chrom, fut));
 
         Ok(ChromProcessingKey(curr_key))
     };
@@ -819,8 +1314,16 @@ pub(crate) async fn write_vals<
     loop {
         match vals_iter.advance(&mut do_read, &mut output)? {
             ChromDataState::NewChrom(read) => {
-                let fut = output.remove(&read.0).unwrap();
-                let chrom_summary = fut.await?;
+                let (chrom, fut) = output.remove(&read.0).unwrap();
+                let chrom_summary = fut.await.map_err(|e| e.in_chrom(&chrom))?;
+                if observer.is_cancelled() {
+                    // Let any chromosomes already being processed stop cleanly
+                    for (_, (_, fut)) in std::mem::take(&mut output) {
+                        let _ = fut.await;
+                    }
+                    return Err(ProcessChromError::Cancelled);
+                }
+                observer.chrom_finished(&chrom);
                 match &mut summary {
                     None => summary = Some(chrom_summary),
                     Some(summary) => {
@@ -885,7 +1388,7 @@ pub(crate) async fn write_vals_no_zoom<
         u32,
         BBIWriteOptions,
         ThreadPool,
-        Values,
+        ObservedValues<Values>,
         String,
         u32,
     ) -> Fut,
@@ -896,6 +1399,7 @@ pub(crate) async fn write_vals_no_zoom<
     process_chrom: G,
     pool: ThreadPool,
     chrom_sizes: HashMap<String, u32>,
+    observer: WriteObserver,
 ) -> Result<
     (
         IdMap,
@@ -907,6 +1411,9 @@ pub(crate) async fn write_vals_no_zoom<
     ),
     ProcessChromError<Values::Error>,
 > {
+    options.validate()?;
+    observer.phase_started(WritePhase::Data);
+
     let total_zoom_counts = std::iter::successors(Some(10), |z: &u64| Some((*z).saturating_mul(4)))
         .take_while(|z| *z < u64::MAX)
         .map(|z| (z, 0));
@@ -923,7 +1430,7 @@ pub(crate) async fn write_vals_no_zoom<
 
     let setup_chrom = || {
         let (ftx, sections_handle, buf, section_receiver) =
-            future_channel(options.channel_size, &pool);
+            future_channel(options.channel_size, &pool, observer.clone());
 
         match send.unbounded_send((section_receiver, buf, sections_handle)) {
             Ok(_) => {}
@@ -950,12 +1457,21 @@ pub(crate) async fn write_vals_no_zoom<
 
         let ftx = setup_chrom();
 
-        let fut = process_chrom(ftx, chrom_id, options, pool.clone(), data, chrom, length);
+        observer.chrom_started(&chrom);
+        let fut = process_chrom(
+            ftx,
+            chrom_id,
+            options,
+            pool.clone(),
+            ObservedValues::new(data, observer.clone()),
+            chrom.clone(),
+            length,
+        );
 
         let curr_key = key;
         key += 1;
 
-        output.insert(curr_key, fut);
+        output.insert(curr_key, (chrom, fut));
 
         Ok(ChromProcessingKey(curr_key))
     };
@@ -965,8 +1481,16 @@ pub(crate) async fn write_vals_no_zoom<
     loop {
         match vals_iter.advance(&mut do_read, &mut output)? {
             ChromDataState::NewChrom(read) => {
-                let fut = output.remove(&read.0).unwrap();
-                let (chrom_summary, zoom_counts) = fut.await?;
+                let (chrom, fut) = output.remove(&read.0).unwrap();
+                let (chrom_summary, zoom_counts) = fut.await.map_err(|e| e.in_chrom(&chrom))?;
+                if observer.is_cancelled() {
+                    // Let any chromosomes already being processed stop cleanly
+                    for (_, (_, fut)) in std::mem::take(&mut output) {
+                        let _ = fut.await;
+                    }
+                    return Err(ProcessChromError::Cancelled);
+                }
+                observer.chrom_finished(&chrom);
 
                 match &mut summary {
                     None => summary = Some(chrom_summary),
@@ -980,9 +1504,9 @@ pub(crate) async fn write_vals_no_zoom<
                     }
                 }
 
-                let zoom_count_map = BTreeMap::from_iter(zoom_counts.into_iter());
+                let zoom_count_map = BTreeMap::from_iter(zoom_counts);
                 for zoom_count in total_zoom_counts.iter_mut() {
-                    let chrom_zoom_count = zoom_count_map.get(&zoom_count.0).copied().unwrap_or(1);
+                    let chrom_zoom_count = zoom_count_map.get(zoom_count.0).copied().unwrap_or(1);
                     *zoom_count.1 += chrom_zoom_count;
                 }
             }
@@ -1023,19 +1547,23 @@ pub(crate) async fn write_zoom_vals<
         u32,
         BBIWriteOptions,
         ThreadPool,
-        Values,
+        ObservedValues<Values>,
     ) -> Fut,
 >(
     mut vals_iter: V,
     options: BBIWriteOptions,
     process_chrom_zoom: G,
-    mut pool: ThreadPool,
+    pool: ThreadPool,
     chrom_ids: &HashMap<String, u32>,
     average_size: u32,
     zoom_counts: BTreeMap<u64, u64>,
     mut file: BufWriter<File>,
     data_size: u64,
+    observer: WriteObserver,
 ) -> Result<(BufWriter<File>, Vec<ZoomHeader>, usize), ProcessChromError<Values::Error>> {
+    observer.phase_started(WritePhase::Zooms);
+    observer.check_cancelled()?;
+
     // Zooms have to be double-buffered: first because chroms could be processed in parallel and second because we don't know the offset of each zoom immediately
     type ZoomValue = (
         Vec<crossbeam_channel::IntoIter<Section>>,
@@ -1060,10 +1588,10 @@ pub(crate) async fn write_zoom_vals<
         .skip_while(|z| z.0 > min_first_zoom_size as u64)
         .skip_while(|z| {
             let mut reduced_size = z.1 * 32;
-            if options.compress {
+            if options.compression != Compression::None {
                 reduced_size /= 2; // Estimate as kent does
             }
-            reduced_size as u64 > data_size / 2
+            reduced_size > data_size / 2
         })
         .take(options.max_zooms as usize)
         .map(|size| {
@@ -1102,7 +1630,7 @@ pub(crate) async fn write_zoom_vals<
 
             for size in resolutions.iter().copied() {
                 let (ftx, handle, buf, section_receiver) =
-                    future_channel(options.channel_size, &mut pool);
+                    future_channel(options.channel_size, &pool, observer.clone());
                 let zoom_info = TempZoomInfo {
                     resolution: size,
                     data_write_future: Box::new(handle),
@@ -1115,15 +1643,21 @@ pub(crate) async fn write_zoom_vals<
             (zoom_infos, zooms_channels)
         };
 
-        let (f_remote, f_handle) =
-            process_chrom_zoom(zooms_channels, chrom_id, options, pool.clone(), data)
-                .remote_handle();
+        observer.chrom_started(&chrom);
+        let (f_remote, f_handle) = process_chrom_zoom(
+            zooms_channels,
+            chrom_id,
+            options,
+            pool.clone(),
+            ObservedValues::new(data, observer.clone()),
+        )
+        .remote_handle();
         pool.spawn_ok(f_remote);
 
         let curr_key = key;
         key += 1;
 
-        output.insert(curr_key, (f_handle, zoom_infos));
+        output.insert(curr_key, (chrom, f_handle, zoom_infos));
 
         Ok(ChromProcessingKey(curr_key))
     };
@@ -1132,7 +1666,7 @@ pub(crate) async fn write_zoom_vals<
         match vals_iter.advance(&mut do_read, &mut output)? {
             ChromDataState::NewChrom(read) => {
                 let read = output.remove(&read.0).unwrap();
-                let (process_future, mut zooms) = read;
+                let (chrom, process_future, mut zooms) = read;
                 // For each zoom, switch the current chromosome to write to the actual zoom file
                 for TempZoomInfo {
                     resolution: size,
@@ -1145,7 +1679,7 @@ pub(crate) async fn write_zoom_vals<
                     data.switch(writer);
                 }
 
-                process_future.await?;
+                process_future.await.map_err(|e| e.in_chrom(&chrom))?;
 
                 for TempZoomInfo {
                     resolution,
@@ -1169,6 +1703,14 @@ pub(crate) async fn write_zoom_vals<
                     // Replace the zoom file again
                     zoom.2.replace(data.await_real_file());
                 }
+                if observer.is_cancelled() {
+                    // Let any chromosomes already being processed stop cleanly
+                    for (_, (_, process_future, _)) in std::mem::take(&mut output) {
+                        let _ = process_future.await;
+                    }
+                    return Err(ProcessChromError::Cancelled);
+                }
+                observer.chrom_finished(&chrom);
             }
             ChromDataState::Finished => break,
             ChromDataState::Error(err) => return Err(ProcessChromError::SourceError(err)),
@@ -1204,7 +1746,8 @@ pub(crate) async fn write_zoom_vals<
         index_offset: first_zoom_index_offset,
     });
 
-    while let Some(mut zoom) = zooms_map_iter.next() {
+    for mut zoom in zooms_map_iter {
+        observer.check_cancelled()?;
         let zoom_data_offset = file.tell()?;
         // First, we can drop the writer - no more data
         drop(zoom.1 .2);
@@ -1224,7 +1767,7 @@ pub(crate) async fn write_zoom_vals<
         let zoom_index_offset = file.tell()?;
         write_rtreeindex(&mut file, nodes, levels, total_sections, options)?;
         zoom_entries.push(ZoomHeader {
-            reduction_level: first_zoom.0,
+            reduction_level: zoom.0,
             data_offset: zoom_data_offset,
             index_offset: zoom_index_offset,
         });
@@ -1237,6 +1780,7 @@ async fn write_data<W: Write, SourceError: Send>(
     mut data_file: W,
     section_sender: crossbeam_channel::Sender<Section>,
     mut frx: futures_mpsc::Receiver<impl Future<Output = io::Result<(SectionData, usize)>> + Send>,
+    observer: WriteObserver,
 ) -> Result<(usize, usize), ProcessChromError<SourceError>> {
     let mut current_offset = 0;
     let mut total = 0;
@@ -1247,6 +1791,7 @@ async fn write_data<W: Write, SourceError: Send>(
         total += 1;
         let size = section.data.len() as u64;
         data_file.write_all(&section.data)?;
+        observer.encoded_bytes_written(size);
         section_sender
             .send(Section {
                 chrom: section.chrom,
@@ -1264,6 +1809,7 @@ async fn write_data<W: Write, SourceError: Send>(
 pub(crate) fn future_channel<Error: Send + 'static, R: Write + Send + 'static>(
     channel_size: usize,
     pool: &ThreadPool,
+    observer: WriteObserver,
 ) -> (
     futures_mpsc::Sender<
         Pin<Box<dyn Future<Output = Result<(SectionData, usize), io::Error>> + Send>>,
@@ -1277,7 +1823,8 @@ pub(crate) fn future_channel<Error: Send + 'static, R: Write + Send + 'static>(
     let file = BufWriter::new(write);
 
     let (section_sender, section_receiver) = unbounded();
-    let (sections_remote, sections_handle) = write_data(file, section_sender, frx).remote_handle();
+    let (sections_remote, sections_handle) =
+        write_data(file, section_sender, frx, observer).remote_handle();
     pool.spawn_ok(sections_remote);
     (ftx, sections_handle, buf, section_receiver)
 }
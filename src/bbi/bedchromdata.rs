@@ -9,16 +9,24 @@
 
 use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufReader, Seek, SeekFrom};
 use std::path::PathBuf;
 
 use crate::bed::bedparser::{
     BedChromData, BedFileStream, BedParser, BedValueError, Parser, StateValue, StreamingBedValues,
 };
+use crate::bed::tabix::TabixIndex;
 use crate::utils::chromvalues::ChromValues;
+use crate::utils::file::gzip::{Compression, GzipReader};
 use crate::utils::streaming_linereader::StreamingLineReader;
 use crate::{ChromData, ChromDataState, ChromProcessingKey, ProcessChromError};
 
+fn unsorted_chroms(prev: &str, chrom: &str) -> BedValueError {
+    BedValueError::InvalidInput(format!(
+        "Input bedGraph not sorted by chromosome ({} found after {}). Sort with `sort -k1,1 -k2,2n`.",
+        chrom, prev
+    ))
+}
+
 pub struct BedParserStreamingIterator<S: StreamingBedValues> {
     bed_data: BedParser<S>,
     allow_out_of_order_chroms: bool,
@@ -59,7 +67,7 @@ impl<S: StreamingBedValues> ChromData for BedParserStreamingIterator<S> {
                 if let Some(c) = last {
                     // TODO: test this correctly fails
                     if !self.allow_out_of_order_chroms && c >= chrom {
-                        return Ok(ChromDataState::Error(BedValueError::InvalidInput("Input bedGraph not sorted by chromosome. Sort with `sort -k1,1 -k2,2n`.".to_string())));
+                        return Ok(ChromDataState::Error(unsorted_chroms(&c, &chrom)));
                     }
                 }
 
@@ -72,6 +80,10 @@ impl<S: StreamingBedValues> ChromData for BedParserStreamingIterator<S> {
     }
 }
 
+/// Reads the chromosomes of a bed-like file in parallel, each from its offset
+/// in `chrom_indices` (such as from `index_chroms`). Since reading starts in
+/// the middle of the file, the line numbers of parse errors count from the
+/// first line of the chromosome (see `BedValueError::InvalidLine`).
 pub struct BedParserParallelStreamingIterator<V, E, ChromError> {
     allow_out_of_order_chroms: bool,
     last_chrom: Option<String>,
@@ -105,18 +117,35 @@ impl<V, E, ChromError> BedParserParallelStreamingIterator<V, E, ChromError> {
             queued_reads: VecDeque::new(),
         }
     }
+
+    /// Creates an iterator over a BGZF compressed file that has a tabix or CSI
+    /// index, which gives the start of each chromosome without
+    /// `index_chroms` having to read the file.
+    pub fn from_tabix(
+        index: &TabixIndex,
+        allow_out_of_order_chroms: bool,
+        path: PathBuf,
+        parse_fn: Parser<V>,
+    ) -> Self {
+        BedParserParallelStreamingIterator::new(
+            index.chrom_offsets(),
+            allow_out_of_order_chroms,
+            path,
+            parse_fn,
+        )
+    }
 }
 
 impl<V> ChromData
     for BedParserParallelStreamingIterator<V, ProcessChromError<BedValueError>, BedValueError>
 {
-    type Values = BedChromData<BedFileStream<V, BufReader<File>>>;
+    type Values = BedChromData<BedFileStream<V, GzipReader<File>>>;
 
     fn advance<
         State,
         F: FnMut(
             String,
-            BedChromData<BedFileStream<V, BufReader<File>>>,
+            BedChromData<BedFileStream<V, GzipReader<File>>>,
             &mut State,
         ) -> Result<ChromProcessingKey, ProcessChromError<BedValueError>>,
     >(
@@ -133,15 +162,18 @@ impl<V> ChromData
                 }
             };
 
-            let mut file = match File::open(&_self.path) {
+            let file = match File::open(&_self.path) {
                 Ok(f) => f,
                 Err(err) => return Ok(ChromDataState::Error(err.into())),
             };
-            file.seek(SeekFrom::Start(curr.0))?;
-            let mut parser = BedParser::new(BedFileStream {
-                bed: StreamingLineReader::new(BufReader::new(file)),
-                parse: _self.parse_fn,
-            });
+            let mut file = GzipReader::new(file)?;
+            file.seek_to(curr.0)?;
+            // For BGZF files, the offset is a virtual offset
+            let bed = match file.compression() {
+                Compression::None => StreamingLineReader::for_chrom(file, curr.1, curr.0),
+                _ => StreamingLineReader::for_bgzf_chrom(file, curr.1, curr.0),
+            };
+            let mut parser = BedParser::new(BedFileStream::new(bed, _self.parse_fn));
 
             Ok(match parser.next_chrom() {
                 Some(Ok((chrom, group))) => {
@@ -149,7 +181,7 @@ impl<V> ChromData
                     if let Some(c) = last {
                         // TODO: test this correctly fails
                         if !_self.allow_out_of_order_chroms && c >= chrom {
-                            return Ok(ChromDataState::Error(BedValueError::InvalidInput("Input bedGraph not sorted by chromosome. Sort with `sort -k1,1 -k2,2n`.".to_string())));
+                            return Ok(ChromDataState::Error(unsorted_chroms(&c, &chrom)));
                         }
                     }
 
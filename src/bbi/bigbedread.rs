@@ -1,16 +1,19 @@
 use std::borrow::BorrowMut;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::Arc;
 use std::vec::Vec;
 
 use byteordered::ByteOrdered;
 use thiserror::Error;
 
-use crate::bbi::{BBIFile, BedEntry, ZoomRecord};
+use crate::bbi::{BBIFile, BedEntry, Summary, ZoomRecord};
 use crate::bbiread::{
     get_block_data, read_info, BBIFileInfo, BBIFileReadInfoError, BBIRead, BBIReadError, Block,
     ChromInfo, ZoomIntervalIter,
 };
+use crate::bed::bedrecord::BedRecord;
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::reopen::{Reopen, ReopenableFile, SeekableRead};
 use crate::{BBIReadInternal, ZoomIntervalError};
 
@@ -73,6 +76,63 @@ where
     }
 }
 
+// Iterates the entries of multiple regions of a chromosome, reusing one
+// `BigBedRead` for all of them
+struct RegionsIter<R: SeekableRead> {
+    bigbed: Option<BigBedRead<R>>,
+    chrom_name: String,
+    chrom: u32,
+    regions: std::vec::IntoIter<(u32, u32)>,
+    current: Option<IntervalIter<std::vec::IntoIter<Block>, R, BigBedRead<R>>>,
+    // The end of the previous region
+    prev_end: u32,
+}
+
+impl<R: SeekableRead> Iterator for RegionsIter<R> {
+    type Item = Result<BedEntry, BBIReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                let (start, end) = (current.start, current.end);
+                match current.next() {
+                    Some(Ok(e)) => {
+                        let overlaps = e.start < end
+                            && (e.end > start || (e.start == e.end && e.start >= start));
+                        // Entries starting before the end of the previous
+                        // region overlap it, so were already returned
+                        if overlaps && e.start >= self.prev_end {
+                            return Some(Ok(e));
+                        }
+                        continue;
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {
+                        self.prev_end = end;
+                        self.bigbed = self.current.take().map(|c| c.bigbed);
+                    }
+                }
+            }
+            let (start, end) = self.regions.next()?;
+            let mut bigbed = self.bigbed.take()?;
+            let blocks = match bigbed.get_overlapping_blocks(&self.chrom_name, start, end) {
+                Ok(blocks) => blocks,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.current = Some(IntervalIter {
+                r: std::marker::PhantomData,
+                bigbed,
+                known_offset: 0,
+                blocks: blocks.into_iter(),
+                vals: None,
+                expected_chrom: self.chrom,
+                start,
+                end,
+            });
+        }
+    }
+}
+
 /// Possible errors encountered when opening a bigBed file to read
 #[derive(Error, Debug)]
 pub enum BigBedReadOpenError {
@@ -161,6 +221,12 @@ where
         Ok(BigBedRead { info, read })
     }
 
+    /// Sets alternate chromosome names that can be used when querying (for
+    /// example, `1` for `chr1`). Names in the file always take precedence.
+    pub fn set_chrom_aliases(&mut self, aliases: ChromAliases) {
+        self.info.chrom_aliases = Some(Arc::new(aliases));
+    }
+
     /// Reads the autosql from this bigBed
     pub fn autosql(&mut self) -> Result<String, BBIReadError> {
         let auto_sql_offset = self.info.header.auto_sql_offset;
@@ -175,6 +241,38 @@ where
         Ok(autosql)
     }
 
+    /// Gets the total summary of this bigBed. The summary is calculated over the
+    /// per-base coverage of the entries.
+    pub fn get_summary(&mut self) -> io::Result<Summary> {
+        let endianness = self.info.header.endianness;
+        let summary_offset = self.info.header.total_summary_offset;
+        let data_offset = self.info.header.full_data_offset;
+        let reader = self.reader();
+        let mut reader = ByteOrdered::runtime(reader, endianness);
+        let (bases_covered, min_val, max_val, sum, sum_squares) = if summary_offset != 0 {
+            reader.seek(SeekFrom::Start(summary_offset))?;
+            (
+                reader.read_u64()?,
+                reader.read_f64()?,
+                reader.read_f64()?,
+                reader.read_f64()?,
+                reader.read_f64()?,
+            )
+        } else {
+            (0, 0.0, 0.0, 0.0, 0.0)
+        };
+        reader.seek(SeekFrom::Start(data_offset))?;
+        let total_items = reader.read_u64()?;
+        Ok(Summary {
+            total_items,
+            bases_covered,
+            min_val,
+            max_val,
+            sum,
+            sum_squares,
+        })
+    }
+
     /// For a given chromosome, start, and end, returns an `Iterator` of the
     /// intersecting `BedEntry`s. The resulting iterator takes a mutable reference
     /// of this `BigBedRead`.
@@ -186,13 +284,7 @@ where
     ) -> Result<impl Iterator<Item = Result<BedEntry, BBIReadError>> + 'a, BBIReadError> {
         let blocks = self.get_overlapping_blocks(chrom_name, start, end)?;
         // TODO: this is only for asserting that the chrom is what we expect
-        let chrom_ix = self
-            .get_info()
-            .chrom_info
-            .iter()
-            .find(|&x| x.name == chrom_name)
-            .unwrap()
-            .id;
+        let chrom_ix = self.info.chrom_id(chrom_name)?;
         Ok(IntervalIter {
             r: std::marker::PhantomData,
             bigbed: self,
@@ -205,6 +297,20 @@ where
         })
     }
 
+    /// Like `get_interval`, but with each entry parsed as a `BedRecord`. The
+    /// number of standard bed fields is taken from the header, and any fields
+    /// after those are in `BedRecord::extra`.
+    pub fn get_interval_records<'a>(
+        &'a mut self,
+        chrom_name: &str,
+        start: u32,
+        end: u32,
+    ) -> Result<impl Iterator<Item = Result<BedRecord, BBIReadError>> + 'a, BBIReadError> {
+        let bed_fields = self.info.header.defined_field_count as usize;
+        let entries = self.get_interval(chrom_name, start, end)?;
+        Ok(entries.map(move |entry| Ok(BedRecord::from_entry(&entry?, bed_fields)?)))
+    }
+
     /// For a given chromosome, start, and end, returns an `Iterator` of the
     /// intersecting `BedEntry`s. The resulting iterator takes this `BigBedRead`
     /// by value.
@@ -216,13 +322,7 @@ where
     ) -> Result<impl Iterator<Item = Result<BedEntry, BBIReadError>>, BBIReadError> {
         let blocks = self.get_overlapping_blocks(chrom_name, start, end)?;
         // TODO: this is only for asserting that the chrom is what we expect
-        let chrom_ix = self
-            .get_info()
-            .chrom_info
-            .iter()
-            .find(|&x| x.name == chrom_name)
-            .unwrap()
-            .id;
+        let chrom_ix = self.info.chrom_id(chrom_name)?;
         Ok(IntervalIter {
             r: std::marker::PhantomData,
             bigbed: self,
@@ -235,6 +335,27 @@ where
         })
     }
 
+    /// Like `get_interval_move`, but for multiple sorted, non-overlapping
+    /// regions of a chromosome, using this `BigBedRead` for all of them. Only
+    /// `BedEntry`s that overlap a region (rather than just touching it) are
+    /// returned, and each is returned once, even if it overlaps multiple
+    /// regions.
+    pub fn get_intervals_move(
+        self,
+        chrom_name: &str,
+        regions: Vec<(u32, u32)>,
+    ) -> Result<impl Iterator<Item = Result<BedEntry, BBIReadError>>, BBIReadError> {
+        let chrom = self.info.chrom_id(chrom_name)?;
+        Ok(RegionsIter {
+            bigbed: Some(self),
+            chrom_name: chrom_name.to_string(),
+            chrom,
+            regions: regions.into_iter(),
+            current: None,
+            prev_end: 0,
+        })
+    }
+
     /// For a given chromosome, start, and end, returns an `Iterator` of the
     /// intersecting `ZoomRecord`s.
     pub fn get_zoom_interval<'a>(
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
 
 use futures::executor::{block_on, ThreadPool};
 use futures::future::FutureExt;
@@ -10,6 +11,7 @@ use futures::task::SpawnExt;
 
 use byteorder::{NativeEndian, WriteBytesExt};
 
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::chromvalues::ChromValues;
 use crate::utils::indexlist::IndexList;
 use crate::utils::tell::Tell;
@@ -17,8 +19,9 @@ use crate::{write_info, ChromData, ChromProcessingInputSectionChannel};
 
 use crate::bbi::{BedEntry, Summary, Value, ZoomRecord, BIGBED_MAGIC};
 use crate::bbiwrite::{
-    self, encode_zoom_section, get_rtreeindex, write_blank_headers, write_chrom_tree,
-    write_rtreeindex, write_zooms, BBIWriteOptions, ProcessChromError, SectionData,
+    self, compress_section, encode_zoom_section, get_rtreeindex, write_blank_headers,
+    write_chrom_tree, write_rtreeindex, write_zooms, AliasedChromData, BBIWriteOptions,
+    CancellationToken, ProcessChromError, SectionData, WriteObserver, WritePhase, WriteProgress,
 };
 
 /// The struct used to write a bigBed file
@@ -26,6 +29,13 @@ pub struct BigBedWrite {
     pub path: String,
     pub options: BBIWriteOptions,
     pub autosql: Option<String>,
+    /// Alternate names for chromosomes. Input chromosomes that aren't in the
+    /// chromosome sizes are renamed to an alias that is.
+    pub chrom_aliases: Option<ChromAliases>,
+    /// Notified of the progress of writing
+    pub progress: Option<Arc<dyn WriteProgress>>,
+    /// Can be used to cancel writing
+    pub cancellation: Option<CancellationToken>,
 }
 
 impl BigBedWrite {
@@ -34,6 +44,9 @@ impl BigBedWrite {
             path,
             options: BBIWriteOptions::default(),
             autosql: None,
+            chrom_aliases: None,
+            progress: None,
+            cancellation: None,
         }
     }
 
@@ -46,6 +59,20 @@ impl BigBedWrite {
         vals: V,
         pool: ThreadPool,
     ) -> Result<(), ProcessChromError<Values::Error>> {
+        let path = self.path.clone();
+        WriteObserver::cleanup(&path, self.write_internal(chrom_sizes, vals, pool))
+    }
+
+    fn write_internal<
+        Values: ChromValues<Value = BedEntry> + Send + 'static,
+        V: ChromData<Values = Values>,
+    >(
+        self,
+        chrom_sizes: HashMap<String, u32>,
+        vals: V,
+        pool: ThreadPool,
+    ) -> Result<(), ProcessChromError<Values::Error>> {
+        let observer = WriteObserver::new(self.progress.clone(), self.cancellation.clone());
         let fp = File::create(self.path.clone())?;
         let mut file = BufWriter::new(fp);
 
@@ -56,6 +83,8 @@ impl BigBedWrite {
             .autosql
             .clone()
             .unwrap_or_else(|| crate::bed::autosql::BED3.to_string());
+        let (field_count, defined_field_count) =
+            crate::bed::autosql::bed_field_counts(&autosql).unwrap_or((3, 3));
         let autosql = CString::new(autosql.into_bytes()).map_err(|_| {
             ProcessChromError::InvalidInput("Invalid autosql: null byte in string".to_owned())
         })?;
@@ -79,12 +108,19 @@ impl BigBedWrite {
         // Write data to file and return
         let (chrom_ids, summary, mut file, raw_sections_iter, zoom_infos, uncompress_buf_size) =
             block_on(bbiwrite::write_vals(
-                vals,
+                AliasedChromData::new(
+                    vals,
+                    self.chrom_aliases.as_ref(),
+                    &chrom_sizes,
+                    self.options,
+                    observer.clone(),
+                ),
                 file,
                 self.options,
                 BigBedWrite::process_chrom,
                 pool,
                 chrom_sizes.clone(),
+                observer.clone(),
             ))?;
         let data_size = file.tell()? - pre_data;
         let mut current_offset = pre_data;
@@ -108,14 +144,19 @@ impl BigBedWrite {
         // Since the chrom tree is read before the index, we put this before the full data index
         // Therefore, there is a higher likelihood that the udc file will only need one read for
         // chrom tree + full data index.
+        observer.phase_started(WritePhase::Index);
+        observer.check_cancelled()?;
         let chrom_index_start = file.tell()?;
-        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids.get_map())?;
+        write_chrom_tree::<NativeEndian, _>(&mut file, chrom_sizes, &chrom_ids.get_map())?;
 
         let index_start = file.tell()?;
         let (nodes, levels, total_sections) = get_rtreeindex(sections_iter, self.options);
         write_rtreeindex(&mut file, nodes, levels, total_sections, self.options)?;
 
+        observer.phase_started(WritePhase::Zooms);
+        observer.check_cancelled()?;
         let zoom_entries = write_zooms(&mut file, zoom_infos, data_size, self.options)?;
+        observer.check_cancelled()?;
         let num_zooms = zoom_entries.len() as u16;
 
         write_info(
@@ -125,9 +166,8 @@ impl BigBedWrite {
             chrom_index_start,
             full_data_offset,
             index_start,
-            // TODO: actually write the correct values for the following
-            3,
-            3,
+            field_count,
+            defined_field_count,
             autosql_offset,
             total_summary_offset,
             uncompress_buf_size,
@@ -152,16 +192,19 @@ impl BigBedWrite {
     where
         I: ChromValues<Value = BedEntry> + Send,
     {
-        // While we do technically lose precision here by using the f32 in Value, we can reuse the same merge_into method
         struct ZoomItem {
             size: u32,
-            live_info: Option<(ZoomRecord, u64)>,
-            overlap: IndexList<Value>,
+            live_info: Option<ZoomRecord>,
             records: Vec<ZoomRecord>,
             channel: ChromProcessingInputSectionChannel,
         }
         struct EntriesSection {
             items: Vec<BedEntry>,
+            // The per-base depth of the entries seen so far that extend past the
+            // start of the current entry. Like kent tools, both the total summary
+            // and the zooms are calculated over this coverage (not the entries).
+            // While we do technically lose precision here by using the f32 in Value,
+            // depths are integers and exactly representable up to 2^24.
             overlap: IndexList<Value>,
             zoom_items: Vec<ZoomItem>,
         }
@@ -176,7 +219,6 @@ impl BigBedWrite {
                 .map(|(size, channel)| ZoomItem {
                     size,
                     live_info: None,
-                    overlap: IndexList::new(),
                     records: Vec::with_capacity(options.items_per_slot as usize),
                     channel,
                 })
@@ -215,220 +257,69 @@ impl BigBedWrite {
                 }
             }
 
-            let add_interval_to_summary =
-                move |overlap: &mut IndexList<Value>,
-                      summary: &mut Option<Summary>,
-                      item_start: u32,
-                      item_end: u32,
-                      next_start_opt: Option<u32>| {
-                    // If any overlaps exists, it must be starting at the current start (else it would have to be after the current entry)
-                    // If the overlap starts before, the entry wasn't correctly cut last iteration
-                    debug_assert!(overlap
-                        .head()
-                        .map(|f| f.start == item_start)
-                        .unwrap_or(true));
-
-                    // For each item in `overlap` that overlaps the current
-                    // item, add `1` to the value.
-                    let mut index = overlap.head_index();
-                    while let Some(i) = index {
-                        match overlap.get_mut(i) {
-                            None => break,
-                            Some(o) => {
-                                o.value += 1.0;
-                                if item_end < o.end {
-                                    let value = o.value - 1.0;
-                                    let end = o.end;
-                                    o.end = item_end;
-                                    overlap.insert_after(
-                                        i,
-                                        Value {
-                                            start: item_end,
-                                            end,
-                                            value,
-                                        },
-                                    );
-                                    break;
-                                }
-                                index = overlap.next_index(i);
-                            }
-                        }
+            add_to_coverage(&mut state_val.overlap, current_val.start, current_val.end);
+
+            let next_start = group
+                .peek()
+                .and_then(|v| v.ok())
+                .map(|v| v.start)
+                .unwrap_or(u32::MAX);
+            let completed = take_completed_coverage(&mut state_val.overlap, next_start);
+
+            for covered in completed.iter() {
+                let len = covered.end - covered.start;
+                let val = f64::from(covered.value);
+                match &mut summary {
+                    None => {
+                        summary = Some(Summary {
+                            total_items: 0,
+                            bases_covered: u64::from(len),
+                            min_val: val,
+                            max_val: val,
+                            sum: f64::from(len) * val,
+                            sum_squares: f64::from(len) * val * val,
+                        })
                     }
-
-                    debug_assert!(overlap.tail().map(|o| o.end >= item_start).unwrap_or(true));
-
-                    if overlap.tail().map(|o| o.end).unwrap_or(item_start) == item_start {
-                        overlap.push_back(Value {
-                            start: item_start,
-                            end: item_end,
-                            value: 1.0,
-                        });
-                    }
-
-                    let next_start = next_start_opt.unwrap_or(u32::max_value());
-
-                    while overlap
-                        .head()
-                        .map(|f| f.start < next_start)
-                        .unwrap_or(false)
-                    {
-                        let mut removed = overlap.pop_front().unwrap();
-                        let (len, val) = if removed.end <= next_start {
-                            (removed.end - removed.start, f64::from(removed.value))
-                        } else {
-                            let len = next_start - removed.start;
-                            let val = f64::from(removed.value);
-                            removed.start = next_start;
-                            overlap.push_front(removed);
-                            (len, val)
-                        };
-
-                        match summary {
-                            None => {
-                                *summary = Some(Summary {
-                                    total_items: 0,
-                                    bases_covered: u64::from(len),
-                                    min_val: val,
-                                    max_val: val,
-                                    sum: f64::from(len) * val,
-                                    sum_squares: f64::from(len) * val * val,
-                                })
-                            }
-                            Some(summary) => {
-                                summary.bases_covered += u64::from(len);
-                                summary.min_val = summary.min_val.min(val);
-                                summary.max_val = summary.max_val.max(val);
-                                summary.sum += f64::from(len) * val;
-                                summary.sum_squares += f64::from(len) * val * val;
-                            }
-                        }
+                    Some(summary) => {
+                        summary.bases_covered += u64::from(len);
+                        summary.min_val = summary.min_val.min(val);
+                        summary.max_val = summary.max_val.max(val);
+                        summary.sum += f64::from(len) * val;
+                        summary.sum_squares += f64::from(len) * val * val;
                     }
-                };
-
-            add_interval_to_summary(
-                &mut state_val.overlap,
-                &mut summary,
-                current_val.start,
-                current_val.end,
-                group.peek().and_then(|v| v.ok()).map(|v| v.start),
-            );
+                }
+            }
 
+            let last_item = group.peek().is_none();
             for zoom_item in state_val.zoom_items.iter_mut() {
                 debug_assert_ne!(zoom_item.records.len(), options.items_per_slot as usize);
 
-                let item_start = current_val.start;
-                let item_end = current_val.end;
-                let overlap = &mut zoom_item.overlap;
-
-                // For each item in `overlap` that overlaps the current
-                // item, add `1` to the value.
-                let mut index = overlap.head_index();
-                while let Some(i) = index {
-                    match overlap.get_mut(i) {
-                        None => break,
-                        Some(o) => {
-                            o.value += 1.0;
-                            if item_end < o.end {
-                                let value = o.value - 1.0;
-                                let end = o.end;
-                                o.end = item_end;
-                                overlap.insert_after(
-                                    i,
-                                    Value {
-                                        start: item_end,
-                                        end,
-                                        value,
-                                    },
-                                );
-                                break;
-                            }
-                            index = overlap.next_index(i);
-                        }
-                    }
-                }
-
-                debug_assert!(overlap.tail().map(|o| o.end >= item_start).unwrap_or(true));
-
-                if overlap.tail().map(|o| o.end).unwrap_or(item_start) == item_start {
-                    overlap.push_back(Value {
-                        start: item_start,
-                        end: item_end,
-                        value: 1.0,
-                    });
-                }
-
-                let next_start = group
-                    .peek()
-                    .and_then(|v| v.ok())
-                    .map(|v| v.start)
-                    .unwrap_or(u32::max_value());
-
-                while overlap
-                    .head()
-                    .map(|f| f.start < next_start)
-                    .unwrap_or(false)
-                {
-                    let mut removed = overlap.pop_front().unwrap();
-                    let val = f64::from(removed.value);
-                    let (removed_start, removed_end) = if removed.end <= next_start {
-                        (removed.start, removed.end)
-                    } else {
-                        let start = removed.start;
-                        removed.start = next_start;
-                        overlap.push_front(removed);
-                        (start, next_start)
-                    };
-
-                    let mut add_start = removed_start;
-                    loop {
-                        if add_start >= removed_end {
-                            if group.peek().is_none() {
-                                if let Some((mut zoom2, total_items)) = zoom_item.live_info.take() {
-                                    zoom2.summary.total_items = total_items;
-                                    zoom_item.records.push(zoom2);
-                                }
-                                if !zoom_item.records.is_empty() {
-                                    let items = std::mem::take(&mut zoom_item.records);
-                                    let handle = pool
-                                        .spawn_with_handle(encode_zoom_section(
-                                            options.compress,
-                                            items,
-                                        ))
-                                        .expect("Couldn't spawn.");
-                                    zoom_item
-                                        .channel
-                                        .send(handle.boxed())
-                                        .await
-                                        .expect("Couln't send");
-                                }
-                            }
-                            break;
-                        }
-                        let (zoom2, _) = zoom_item.live_info.get_or_insert((
-                            ZoomRecord {
-                                chrom: chrom_id,
-                                start: add_start,
-                                end: add_start,
-                                summary: Summary {
-                                    total_items: 0,
-                                    bases_covered: 0,
-                                    min_val: 1.0,
-                                    max_val: 1.0,
-                                    sum: 0.0,
-                                    sum_squares: 0.0,
-                                },
+                for covered in completed.iter() {
+                    let val = f64::from(covered.value);
+                    let mut add_start = covered.start;
+                    while add_start < covered.end {
+                        let zoom2 = zoom_item.live_info.get_or_insert(ZoomRecord {
+                            chrom: chrom_id,
+                            start: add_start,
+                            end: add_start,
+                            summary: Summary {
+                                total_items: 0,
+                                bases_covered: 0,
+                                min_val: val,
+                                max_val: val,
+                                sum: 0.0,
+                                sum_squares: 0.0,
                             },
-                            0,
-                        ));
+                        });
                         // The end of zoom record
                         let next_end = zoom2.start + zoom_item.size;
                         // End of bases that we could add
-                        let add_end = std::cmp::min(next_end, removed_end);
+                        let add_end = std::cmp::min(next_end, covered.end);
                         // If the last zoom ends before this value starts, we don't add anything
-                        if add_end >= add_start {
+                        if add_end > add_start {
                             let added_bases = add_end - add_start;
                             zoom2.end = add_end;
-                            zoom2.summary.total_items += 1; // XXX
+                            zoom2.summary.total_items += 1;
                             zoom2.summary.bases_covered += u64::from(added_bases);
                             zoom2.summary.min_val = zoom2.summary.min_val.min(val);
                             zoom2.summary.max_val = zoom2.summary.max_val.max(val);
@@ -438,24 +329,15 @@ impl BigBedWrite {
                         // If we made it to the end of the zoom (whether it was because the zoom ended before this value started,
                         // or we added to the end of the zoom), then write this zooms to the current section
                         if add_end == next_end {
-                            zoom_item.records.push(
-                                zoom_item
-                                    .live_info
-                                    .take()
-                                    .map(|(mut zoom_item, total_items)| {
-                                        zoom_item.summary.total_items = total_items;
-                                        zoom_item
-                                    })
-                                    .unwrap(),
-                            );
+                            zoom_item.records.push(zoom_item.live_info.take().unwrap());
                         }
                         // Set where we would start for next time
-                        add_start = std::cmp::max(add_end, removed_start);
+                        add_start = std::cmp::max(add_end, add_start);
                         // Write section if full
                         if zoom_item.records.len() == options.items_per_slot as usize {
                             let items = std::mem::take(&mut zoom_item.records);
                             let handle = pool
-                                .spawn_with_handle(encode_zoom_section(options.compress, items))
+                                .spawn_with_handle(encode_zoom_section(options, items))
                                 .expect("Couldn't spawn.");
                             zoom_item
                                 .channel
@@ -466,23 +348,43 @@ impl BigBedWrite {
                     }
                 }
 
+                // On the last item of the chrom, all coverage has been taken
+                // above, so write out whatever is left.
+                if last_item {
+                    if let Some(zoom2) = zoom_item.live_info.take() {
+                        zoom_item.records.push(zoom2);
+                    }
+                    if !zoom_item.records.is_empty() {
+                        let items = std::mem::take(&mut zoom_item.records);
+                        let handle = pool
+                            .spawn_with_handle(encode_zoom_section(options, items))
+                            .expect("Couldn't spawn.");
+                        zoom_item
+                            .channel
+                            .send(handle.boxed())
+                            .await
+                            .expect("Couln't send");
+                    }
+                }
+
                 debug_assert_ne!(zoom_item.records.len(), options.items_per_slot as usize);
             }
 
             state_val.items.push(current_val);
-            if group.peek().is_none() || state_val.items.len() >= options.items_per_slot as usize {
+            if last_item || state_val.items.len() >= options.items_per_slot as usize {
                 let items = std::mem::replace(
                     &mut state_val.items,
                     Vec::with_capacity(options.items_per_slot as usize),
                 );
                 let handle = pool
-                    .spawn_with_handle(encode_section(options.compress, items, chrom_id))
+                    .spawn_with_handle(encode_section(options, items, chrom_id))
                     .expect("Couldn't spawn.");
                 ftx.send(handle.boxed()).await.expect("Couldn't send");
             }
         }
 
         debug_assert!(state_val.items.is_empty());
+        debug_assert!(state_val.overlap.head().is_none());
         for zoom_item in state_val.zoom_items.iter_mut() {
             debug_assert!(zoom_item.live_info.is_none());
             debug_assert!(zoom_item.records.is_empty());
@@ -504,13 +406,89 @@ impl BigBedWrite {
     }
 }
 
+/// Adds an entry spanning `item_start..item_end` to the coverage in `overlap`,
+/// incrementing the depth of any overlapping regions by `1`.
+fn add_to_coverage(overlap: &mut IndexList<Value>, item_start: u32, item_end: u32) {
+    // Zero-length entries don't cover any bases
+    if item_start == item_end {
+        return;
+    }
+    // If any overlaps exists, it must be starting at the current start (else it would have to be after the current entry)
+    // If the overlap starts before, the entry wasn't correctly cut last iteration
+    debug_assert!(overlap
+        .head()
+        .map(|f| f.start == item_start)
+        .unwrap_or(true));
+
+    // For each item in `overlap` that overlaps the current
+    // item, add `1` to the value.
+    let mut index = overlap.head_index();
+    while let Some(i) = index {
+        match overlap.get_mut(i) {
+            None => break,
+            Some(o) => {
+                o.value += 1.0;
+                if item_end < o.end {
+                    let value = o.value - 1.0;
+                    let end = o.end;
+                    o.end = item_end;
+                    overlap.insert_after(
+                        i,
+                        Value {
+                            start: item_end,
+                            end,
+                            value,
+                        },
+                    );
+                    break;
+                }
+                index = overlap.next_index(i);
+            }
+        }
+    }
+
+    // Any part of the entry past the existing coverage has a depth of `1`
+    let covered_end = overlap.tail().map(|o| o.end).unwrap_or(item_start);
+    debug_assert!(covered_end >= item_start);
+    if item_end > covered_end {
+        overlap.push_back(Value {
+            start: covered_end,
+            end: item_end,
+            value: 1.0,
+        });
+    }
+}
+
+/// Removes and returns the coverage in `overlap` before `next_start`, which
+/// can no longer change.
+fn take_completed_coverage(overlap: &mut IndexList<Value>, next_start: u32) -> Vec<Value> {
+    let mut completed = vec![];
+    while overlap
+        .head()
+        .map(|f| f.start < next_start)
+        .unwrap_or(false)
+    {
+        let mut removed = overlap.pop_front().unwrap();
+        if removed.end <= next_start {
+            completed.push(removed);
+        } else {
+            completed.push(Value {
+                start: removed.start,
+                end: next_start,
+                value: removed.value,
+            });
+            removed.start = next_start;
+            overlap.push_front(removed);
+        }
+    }
+    completed
+}
+
 async fn encode_section(
-    compress: bool,
+    options: BBIWriteOptions,
     items_in_section: Vec<BedEntry>,
     chrom_id: u32,
 ) -> io::Result<(SectionData, usize)> {
-    use libdeflater::{CompressionLvl, Compressor};
-
     let mut bytes = Vec::with_capacity(items_in_section.len() * 30);
 
     let start = items_in_section[0].start;
@@ -527,18 +505,7 @@ async fn encode_section(
         bytes.write_all(&[b'\0'])?;
     }
 
-    let (out_bytes, uncompress_buf_size) = if compress {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let max_sz = compressor.zlib_compress_bound(bytes.len());
-        let mut compressed_data = vec![0; max_sz];
-        let actual_sz = compressor
-            .zlib_compress(&bytes, &mut compressed_data)
-            .unwrap();
-        compressed_data.resize(actual_sz, 0);
-        (compressed_data, bytes.len())
-    } else {
-        (bytes, 0)
-    };
+    let (out_bytes, uncompress_buf_size) = compress_section(bytes, options)?;
 
     Ok((
         SectionData {
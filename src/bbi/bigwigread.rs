@@ -42,6 +42,7 @@ assert_eq!(first_interval.value, 0.06792);
 use std::borrow::BorrowMut;
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
 use std::vec::Vec;
 
 use byteordered::{ByteOrdered, Endianness};
@@ -52,6 +53,7 @@ use crate::bbiread::{
     get_block_data, read_info, BBIFileInfo, BBIFileReadInfoError, BBIRead, BBIReadError, Block,
     ChromInfo, ZoomIntervalIter,
 };
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::reopen::{Reopen, ReopenableFile, SeekableRead};
 use crate::{BBIReadInternal, ZoomIntervalError};
 
@@ -115,6 +117,49 @@ where
     }
 }
 
+// Iterates the values of multiple regions of a chromosome, reusing one
+// `BigWigRead` for all of them
+struct RegionsIter<R: SeekableRead> {
+    bigwig: Option<BigWigRead<R>>,
+    chrom_name: String,
+    chrom: u32,
+    regions: std::vec::IntoIter<(u32, u32)>,
+    current: Option<IntervalIter<std::vec::IntoIter<Block>, R, BigWigRead<R>>>,
+}
+
+impl<R: SeekableRead> Iterator for RegionsIter<R> {
+    type Item = Result<Value, BBIReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                match current.next() {
+                    // Values are clipped to the region, so those only touching it are empty
+                    Some(Ok(v)) if v.start == v.end => continue,
+                    Some(v) => return Some(v),
+                    None => self.bigwig = self.current.take().map(|c| c.bigwig),
+                }
+            }
+            let (start, end) = self.regions.next()?;
+            let mut bigwig = self.bigwig.take()?;
+            let blocks = match bigwig.get_overlapping_blocks(&self.chrom_name, start, end) {
+                Ok(blocks) => blocks,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.current = Some(IntervalIter {
+                r: std::marker::PhantomData,
+                bigwig,
+                known_offset: 0,
+                blocks: blocks.into_iter(),
+                vals: None,
+                chrom: self.chrom,
+                start,
+                end,
+            });
+        }
+    }
+}
+
 /// Possible errors encountered when opening a bigWig file to read
 #[derive(Debug, Error)]
 pub enum BigWigReadOpenError {
@@ -213,6 +258,12 @@ where
         &self.read
     }
 
+    /// Sets alternate chromosome names that can be used when querying (for
+    /// example, `1` for `chr1`). Names in the file always take precedence.
+    pub fn set_chrom_aliases(&mut self, aliases: ChromAliases) {
+        self.info.chrom_aliases = Some(Arc::new(aliases));
+    }
+
     /// Returns the summary data from bigWig
     ///
     /// Note: For version 1 of bigWigs, there is no total summary. In that
@@ -295,6 +346,25 @@ where
         })
     }
 
+    /// Like `get_interval_move`, but for multiple sorted, non-overlapping
+    /// regions of a chromosome, using this `BigWigRead` for all of them. The
+    /// `Value`s are clipped to the regions, and those only touching a region
+    /// are not returned.
+    pub fn get_intervals_move(
+        self,
+        chrom_name: &str,
+        regions: Vec<(u32, u32)>,
+    ) -> Result<impl Iterator<Item = Result<Value, BBIReadError>>, BBIReadError> {
+        let chrom = self.info.chrom_id(chrom_name)?;
+        Ok(RegionsIter {
+            bigwig: Some(self),
+            chrom_name: chrom_name.to_string(),
+            chrom,
+            regions: regions.into_iter(),
+            current: None,
+        })
+    }
+
     /// For a given chromosome, start, and end, returns an `Iterator` of the
     /// intersecting `ZoomRecord`s.
     pub fn get_zoom_interval<'a>(
@@ -43,6 +43,7 @@ out.write(chrom_map, vals, pool)?;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
 
 use futures::executor::{block_on, ThreadPool};
 use futures::future::FutureExt;
@@ -52,14 +53,18 @@ use futures::Future;
 
 use byteorder::{NativeEndian, WriteBytesExt};
 
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::chromvalues::ChromValues;
+use crate::utils::merge::MergeOp;
 use crate::utils::tell::Tell;
 use crate::{write_info, ChromData, ChromProcessingInputSectionChannel, Section};
 
 use crate::bbi::{Summary, Value, ZoomRecord, BIGWIG_MAGIC};
 use crate::bbiwrite::{
-    self, encode_zoom_section, get_rtreeindex, write_blank_headers, write_chrom_tree,
-    write_rtreeindex, write_zooms, BBIWriteOptions, ProcessChromError, SectionData,
+    self, compress_section, encode_zoom_section, get_rtreeindex, write_blank_headers,
+    write_chrom_tree, write_rtreeindex, write_zooms, AliasedChromData, BBIWriteOptions,
+    CancellationToken, ObservedValues, OverlapPolicy, ProcessChromError, SectionData,
+    WriteObserver, WritePhase, WriteProgress,
 };
 
 struct ZoomItem {
@@ -72,10 +77,269 @@ struct ZoomItem {
     channel: ChromProcessingInputSectionChannel,
 }
 
+/// A lossy rounding applied to values before they are written
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Quantization {
+    /// Round values to the given number of significant (decimal) digits,
+    /// from `1` to `9` (the most an `f32` can distinguish)
+    SignificantDigits(u8),
+    /// Round values to the nearest multiple of the given step
+    Step(f32),
+}
+
+impl Quantization {
+    /// Returns the quantized form of `value`
+    pub fn quantize(&self, value: f32) -> f32 {
+        if !value.is_finite() {
+            return value;
+        }
+        let value = f64::from(value);
+        let quantized = match *self {
+            Quantization::SignificantDigits(digits) => {
+                if value == 0.0 {
+                    return 0.0;
+                }
+                let magnitude = value.abs().log10().floor() as i32;
+                let scale = 10f64.powi(i32::from(digits) - 1 - magnitude);
+                (value * scale).round() / scale
+            }
+            Quantization::Step(step) => {
+                let step = f64::from(step);
+                (value / step).round() * step
+            }
+        };
+        quantized as f32
+    }
+
+    fn validate<E>(&self) -> Result<(), ProcessChromError<E>> {
+        match *self {
+            Quantization::SignificantDigits(0) => Err(ProcessChromError::InvalidInput(
+                "Invalid quantization: must keep at least one significant digit".to_owned(),
+            )),
+            Quantization::SignificantDigits(digits) if digits > 9 => {
+                Err(ProcessChromError::InvalidInput(format!(
+                    "Invalid quantization: an f32 has at most 9 significant digits (got {})",
+                    digits
+                )))
+            }
+            Quantization::Step(step) if !(step.is_finite() && step > 0.0) => {
+                Err(ProcessChromError::InvalidInput(format!(
+                    "Invalid quantization: step must be positive (got {})",
+                    step
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Quantizes the values of a chromosome and merges adjacent values that are
+/// equal after quantization into a single value. Without a quantization, the
+/// values are passed through unchanged.
+struct QuantizedValues<I: ChromValues<Value = Value>> {
+    inner: I,
+    quantization: Option<Quantization>,
+    next: Option<Result<Value, I::Error>>,
+}
+
+impl<I: ChromValues<Value = Value>> QuantizedValues<I> {
+    fn new(inner: I, quantization: Option<Quantization>) -> Self {
+        QuantizedValues {
+            inner,
+            quantization,
+            next: None,
+        }
+    }
+
+    fn load_next(&mut self) {
+        if self.next.is_some() {
+            return;
+        }
+        let quantization = match self.quantization {
+            None => {
+                self.next = self.inner.next();
+                return;
+            }
+            Some(quantization) => quantization,
+        };
+        let mut current = match self.inner.next() {
+            Some(Ok(v)) => v,
+            other => {
+                self.next = other;
+                return;
+            }
+        };
+        current.value = quantization.quantize(current.value);
+        while let Some(Ok(next)) = self.inner.peek() {
+            if next.start != current.end || quantization.quantize(next.value) != current.value {
+                break;
+            }
+            current.end = next.end;
+            self.inner.next();
+        }
+        self.next = Some(Ok(current));
+    }
+}
+
+impl<I: ChromValues<Value = Value>> ChromValues for QuantizedValues<I> {
+    type Value = Value;
+    type Error = I::Error;
+
+    fn next(&mut self) -> Option<Result<Value, I::Error>> {
+        self.load_next();
+        self.next.take()
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &I::Error>> {
+        self.load_next();
+        self.next.as_ref().map(|v| v.as_ref())
+    }
+}
+
+/// Resolves overlaps between the values of a chromosome according to an
+/// `OverlapPolicy`, by splitting the values where they overlap. Values that
+/// don't overlap any others are passed through unchanged, as are all values
+/// with `OverlapPolicy::Error` (overlaps are then reported when processing the
+/// values). Resolved values are output as soon as they are known, so only the
+/// values overlapping the current position are kept.
+struct ResolvedOverlaps<I: ChromValues<Value = Value>> {
+    inner: I,
+    op: Option<MergeOp>,
+    // The values that cover `pos`, in input order
+    active: Vec<Value>,
+    pos: u32,
+    // A resolved value that couldn't be joined with the previous one
+    pending: Option<Value>,
+    next: Option<Result<Value, I::Error>>,
+}
+
+impl<I: ChromValues<Value = Value>> ResolvedOverlaps<I> {
+    fn new(inner: I, policy: OverlapPolicy) -> Self {
+        let op = match policy {
+            OverlapPolicy::Error => None,
+            OverlapPolicy::Sum => Some(MergeOp::Sum),
+            OverlapPolicy::Max => Some(MergeOp::Max),
+            OverlapPolicy::Mean => Some(MergeOp::MeanCovering),
+            OverlapPolicy::Last => Some(MergeOp::Last),
+        };
+        ResolvedOverlaps {
+            inner,
+            op,
+            active: vec![],
+            pos: 0,
+            pending: None,
+            next: None,
+        }
+    }
+
+    /// The next resolved value, up to where the values covering it change
+    fn next_resolved(&mut self, op: &MergeOp) -> Option<Result<Value, I::Error>> {
+        if let Some(pending) = self.pending.take() {
+            return Some(Ok(pending));
+        }
+        if self.active.is_empty() {
+            match self.inner.next()? {
+                Ok(first) => {
+                    self.pos = first.start;
+                    self.active.push(first);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        // Values are sorted by start, so any others that cover `pos` start there
+        while let Some(Ok(next)) = self.inner.peek() {
+            if next.start != self.pos {
+                break;
+            }
+            self.active.extend(self.inner.next().and_then(Result::ok));
+        }
+        let mut end = self.active.iter().map(|v| v.end).min().unwrap();
+        // Unsorted values (starting before `pos`) are reported when processing the values
+        if let Some(Ok(next)) = self.inner.peek() {
+            if next.start > self.pos {
+                end = end.min(next.start);
+            }
+        }
+        let resolved = Value {
+            start: self.pos,
+            end,
+            value: op.combine(self.active.iter().map(|v| v.value)),
+        };
+        self.pos = end;
+        self.active.retain(|v| v.end > end);
+        Some(Ok(resolved))
+    }
+
+    fn load_next(&mut self) {
+        if self.next.is_some() {
+            return;
+        }
+        let op = match self.op.take() {
+            None => {
+                self.next = self.inner.next();
+                return;
+            }
+            Some(op) => op,
+        };
+        let mut current = match self.next_resolved(&op) {
+            Some(Ok(v)) => v,
+            other => {
+                self.next = other;
+                self.op = Some(op);
+                return;
+            }
+        };
+        // Join resolved values that are equal within a group of overlapping values
+        while !self.active.is_empty() {
+            match self.next_resolved(&op) {
+                Some(Ok(next)) if next.value == current.value => current.end = next.end,
+                Some(Ok(next)) => {
+                    self.pending = Some(next);
+                    break;
+                }
+                _ => unreachable!("A value is active"),
+            }
+        }
+        self.next = Some(Ok(current));
+        self.op = Some(op);
+    }
+}
+
+impl<I: ChromValues<Value = Value>> ChromValues for ResolvedOverlaps<I> {
+    type Value = Value;
+    type Error = I::Error;
+
+    fn next(&mut self) -> Option<Result<Value, I::Error>> {
+        self.load_next();
+        self.next.take()
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &I::Error>> {
+        self.load_next();
+        self.next.as_ref().map(|v| v.as_ref())
+    }
+}
+
 /// The struct used to write a bigWig file
+///
+/// For a given input and options, the written file is byte-identical
+/// regardless of the number of threads in the provided `ThreadPool` or of how
+/// chromosomes are scheduled. `write` and `write_singlethreaded` write the same
+/// file, while `write_multipass` may select different zoom levels.
 pub struct BigWigWrite {
     pub path: String,
     pub options: BBIWriteOptions,
+    /// If set, values are quantized before being written, and adjacent values
+    /// that become equal are merged. Zooms and the summary are calculated from
+    /// the quantized values.
+    pub quantization: Option<Quantization>,
+    /// Alternate names for chromosomes. Input chromosomes that aren't in the
+    /// chromosome sizes are renamed to an alias that is.
+    pub chrom_aliases: Option<ChromAliases>,
+    /// Notified of the progress of writing
+    pub progress: Option<Arc<dyn WriteProgress>>,
+    /// Can be used to cancel writing
+    pub cancellation: Option<CancellationToken>,
 }
 
 impl BigWigWrite {
@@ -83,6 +347,10 @@ impl BigWigWrite {
         BigWigWrite {
             path,
             options: BBIWriteOptions::default(),
+            quantization: None,
+            chrom_aliases: None,
+            progress: None,
+            cancellation: None,
         }
     }
 
@@ -129,7 +397,7 @@ impl BigWigWrite {
         // Putting the chrom tree before the data also has a higher likelihood of being included with the beginning headers,
         // but requires us to know all the data ahead of time (when writing)
         let chrom_index_start = file.tell()?;
-        write_chrom_tree(file, chrom_sizes, chrom_ids)?;
+        write_chrom_tree::<NativeEndian, _>(file, chrom_sizes, chrom_ids)?;
 
         let index_start = file.tell()?;
         let (nodes, levels, total_sections) = get_rtreeindex(sections_iter, options);
@@ -148,29 +416,38 @@ impl BigWigWrite {
         vals: V,
         pool: ThreadPool,
     ) -> Result<(), ProcessChromError<Values::Error>> {
-        let process_chrom = |zooms_channels: Vec<(u32, ChromProcessingInputSectionChannel)>,
-                             ftx: ChromProcessingInputSectionChannel,
-                             chrom_id: u32,
-                             options: BBIWriteOptions,
-                             pool: ThreadPool,
-                             chrom_values: Values,
-                             chrom: String,
-                             chrom_length: u32| {
-            let fut = BigWigWrite::process_chrom(
-                zooms_channels,
-                ftx,
-                chrom_id,
-                options,
-                pool.clone(),
-                chrom_values,
-                chrom,
-                chrom_length,
-            );
-            let (fut, handle) = fut.remote_handle();
-            pool.spawn_ok(fut);
-            handle
-        };
-        self.write_internal(chrom_sizes, vals, pool, process_chrom)
+        let quantization = self.quantization;
+        let process_chrom =
+            move |zooms_channels: Vec<(u32, ChromProcessingInputSectionChannel)>,
+                  ftx: ChromProcessingInputSectionChannel,
+                  chrom_id: u32,
+                  options: BBIWriteOptions,
+                  pool: ThreadPool,
+                  chrom_values: ObservedValues<Values>,
+                  chrom: String,
+                  chrom_length: u32| {
+                let fut = BigWigWrite::process_chrom(
+                    zooms_channels,
+                    ftx,
+                    chrom_id,
+                    options,
+                    pool.clone(),
+                    QuantizedValues::new(
+                        ResolvedOverlaps::new(chrom_values, options.overlap_policy),
+                        quantization,
+                    ),
+                    chrom,
+                    chrom_length,
+                );
+                let (fut, handle) = fut.remote_handle();
+                pool.spawn_ok(fut);
+                handle
+            };
+        let path = self.path.clone();
+        WriteObserver::cleanup(
+            &path,
+            self.write_internal(chrom_sizes, vals, pool, process_chrom),
+        )
     }
 
     /// Write the values from `V` as a bigWig. Will utilize the provided threadpool for encoding values, but will read through values on the current thread.
@@ -183,7 +460,35 @@ impl BigWigWrite {
         vals: V,
         pool: ThreadPool,
     ) -> Result<(), ProcessChromError<Values::Error>> {
-        self.write_internal(chrom_sizes, vals, pool, BigWigWrite::process_chrom)
+        let quantization = self.quantization;
+        let process_chrom =
+            move |zooms_channels: Vec<(u32, ChromProcessingInputSectionChannel)>,
+                  ftx: ChromProcessingInputSectionChannel,
+                  chrom_id: u32,
+                  options: BBIWriteOptions,
+                  pool: ThreadPool,
+                  chrom_values: ObservedValues<Values>,
+                  chrom: String,
+                  chrom_length: u32| {
+                BigWigWrite::process_chrom(
+                    zooms_channels,
+                    ftx,
+                    chrom_id,
+                    options,
+                    pool,
+                    QuantizedValues::new(
+                        ResolvedOverlaps::new(chrom_values, options.overlap_policy),
+                        quantization,
+                    ),
+                    chrom,
+                    chrom_length,
+                )
+            };
+        let path = self.path.clone();
+        WriteObserver::cleanup(
+            &path,
+            self.write_internal(chrom_sizes, vals, pool, process_chrom),
+        )
     }
 
     fn write_internal<
@@ -196,7 +501,7 @@ impl BigWigWrite {
             u32,
             BBIWriteOptions,
             ThreadPool,
-            Values,
+            ObservedValues<Values>,
             String,
             u32,
         ) -> Fut,
@@ -207,6 +512,10 @@ impl BigWigWrite {
         pool: ThreadPool,
         process_chrom: G,
     ) -> Result<(), ProcessChromError<Values::Error>> {
+        if let Some(quantization) = self.quantization {
+            quantization.validate()?;
+        }
+        let observer = WriteObserver::new(self.progress.clone(), self.cancellation.clone());
         let fp = File::create(self.path.clone())?;
         let mut file = BufWriter::new(fp);
 
@@ -215,14 +524,23 @@ impl BigWigWrite {
         // Write data to file and return
         let (chrom_ids, summary, mut file, raw_sections_iter, zoom_infos, uncompress_buf_size) =
             block_on(bbiwrite::write_vals(
-                vals,
+                AliasedChromData::new(
+                    vals,
+                    self.chrom_aliases.as_ref(),
+                    &chrom_sizes,
+                    self.options,
+                    observer.clone(),
+                ),
                 file,
                 self.options,
                 process_chrom,
                 pool,
                 chrom_sizes.clone(),
+                observer.clone(),
             ))?;
 
+        observer.phase_started(WritePhase::Index);
+        observer.check_cancelled()?;
         let chrom_ids = chrom_ids.get_map();
         let (data_size, chrom_index_start, index_start, total_sections) = BigWigWrite::write_mid(
             &mut file,
@@ -233,7 +551,10 @@ impl BigWigWrite {
             self.options,
         )?;
 
+        observer.phase_started(WritePhase::Zooms);
+        observer.check_cancelled()?;
         let zoom_entries = write_zooms(&mut file, zoom_infos, data_size, self.options)?;
+        observer.check_cancelled()?;
         let num_zooms = zoom_entries.len() as u16;
 
         write_info(
@@ -268,6 +589,27 @@ impl BigWigWrite {
         chrom_sizes: HashMap<String, u32>,
         pool: ThreadPool,
     ) -> Result<(), ProcessChromError<Values::Error>> {
+        let path = self.path.clone();
+        WriteObserver::cleanup(
+            &path,
+            self.write_multipass_internal(make_vals, chrom_sizes, pool),
+        )
+    }
+
+    fn write_multipass_internal<
+        Values: ChromValues<Value = Value> + Send + 'static,
+        V: ChromData<Values = Values>,
+    >(
+        self,
+        make_vals: impl Fn() -> Result<V, ProcessChromError<Values::Error>>,
+        chrom_sizes: HashMap<String, u32>,
+        pool: ThreadPool,
+    ) -> Result<(), ProcessChromError<Values::Error>> {
+        let quantization = self.quantization;
+        if let Some(quantization) = quantization {
+            quantization.validate()?;
+        }
+        let observer = WriteObserver::new(self.progress.clone(), self.cancellation.clone());
         let fp = File::create(self.path.clone())?;
         let mut file = BufWriter::new(fp);
 
@@ -275,19 +617,22 @@ impl BigWigWrite {
 
         let vals = make_vals()?;
 
-        let process_chrom = |ftx: ChromProcessingInputSectionChannel,
-                             chrom_id: u32,
-                             options: BBIWriteOptions,
-                             pool: ThreadPool,
-                             chrom_values: Values,
-                             chrom: String,
-                             chrom_length: u32| {
+        let process_chrom = move |ftx: ChromProcessingInputSectionChannel,
+                                  chrom_id: u32,
+                                  options: BBIWriteOptions,
+                                  pool: ThreadPool,
+                                  chrom_values: ObservedValues<Values>,
+                                  chrom: String,
+                                  chrom_length: u32| {
             let fut = BigWigWrite::process_chrom_no_zooms(
                 ftx,
                 chrom_id,
                 options,
                 pool.clone(),
-                chrom_values,
+                QuantizedValues::new(
+                    ResolvedOverlaps::new(chrom_values, options.overlap_policy),
+                    quantization,
+                ),
                 chrom,
                 chrom_length,
             );
@@ -299,38 +644,74 @@ impl BigWigWrite {
         // Write data to file and return
         let (chrom_ids, summary, zoom_counts, mut file, raw_sections_iter, mut uncompress_buf_size) =
             block_on(bbiwrite::write_vals_no_zoom(
-                vals,
+                AliasedChromData::new(
+                    vals,
+                    self.chrom_aliases.as_ref(),
+                    &chrom_sizes,
+                    self.options,
+                    observer.clone(),
+                ),
                 file,
                 self.options,
                 process_chrom,
                 pool.clone(),
                 chrom_sizes.clone(),
+                observer.clone(),
             ))?;
 
+        observer.phase_started(WritePhase::Index);
+        observer.check_cancelled()?;
         let chrom_ids = chrom_ids.get_map();
         let (data_size, chrom_index_start, index_start, total_sections) = BigWigWrite::write_mid(
             &mut file,
             pre_data,
             raw_sections_iter,
-            chrom_sizes,
+            chrom_sizes.clone(),
             &chrom_ids,
             self.options,
         )?;
+        observer.check_cancelled()?;
 
         let vals = make_vals()?;
 
+        let process_chrom_zoom =
+            move |zooms_channels: Vec<(u32, ChromProcessingInputSectionChannel)>,
+                  chrom_id: u32,
+                  options: BBIWriteOptions,
+                  pool: ThreadPool,
+                  chrom_values: ObservedValues<Values>| {
+                BigWigWrite::process_chrom_zoom(
+                    zooms_channels,
+                    chrom_id,
+                    options,
+                    pool,
+                    QuantizedValues::new(
+                        ResolvedOverlaps::new(chrom_values, options.overlap_policy),
+                        quantization,
+                    ),
+                )
+            };
         let (mut file, zoom_entries, zoom_uncompress_buf_size) =
             block_on(bbiwrite::write_zoom_vals(
-                vals,
+                // Dropped chromosomes were already reported in the first pass
+                AliasedChromData::new(
+                    vals,
+                    self.chrom_aliases.as_ref(),
+                    &chrom_sizes,
+                    self.options,
+                    WriteObserver::new(None, observer.cancellation.clone()),
+                ),
                 self.options,
-                BigWigWrite::process_chrom_zoom,
+                process_chrom_zoom,
                 pool,
                 &chrom_ids,
                 (summary.bases_covered as f64 / summary.total_items as f64) as u32,
                 zoom_counts,
                 file,
                 data_size,
+                observer.clone(),
             ))?;
+        observer.check_cancelled()?;
         uncompress_buf_size = uncompress_buf_size.max(zoom_uncompress_buf_size);
         let num_zooms = zoom_entries.len() as u16;
 
@@ -412,7 +793,7 @@ impl BigWigWrite {
         if chrom_values.peek().is_none() || items.len() >= options.items_per_slot as usize {
             let items = std::mem::take(items);
             let handle = pool
-                .spawn_with_handle(encode_section(options.compress, items, chrom_id))
+                .spawn_with_handle(encode_section(options, items, chrom_id))
                 .expect("Couldn't spawn.");
             ftx.send(handle.boxed()).await.expect("Couldn't send");
         }
@@ -448,7 +829,7 @@ impl BigWigWrite {
                 {
                     let items = std::mem::take(&mut zoom_item.records);
                     let handle = pool
-                        .spawn_with_handle(encode_zoom_section(options.compress, items))
+                        .spawn_with_handle(encode_zoom_section(options, items))
                         .expect("Couldn't spawn.");
                     zoom_item
                         .channel
@@ -702,12 +1083,10 @@ impl BigWigWrite {
 }
 
 async fn encode_section(
-    compress: bool,
+    options: BBIWriteOptions,
     items_in_section: Vec<Value>,
     chrom_id: u32,
 ) -> io::Result<(SectionData, usize)> {
-    use libdeflater::{CompressionLvl, Compressor};
-
     let mut bytes = Vec::with_capacity(24 + (items_in_section.len() * 24));
 
     let start = items_in_section[0].start;
@@ -727,18 +1106,7 @@ async fn encode_section(
         bytes.write_f32::<NativeEndian>(item.value)?;
     }
 
-    let (out_bytes, uncompress_buf_size) = if compress {
-        let mut compressor = Compressor::new(CompressionLvl::default());
-        let max_sz = compressor.zlib_compress_bound(bytes.len());
-        let mut compressed_data = vec![0; max_sz];
-        let actual_sz = compressor
-            .zlib_compress(&bytes, &mut compressed_data)
-            .unwrap();
-        compressed_data.resize(actual_sz, 0);
-        (compressed_data, bytes.len())
-    } else {
-        (bytes, 0)
-    };
+    let (out_bytes, uncompress_buf_size) = compress_section(bytes, options)?;
 
     Ok((
         SectionData {
@@ -4,4 +4,7 @@ Contains utilities useful for reading and writing bed files and bed-like data.
 
 pub mod autosql;
 pub mod bedparser;
+pub mod bedrecord;
+#[cfg(any(feature = "write", feature = "cli"))]
 pub mod indexer;
+pub mod tabix;
@@ -2,6 +2,12 @@
 Utitilies for reading and writing the autosql section of a bigBed.
 */
 
+use thiserror::Error;
+
+use crate::bbi::BedEntry;
+use crate::bed::bedparser::BedValueError;
+use crate::bed::bedrecord::BedRecord;
+
 pub const BED3: &str = r#"
 table bed3
 "Simple bed"
@@ -12,41 +18,65 @@ table bed3
 )
 "#;
 
-pub fn bed_autosql(rest: &str) -> String {
-    let extra_fields = if rest.is_empty() {
-        0
-    } else {
-        rest.split('\t').count()
-    };
-    let mut def = "\
-table bed
-\"Browser Extensible Data\"
-(
-    string chrom;       \"Reference sequence chromosome or scaffold\"
-    uint   chromStart;  \"Start position in chromosome\"
-    uint   chromEnd;    \"End position in chromosome\"
-"
-    .to_string();
-    const FIELDS: &[&str] = &[
-        "   string name;        \"Name of item.\"\n",
-        "   uint score;          \"Score (0-1000)\"\n",
-        "   char[1] strand;     \"+ or - for strand\"\n",
+// The standard bed fields after the first three, and their autosql definitions
+const BED_FIELDS: &[(&str, &str)] = &[
+    ("name", "   string name;        \"Name of item.\"\n"),
+    ("score", "   uint score;          \"Score (0-1000)\"\n"),
+    ("strand", "   char[1] strand;     \"+ or - for strand\"\n"),
+    (
+        "thickStart",
         "   uint thickStart;   \"Start of where display should be thick (start codon)\"\n",
+    ),
+    (
+        "thickEnd",
         "   uint thickEnd;     \"End of where display should be thick (stop codon)\"\n",
+    ),
+    (
+        "itemRgb",
         "   uint reserved;     \"Used as itemRgb as of 2004-11-22\"\n",
-        "   int blockCount;    \"Number of blocks\"\n",
+    ),
+    ("blockCount", "   int blockCount;    \"Number of blocks\"\n"),
+    (
+        "blockSizes",
         "   int[blockCount] blockSizes; \"Comma separated list of block sizes\"\n",
+    ),
+    (
+        "chromStarts",
         "   int[blockCount] chromStarts; \"Start positions relative to chromStart\"\n",
-        "   int expCount;	\"Experiment count\"\n",
-        "   int[expCount] expIds;	\"Comma separated list of experiment ids. Always 0,1,2,3....\"\n",
+    ),
+    ("expCount", "   int expCount;\t\"Experiment count\"\n"),
+    (
+        "expIds",
+        "   int[expCount] expIds;\t\"Comma separated list of experiment ids. Always 0,1,2,3....\"\n",
+    ),
+    (
+        "expScores",
         "   float[expCount] expScores; \"Comma separated list of experiment scores.\"\n",
-    ];
-    for field in &FIELDS[0..extra_fields.min(FIELDS.len())] {
+    ),
+];
+
+const BED_HEADER: &str = "\
+table bed
+\"Browser Extensible Data\"
+(
+    string chrom;       \"Reference sequence chromosome or scaffold\"
+    uint   chromStart;  \"Start position in chromosome\"
+    uint   chromEnd;    \"End position in chromosome\"
+";
+
+pub fn bed_autosql(rest: &str) -> String {
+    let extra_fields = if rest.is_empty() {
+        0
+    } else {
+        rest.split('\t').count()
+    };
+    let mut def = BED_HEADER.to_string();
+    for (_, field) in &BED_FIELDS[0..extra_fields.min(BED_FIELDS.len())] {
         def.push_str(field);
     }
-    for i in FIELDS.len()..extra_fields.max(FIELDS.len()) {
+    for i in BED_FIELDS.len()..extra_fields.max(BED_FIELDS.len()) {
         def.push_str(&format!(
-            "   lstring field{};	\"Undocumented field\"\n",
+            "   lstring field{};\t\"Undocumented field\"\n",
             i + 3 + 1
         ))
     }
@@ -54,6 +84,438 @@ table bed
     def
 }
 
+/// Like `bed_autosql`, but with column names from a header line (such as
+/// `#chrom start end name score strand signalValue`). Leading columns named
+/// like the standard bed fields are defined as those fields, and the
+/// remaining columns are defined as strings with the header's names. If the
+/// number of names doesn't match the number of columns, this is the same as
+/// `bed_autosql`.
+pub fn bed_autosql_with_names(rest: &str, names: &[String]) -> String {
+    let extra_fields = if rest.is_empty() {
+        0
+    } else {
+        rest.split('\t').count()
+    };
+    if names.len() != extra_fields + 3 {
+        return bed_autosql(rest);
+    }
+    let names = &names[3..];
+    let standard = names
+        .iter()
+        .zip(BED_FIELDS)
+        .take_while(|(name, (field, _))| {
+            name.eq_ignore_ascii_case(field)
+                || (*field == "itemRgb" && name.eq_ignore_ascii_case("reserved"))
+                || (*field == "chromStarts" && name.eq_ignore_ascii_case("blockStarts"))
+        })
+        .count();
+    let mut def = BED_HEADER.to_string();
+    for (_, field) in &BED_FIELDS[0..standard] {
+        def.push_str(field);
+    }
+    for name in &names[standard..] {
+        // Autosql names can only contain letters, digits, and underscores
+        let mut name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            name.insert_str(0, "field_");
+        }
+        def.push_str(&format!("   lstring {};\t\"{}\"\n", name, name));
+    }
+    def.push(')');
+    def
+}
+
+/// Like `bed_autosql`, but only the first `bed_fields` fields (including the
+/// chrom, start, and end) are defined as the standard bed fields, as for a
+/// bed type like `bed6+4`. The remaining columns are undocumented strings.
+pub fn bed_autosql_with_bed_fields(rest: &str, bed_fields: usize) -> String {
+    let extra_fields = if rest.is_empty() {
+        0
+    } else {
+        rest.split('\t').count()
+    };
+    let standard = bed_fields.saturating_sub(3).min(9);
+    let mut def = BED_HEADER.to_string();
+    for (_, field) in &BED_FIELDS[0..standard.min(extra_fields)] {
+        def.push_str(field);
+    }
+    for i in standard..extra_fields {
+        def.push_str(&format!(
+            "   lstring field{};\t\"Undocumented field\"\n",
+            i + 3 + 1
+        ))
+    }
+    def.push(')');
+    def
+}
+
+/// The number of fields defined by `autosql`, and how many of those (from
+/// the start) are the standard bed fields, as stored in a bigBed's header.
+/// Returns `None` if `autosql` can't be parsed or doesn't declare any fields.
+pub fn bed_field_counts(autosql: &str) -> Option<(u16, u16)> {
+    let declarations = parse::parse_autosql(autosql).ok()?;
+    let fields = &declarations.first()?.fields;
+    if fields.is_empty() {
+        return None;
+    }
+    let names = ["chrom", "chromStart", "chromEnd"]
+        .into_iter()
+        .chain(BED_FIELDS[..9].iter().map(|(name, _)| *name));
+    let defined = fields
+        .iter()
+        .zip(names)
+        .take_while(|(field, name)| {
+            field.name == *name
+                || (*name == "itemRgb" && field.name == "reserved")
+                || (*name == "chromStarts" && field.name == "blockStarts")
+        })
+        .count();
+    Some((fields.len() as u16, defined as u16))
+}
+
+pub const NARROW_PEAK: &str = r#"
+table narrowPeak
+"BED6+4 Peaks of signal enrichment based on pooled, normalized (interpreted) data."
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    string name;         "Name given to a region (preferably unique). Use . if no name is assigned"
+    uint   score;        "Indicates how dark the peak will be displayed in the browser (0-1000)"
+    char[1] strand;      "+ or - or . for unknown"
+    float  signalValue;  "Measurement of average enrichment for the region"
+    float  pValue;       "Statistical significance of signal value (-log10). Set to -1 if not used."
+    float  qValue;       "Statistical significance with multiple-test correction applied (FDR -log10). Set to -1 if not used."
+    int    peak;         "Point-source called for this peak; 0-based offset from chromStart. Set to -1 if no point-source called."
+)
+"#;
+
+pub const BROAD_PEAK: &str = r#"
+table broadPeak
+"BED6+3 Peaks of signal enrichment based on pooled, normalized (interpreted) data."
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    string name;         "Name given to a region (preferably unique). Use . if no name is assigned"
+    uint   score;        "Indicates how dark the peak will be displayed in the browser (0-1000)"
+    char[1] strand;      "+ or - or . for unknown"
+    float  signalValue;  "Measurement of average enrichment for the region"
+    float  pValue;       "Statistical significance of signal value (-log10). Set to -1 if not used."
+    float  qValue;       "Statistical significance with multiple-test correction applied (FDR -log10). Set to -1 if not used."
+)
+"#;
+
+pub const GAPPED_PEAK: &str = r#"
+table gappedPeak
+"BED12+3 Peaks of signal enrichment that may be spliced or incorporate gaps in the genomic sequence."
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    string name;         "Name given to a region (preferably unique). Use . if no name is assigned"
+    uint   score;        "Indicates how dark the peak will be displayed in the browser (0-1000)"
+    char[1] strand;      "+ or - or . for unknown"
+    uint   thickStart;   "Start of where display should be thick"
+    uint   thickEnd;     "End of where display should be thick"
+    uint   reserved;     "Used as itemRgb"
+    int    blockCount;   "Number of blocks"
+    int[blockCount] blockSizes; "Comma separated list of block sizes"
+    int[blockCount] chromStarts; "Start positions relative to chromStart"
+    float  signalValue;  "Measurement of average enrichment for the region"
+    float  pValue;       "Statistical significance of signal value (-log10). Set to -1 if not used."
+    float  qValue;       "Statistical significance with multiple-test correction applied (FDR -log10). Set to -1 if not used."
+)
+"#;
+
+pub const BED_METHYL: &str = r#"
+table bedMethyl
+"BED9+2 Methylation data"
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    string name;         "Name of item"
+    uint   score;        "Score from 0-1000. Capped number of reads"
+    char[1] strand;      "+ or - or . for unknown"
+    uint   thickStart;   "Start of where display should be thick"
+    uint   thickEnd;     "End of where display should be thick"
+    uint   reserved;     "Color value R,G,B"
+    uint   readCount;    "Number of reads or coverage"
+    uint   percentMeth;  "Percentage of reads that show methylation at this position in the genome"
+)
+"#;
+
+pub const INTERACT: &str = r#"
+table interact
+"BED5+13 Interaction between two regions"
+(
+    string chrom;        "Chromosome (or contig, scaffold, etc.). For interchromosomal, use 2 records"
+    uint   chromStart;   "Start position of lower region. For interchromosomal, set to chromStart of this region"
+    uint   chromEnd;     "End position of upper region. For interchromosomal, set to chromEnd of this region"
+    string name;         "Name of item, for display. Usually 'sourceName/targetName/exp' or empty"
+    uint   score;        "Score (0-1000)"
+    double value;        "Strength of interaction or other data value. Typically basis for score"
+    string exp;          "Experiment name (metadata for filtering). Use . if not applicable"
+    string color;        "Item color. Specified as r,g,b or hexadecimal #RRGGBB or html color name. Use 0 and spectrum setting to shade by score"
+    string sourceChrom;  "Chromosome of source region (directional) or lower region. For non-directional interchromosomal, chrom of this region."
+    uint   sourceStart;  "Start position in chromosome of source/lower/this region"
+    uint   sourceEnd;    "End position in chromosome of source/lower/this region"
+    string sourceName;   "Identifier of source/lower/this region"
+    string sourceStrand; "Orientation of source/lower/this region: + or -. Use . if not applicable"
+    string targetChrom;  "Chromosome of target region (directional) or upper region. For non-directional interchromosomal, chrom of other region"
+    uint   targetStart;  "Start position in chromosome of target/upper/this region"
+    uint   targetEnd;    "End position in chromosome of target/upper/this region"
+    string targetName;   "Identifier of target/upper/this region"
+    string targetStrand; "Orientation of target/upper/this region: + or -. Use . if not applicable"
+)
+"#;
+
+pub const BIG_GENE_PRED: &str = r#"
+table bigGenePred
+"BED12+8 Gene models"
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    string name;         "Name or ID of item, ideally both human readable and unique"
+    uint   score;        "Score (0-1000)"
+    char[1] strand;      "+ or - for strand"
+    uint   thickStart;   "Start of where display should be thick (start codon)"
+    uint   thickEnd;     "End of where display should be thick (stop codon)"
+    uint   reserved;     "RGB value (use R,G,B string in input file)"
+    int    blockCount;   "Number of blocks"
+    int[blockCount] blockSizes; "Comma separated list of block sizes"
+    int[blockCount] chromStarts; "Start positions relative to chromStart"
+    string name2;        "Alternative/human readable name"
+    string cdsStartStat; "Status of CDS start annotation (none, unknown, incomplete, or complete)"
+    string cdsEndStat;   "Status of CDS end annotation (none, unknown, incomplete, or complete)"
+    int[blockCount] exonFrames; "Exon frame {0,1,2}, or -1 if no frame for exon"
+    string type;         "Transcript type"
+    string geneName;     "Primary identifier for gene"
+    string geneName2;    "Alternative/human readable gene name"
+    string geneType;     "Gene type"
+)
+"#;
+
+/// Common bed-based formats that have a built-in autosql definition
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BedFormat {
+    /// ENCODE narrowPeak (BED6+4)
+    NarrowPeak,
+    /// ENCODE broadPeak (BED6+3)
+    BroadPeak,
+    /// ENCODE gappedPeak (BED12+3)
+    GappedPeak,
+    /// ENCODE bedMethyl (BED9+2)
+    BedMethyl,
+    /// UCSC interact, used for bigInteract (BED5+13)
+    Interact,
+    /// UCSC bigGenePred (BED12+8)
+    BigGenePred,
+}
+
+impl BedFormat {
+    pub const ALL: [BedFormat; 6] = [
+        BedFormat::NarrowPeak,
+        BedFormat::BroadPeak,
+        BedFormat::GappedPeak,
+        BedFormat::BedMethyl,
+        BedFormat::Interact,
+        BedFormat::BigGenePred,
+    ];
+
+    /// The name of the format, as used by UCSC
+    pub fn name(&self) -> &'static str {
+        match self {
+            BedFormat::NarrowPeak => "narrowPeak",
+            BedFormat::BroadPeak => "broadPeak",
+            BedFormat::GappedPeak => "gappedPeak",
+            BedFormat::BedMethyl => "bedMethyl",
+            BedFormat::Interact => "interact",
+            BedFormat::BigGenePred => "bigGenePred",
+        }
+    }
+
+    /// Finds a format by name (ignoring case). `bigInteract` is accepted for
+    /// `interact`, and `genePred` for `bigGenePred`.
+    pub fn from_name(name: &str) -> Option<BedFormat> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "biginteract" => return Some(BedFormat::Interact),
+            "genepred" => return Some(BedFormat::BigGenePred),
+            _ => {}
+        }
+        BedFormat::ALL
+            .into_iter()
+            .find(|f| f.name().to_ascii_lowercase() == name)
+    }
+
+    pub fn autosql(&self) -> &'static str {
+        match self {
+            BedFormat::NarrowPeak => NARROW_PEAK,
+            BedFormat::BroadPeak => BROAD_PEAK,
+            BedFormat::GappedPeak => GAPPED_PEAK,
+            BedFormat::BedMethyl => BED_METHYL,
+            BedFormat::Interact => INTERACT,
+            BedFormat::BigGenePred => BIG_GENE_PRED,
+        }
+    }
+}
+
+/// Possible errors when creating a `BedFieldValidator` from an autosql
+#[derive(Error, Debug)]
+pub enum BedFieldValidatorError {
+    #[error("Invalid autosql: {:?}", .0)]
+    ParseError(parse::ParseError),
+    #[error("Invalid autosql: no fields are declared.")]
+    NoFields,
+    #[error("Invalid autosql: the first fields must be chrom, chromStart, and chromEnd.")]
+    MissingBedFields,
+}
+
+/// Checks that bed entries have the expected number of fields, that the
+/// standard bed fields are valid, and that any other fields match the types
+/// declared in an autosql definition.
+#[derive(Clone, Debug)]
+pub struct BedFieldValidator {
+    // The number of standard bed fields (including chrom, start, and end)
+    bed_fields: usize,
+    // The total number of fields, or `None` if any number of extra fields
+    // is allowed
+    field_count: Option<usize>,
+    // The declared fields after the standard bed fields
+    extra_fields: Vec<parse::Field>,
+}
+
+impl BedFieldValidator {
+    /// Validates entries against the fields declared in `autosql`
+    pub fn from_autosql(autosql: &str) -> Result<Self, BedFieldValidatorError> {
+        let declarations =
+            parse::parse_autosql(autosql).map_err(BedFieldValidatorError::ParseError)?;
+        let (field_count, bed_fields) =
+            bed_field_counts(autosql).ok_or(BedFieldValidatorError::NoFields)?;
+        if bed_fields < 3 {
+            return Err(BedFieldValidatorError::MissingBedFields);
+        }
+        Ok(BedFieldValidator {
+            bed_fields: bed_fields as usize,
+            field_count: Some(field_count as usize),
+            extra_fields: declarations[0].fields[bed_fields as usize..].to_vec(),
+        })
+    }
+
+    /// Validates entries against a UCSC bed type, such as `bed6`, `bed6+4`
+    /// (exactly four extra fields), or `bed6+` (any number of extra fields).
+    /// The extra fields can have any values.
+    pub fn from_bed_type(bed_type: &str) -> Option<Self> {
+        let bed_type = bed_type.strip_prefix("bed")?;
+        let (bed_fields, extra) = match bed_type.split_once('+') {
+            Some((bed_fields, "")) => (bed_fields, None),
+            Some((bed_fields, extra)) => (bed_fields, Some(extra.parse::<usize>().ok()?)),
+            None => (bed_type, Some(0)),
+        };
+        let bed_fields = bed_fields.parse::<usize>().ok()?;
+        if !(3..=12).contains(&bed_fields) || bed_fields == 10 || bed_fields == 11 {
+            return None;
+        }
+        Some(BedFieldValidator {
+            bed_fields,
+            field_count: extra.map(|extra| bed_fields + extra),
+            extra_fields: vec![],
+        })
+    }
+
+    /// The number of standard bed fields (including chrom, start, and end)
+    pub fn bed_fields(&self) -> usize {
+        self.bed_fields
+    }
+
+    /// The total number of fields, if it is fixed
+    pub fn field_count(&self) -> Option<usize> {
+        self.field_count
+    }
+
+    pub fn validate(&self, entry: &BedEntry) -> Result<(), BedValueError> {
+        let rest = entry.rest.trim_end_matches(['\r', '\n']);
+        let cols: Vec<&str> = match rest.is_empty() {
+            true => vec![],
+            false => rest.split('\t').collect(),
+        };
+        let found = cols.len() + 3;
+        let expected = self.field_count.unwrap_or(self.bed_fields);
+        if found < self.bed_fields || (self.field_count.is_some() && found != expected) {
+            return Err(BedValueError::InvalidInput(format!(
+                "Expected {} fields, but found {}.",
+                expected, found
+            )));
+        }
+        BedRecord::from_entry(entry, self.bed_fields)?;
+
+        let extra = &cols[self.bed_fields - 3..];
+        for (i, (field, value)) in self.extra_fields.iter().zip(extra).enumerate() {
+            validate_field(field, value).map_err(|message| {
+                BedValueError::InvalidInput(format!(
+                    "Invalid {} (column {}): {}",
+                    field.name,
+                    self.bed_fields + 1 + i,
+                    message
+                ))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+// Checks that `value` is valid for `field`, returning why not if it isn't
+fn validate_field(field: &parse::Field, value: &str) -> Result<(), String> {
+    use parse::FieldType;
+
+    let check = |value: &str| -> bool {
+        let value = value.trim();
+        match &field.field_type {
+            FieldType::Int | FieldType::Short | FieldType::Byte | FieldType::Bigint => {
+                value.parse::<i64>().is_ok()
+            }
+            FieldType::Uint | FieldType::Ushort | FieldType::Ubyte => value.parse::<u64>().is_ok(),
+            FieldType::Float | FieldType::Double => value.parse::<f64>().is_ok(),
+            FieldType::Enum(values) => values.iter().any(|v| v == value),
+            FieldType::Set(values) => value
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .all(|v| values.iter().any(|value| value == v)),
+            FieldType::Char
+            | FieldType::String
+            | FieldType::Lstring
+            | FieldType::Declaration(..) => true,
+        }
+    };
+    match (&field.field_type, &field.field_size) {
+        // A fixed size string
+        (parse::FieldType::Char, Some(size)) => match size.parse::<usize>() {
+            Ok(size) if value.len() > size => {
+                Err(format!("`{}` is longer than {} characters.", value, size))
+            }
+            _ => Ok(()),
+        },
+        // An array (its size may be given by another field)
+        (_, Some(_)) => match value.split(',').filter(|v| !v.is_empty()).all(check) {
+            true => Ok(()),
+            false => Err(format!(
+                "`{}` isn't a list of {:?}.",
+                value, field.field_type
+            )),
+        },
+        (_, None) => match check(value) {
+            true => Ok(()),
+            false => Err(format!("`{}` isn't a valid {:?}.", value, field.field_type)),
+        },
+    }
+}
+
 // Defined by https://github.com/ucscGenomeBrowser/kent/blob/c26640b68ba8ad219e7d79c3f8251ea20f9f57e0/src/hg/autoSql/autoSql.doc
 pub mod parse {
     mod parser {
@@ -701,6 +1163,47 @@ pub mod parse {
             super::parse_autosql(super::super::BED3).unwrap();
         }
 
+        #[test]
+        fn test_bed_autosql_with_names() {
+            let names: Vec<String> = [
+                "chrom",
+                "start",
+                "end",
+                "name",
+                "score",
+                "strand",
+                "signalValue",
+                "p-value",
+            ]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+            let autosql = super::super::bed_autosql_with_names("a\t0\t+\t1.5\t0.01", &names);
+            let declarations = super::parse_autosql(&autosql).unwrap();
+            let fields: Vec<&str> = declarations[0]
+                .fields
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect();
+            assert_eq!(
+                fields,
+                [
+                    "chrom",
+                    "chromStart",
+                    "chromEnd",
+                    "name",
+                    "score",
+                    "strand",
+                    "signalValue",
+                    "p_value"
+                ]
+            );
+
+            // The names don't match the columns
+            let autosql = super::super::bed_autosql_with_names("a\t0", &names);
+            assert_eq!(autosql, super::super::bed_autosql("a\t0"));
+        }
+
         #[test]
         fn test_maintest() {
             let main_test = r#"
@@ -822,3 +1325,103 @@ pub mod parse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(rest: &str) -> BedEntry {
+        BedEntry {
+            start: 100,
+            end: 200,
+            rest: rest.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        let expected = [(10, 6), (9, 6), (15, 12), (11, 9), (18, 5), (20, 12)];
+        for (format, counts) in BedFormat::ALL.into_iter().zip(expected) {
+            assert_eq!(bed_field_counts(format.autosql()), Some(counts));
+            assert_eq!(BedFormat::from_name(format.name()), Some(format));
+        }
+        assert_eq!(
+            BedFormat::from_name("bigInteract"),
+            Some(BedFormat::Interact)
+        );
+        assert_eq!(
+            BedFormat::from_name("NARROWPEAK"),
+            Some(BedFormat::NarrowPeak)
+        );
+        assert_eq!(BedFormat::from_name("bed6"), None);
+
+        assert_eq!(bed_field_counts(BED3), Some((3, 3)));
+        assert_eq!(
+            bed_field_counts(&bed_autosql("name\t0\t+\t1.5")),
+            Some((7, 7))
+        );
+        assert_eq!(
+            bed_field_counts(&bed_autosql_with_bed_fields("name\t0\t+\t1.5", 6)),
+            Some((7, 6))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let narrow_peak = BedFieldValidator::from_autosql(NARROW_PEAK).unwrap();
+        assert!(narrow_peak
+            .validate(&entry("peak1\t500\t.\t11.08\t-1\t0.5\t230"))
+            .is_ok());
+        // Missing a column, an invalid strand, and a float peak
+        assert!(narrow_peak
+            .validate(&entry("peak1\t500\t.\t11.08\t-1\t0.5"))
+            .is_err());
+        assert!(narrow_peak
+            .validate(&entry("peak1\t500\tx\t11.08\t-1\t0.5\t230"))
+            .is_err());
+        let err = narrow_peak
+            .validate(&entry("peak1\t500\t.\t11.08\t-1\t0.5\t2.5"))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid peak (column 10): `2.5` isn't a valid Int."
+        );
+
+        // Arrays, and itemRgb as R,G,B
+        let gene_pred = BedFieldValidator::from_autosql(BIG_GENE_PRED).unwrap();
+        let rest = "tx1\t0\t+\t100\t200\t255,0,0\t2\t10,10,\t0,90,\tname2\tcmpl\tcmpl\t0,-1,\tcoding\tgene\tgene2\tprotein";
+        assert!(gene_pred.validate(&entry(rest)).is_ok());
+        let rest = rest.replace("0,-1,", "0,x,");
+        assert!(gene_pred.validate(&entry(&rest)).is_err());
+
+        let bed6 = BedFieldValidator::from_bed_type("bed6+").unwrap();
+        assert!(bed6.validate(&entry("a\t0\t+")).is_ok());
+        assert!(bed6.validate(&entry("a\t0\t+\tanything\telse")).is_ok());
+        assert!(bed6.validate(&entry("a\t0")).is_err());
+        let bed6_2 = BedFieldValidator::from_bed_type("bed6+2").unwrap();
+        assert!(bed6_2.validate(&entry("a\t0\t+\tanything")).is_err());
+        assert_eq!(
+            BedFieldValidator::from_bed_type("bed3")
+                .unwrap()
+                .field_count(),
+            Some(3)
+        );
+        assert!(BedFieldValidator::from_bed_type("bed11").is_none());
+        assert!(BedFieldValidator::from_bed_type("narrowPeak").is_none());
+
+        let no_bed_fields = r#"table test
+"Not a bed"
+(
+string name; "Name"
+uint count; "Count"
+)"#;
+        assert!(matches!(
+            BedFieldValidator::from_autosql(no_bed_fields),
+            Err(BedFieldValidatorError::MissingBedFields)
+        ));
+        assert!(matches!(
+            BedFieldValidator::from_autosql("tablex test"),
+            Err(BedFieldValidatorError::ParseError(_))
+        ));
+    }
+}
@@ -9,7 +9,6 @@
 //! The second layer of abstraction (`BedParser`) manages the state information for when values switch
 //! from one chromosome to another. The is important because bigwig/bigbed writing is "chunked" by chromosome.
 
-use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read};
 use std::sync::Arc;
 
@@ -17,7 +16,43 @@ use crossbeam_utils::atomic::AtomicCell;
 use thiserror::Error;
 
 use crate::bbi::{BedEntry, Value};
-use crate::utils::streaming_linereader::StreamingLineReader;
+use crate::utils::streaming_linereader::{LinePosition, StreamingLineReader};
+
+/// Returns whether a line of a bed-like file is a header line rather than
+/// data: a `track` or `browser` line, a `#` comment, or a blank line.
+pub fn is_header_line(line: &str) -> bool {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return true;
+    }
+    matches!(line.split_whitespace().next(), Some("track" | "browser"))
+}
+
+/// The header lines (`track`, `browser`, and `#` lines) before the first data
+/// line of a bed-like file.
+#[derive(Clone, Debug, Default)]
+pub struct BedHeader {
+    pub lines: Vec<String>,
+}
+
+impl BedHeader {
+    /// The column names of the last `#` line, such as `#chrom start end name`.
+    pub fn column_names(&self) -> Option<Vec<String>> {
+        let line = self.lines.iter().rev().find(|l| l.starts_with('#'))?;
+        let line = line.trim_start_matches('#').trim();
+        if line.is_empty() {
+            return None;
+        }
+        let delimiter = if line.contains('\t') { '\t' } else { ' ' };
+        Some(
+            line.split(delimiter)
+                .map(|c| c.trim())
+                .filter(|c| !c.is_empty())
+                .map(|c| c.to_string())
+                .collect(),
+        )
+    }
+}
 
 pub fn parse_bed<'a>(s: &'a str) -> Option<Result<(&'a str, BedEntry), BedValueError>> {
     let mut split = s.splitn(4, '\t');
@@ -26,18 +61,18 @@ pub fn parse_bed<'a>(s: &'a str) -> Option<Result<(&'a str, BedEntry), BedValueE
         None => return None,
     };
     let res = (|| {
-        let s = split
-            .next()
-            .ok_or_else(|| BedValueError::InvalidInput(format!("Missing start: {:}", s)))?;
-        let start = s
-            .parse::<u32>()
-            .map_err(|_| BedValueError::InvalidInput(format!("Invalid start: {:}", s)))?;
-        let s = split
-            .next()
-            .ok_or_else(|| BedValueError::InvalidInput(format!("Missing end: {:}", s)))?;
+        let s = split.next().ok_or_else(|| {
+            BedValueError::InvalidInput(format!("Missing start (column 2): {:}", s))
+        })?;
+        let start = s.parse::<u32>().map_err(|_| {
+            BedValueError::InvalidInput(format!("Invalid start (column 2): {:}", s))
+        })?;
+        let s = split.next().ok_or_else(|| {
+            BedValueError::InvalidInput(format!("Missing end (column 3): {:}", s))
+        })?;
         let end = s
             .parse::<u32>()
-            .map_err(|_| BedValueError::InvalidInput(format!("Invalid end: {:}", s)))?;
+            .map_err(|_| BedValueError::InvalidInput(format!("Invalid end (column 3): {:}", s)))?;
         let rest = split.next().unwrap_or("").to_string();
         Ok((start, end, rest))
     })();
@@ -54,24 +89,24 @@ pub fn parse_bedgraph<'a>(s: &'a str) -> Option<Result<(&'a str, Value), BedValu
         None => return None,
     };
     let res = (|| {
-        let s = split
-            .next()
-            .ok_or_else(|| BedValueError::InvalidInput(format!("Missing start: {:}", s)))?;
-        let start = s
-            .parse::<u32>()
-            .map_err(|_| BedValueError::InvalidInput(format!("Invalid start: {:}", s)))?;
-        let s = split
-            .next()
-            .ok_or_else(|| BedValueError::InvalidInput(format!("Missing end: {:}", s)))?;
+        let s = split.next().ok_or_else(|| {
+            BedValueError::InvalidInput(format!("Missing start (column 2): {:}", s))
+        })?;
+        let start = s.parse::<u32>().map_err(|_| {
+            BedValueError::InvalidInput(format!("Invalid start (column 2): {:}", s))
+        })?;
+        let s = split.next().ok_or_else(|| {
+            BedValueError::InvalidInput(format!("Missing end (column 3): {:}", s))
+        })?;
         let end = s
             .parse::<u32>()
-            .map_err(|_| BedValueError::InvalidInput(format!("Invalid end: {:}", s)))?;
-        let s = split
-            .next()
-            .ok_or_else(|| BedValueError::InvalidInput(format!("Missing value: {:}", s)))?;
-        let value = s
-            .parse::<f32>()
-            .map_err(|_| BedValueError::InvalidInput(format!("Invalid value: {:}", s)))?;
+            .map_err(|_| BedValueError::InvalidInput(format!("Invalid end (column 3): {:}", s)))?;
+        let s = split.next().ok_or_else(|| {
+            BedValueError::InvalidInput(format!("Missing value (column 4): {:}", s))
+        })?;
+        let value = s.parse::<f32>().map_err(|_| {
+            BedValueError::InvalidInput(format!("Invalid value (column 4): {:}", s))
+        })?;
         Ok((start, end, value))
     })();
     match res {
@@ -87,38 +122,110 @@ pub trait StreamingBedValues {
     type Value;
 
     fn next(&mut self) -> Option<Result<(&str, Self::Value), BedValueError>>;
+
+    /// The header lines seen so far, if they are captured
+    fn header(&self) -> Option<&BedHeader> {
+        None
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum BedValueError {
     #[error("{}", .0)]
     InvalidInput(String),
+    /// Invalid input at a line of the file. When chromosomes are read in
+    /// parallel (by `BedParserParallelStreamingIterator`), each is read from
+    /// its offset in the file, so the line number counts from the first line
+    /// of the chromosome. For BGZF files, the position then has the virtual
+    /// offset of that first line instead of the byte offset of the line.
+    #[error("Invalid input at {}: {}", .position, .message)]
+    InvalidLine {
+        position: LinePosition,
+        message: String,
+    },
     #[error("Error occurred: {}", .0)]
     IoError(#[from] io::Error),
 }
 
+impl BedValueError {
+    /// Adds the position of the line that caused the error, if it is invalid
+    /// input without a position.
+    pub fn at(self, position: LinePosition) -> Self {
+        match self {
+            BedValueError::InvalidInput(message) => {
+                BedValueError::InvalidLine { position, message }
+            }
+            e => e,
+        }
+    }
+}
+
 pub type Parser<V> = for<'a> fn(&'a str) -> Option<Result<(&'a str, V), BedValueError>>;
 
-/// Parses a bed-like file
+/// Checks a parsed value, such as that a bed entry has the expected fields
+pub type Validator<V> = Box<dyn Fn(&V) -> Result<(), BedValueError> + Send>;
+
+/// Parses a bed-like file. Header lines (see `is_header_line`) are skipped,
+/// and those before the first data line are kept in `header` if
+/// `capture_header` is set. If there is a `validate` function, values that
+/// fail it are errors (at the line of the value).
 pub struct BedFileStream<V, B> {
     pub bed: StreamingLineReader<B>,
     pub parse: Parser<V>,
+    pub validate: Option<Validator<V>>,
+    pub capture_header: bool,
+    pub header: BedHeader,
+    data_started: bool,
+}
+
+impl<V, B> BedFileStream<V, B> {
+    pub fn new(bed: StreamingLineReader<B>, parse: Parser<V>) -> Self {
+        BedFileStream {
+            bed,
+            parse,
+            validate: None,
+            capture_header: false,
+            header: BedHeader::default(),
+            data_started: false,
+        }
+    }
 }
 
 impl<V, B: BufRead> StreamingBedValues for BedFileStream<V, B> {
     type Value = V;
 
     fn next(&mut self) -> Option<Result<(&str, Self::Value), BedValueError>> {
-        let line = match self.bed.read()? {
-            Ok(line) => line.trim_end(),
-            Err(e) => return Some(Err(e.into())),
-        };
-        match (self.parse)(line) {
+        loop {
+            let line = match self.bed.read()? {
+                Ok(line) => line.trim_end(),
+                Err(e) => return Some(Err(e.into())),
+            };
+            if !is_header_line(line) {
+                self.data_started = true;
+                break;
+            }
+            if self.capture_header && !self.data_started && !line.is_empty() {
+                self.header.lines.push(line.to_string());
+            }
+        }
+        let line = self.bed.current_line().trim_end();
+        let validated = (self.parse)(line).map(|v| {
+            let v = v?;
+            match &self.validate {
+                Some(validate) => validate(&v.1).map(|_| v),
+                None => Ok(v),
+            }
+        });
+        match validated {
             None => None,
             Some(Ok(v)) => Some(Ok(v)),
-            Some(Err(e)) => Some(Err(e.into())),
+            Some(Err(e)) => Some(Err(e.at(self.bed.position()))),
         }
     }
+
+    fn header(&self) -> Option<&BedHeader> {
+        self.capture_header.then_some(&self.header)
+    }
 }
 
 // Wraps a bed-like Iterator
@@ -219,21 +326,32 @@ impl<S: StreamingBedValues> BedParser<S> {
     }
 }
 
-impl BedParser<BedFileStream<BedEntry, BufReader<File>>> {
-    pub fn from_bed_file(file: File) -> Self {
-        BedParser::new(BedFileStream {
-            bed: StreamingLineReader::new(BufReader::new(file)),
-            parse: parse_bed,
-        })
+impl<R: Read> BedParser<BedFileStream<BedEntry, BufReader<R>>> {
+    pub fn from_bed_file(file: R) -> Self {
+        let mut stream =
+            BedFileStream::new(StreamingLineReader::new(BufReader::new(file)), parse_bed);
+        stream.capture_header = true;
+        BedParser::new(stream)
+    }
+
+    /// Like `from_bed_file`, but each entry is checked with `validate`
+    pub fn from_validated_bed_file(file: R, validate: Validator<BedEntry>) -> Self {
+        let mut stream =
+            BedFileStream::new(StreamingLineReader::new(BufReader::new(file)), parse_bed);
+        stream.capture_header = true;
+        stream.validate = Some(validate);
+        BedParser::new(stream)
     }
 }
 
 impl<R: Read> BedParser<BedFileStream<Value, BufReader<R>>> {
     pub fn from_bedgraph_file(file: R) -> Self {
-        BedParser::new(BedFileStream {
-            bed: StreamingLineReader::new(BufReader::new(file)),
-            parse: parse_bedgraph,
-        })
+        let mut stream = BedFileStream::new(
+            StreamingLineReader::new(BufReader::new(file)),
+            parse_bedgraph,
+        );
+        stream.capture_header = true;
+        BedParser::new(stream)
     }
 }
 
@@ -246,6 +364,17 @@ impl<V: Clone, E: Into<BedValueError>, I: Iterator<Item = Result<(String, V), E>
 }
 
 impl<S: StreamingBedValues> BedParser<S> {
+    /// The header lines of the underlying stream, if it captures them. Only
+    /// the lines before the first value that has been read are included.
+    pub fn header(&self) -> Option<BedHeader> {
+        let state = self.state.swap(None).expect(
+            "Invalid usage. The header can't be read while values for a chrom are being read.",
+        );
+        let header = state.stream.header().cloned();
+        self.state.swap(Some(state));
+        header
+    }
+
     // This is *valid* to call multiple times for the same chromosome (assuming the
     // `BedChromData` has been dropped), since calling this function doesn't
     // actually advance the state (it will only set `next_val` if it currently is none).
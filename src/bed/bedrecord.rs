@@ -0,0 +1,348 @@
+//! A parsed representation of bed entries, with the standard bed fields
+//! (`name` through the BED12 blocks) as typed values rather than a string.
+//!
+//! A `BedRecord` can be parsed from a `BedEntry` with `BedRecord::from_entry`
+//! (or `TryFrom`), or read directly from a bigBed with
+//! `BigBedRead::get_interval_records`.
+
+use std::str::FromStr;
+
+use crate::bbi::BedEntry;
+use crate::bed::bedparser::BedValueError;
+
+/// The strand of a bed entry
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+    Unknown,
+}
+
+impl Strand {
+    /// Parses a bed strand column: `+`, `-`, or anything else (such as `.`) for an unknown strand.
+    pub fn parse(s: &str) -> Strand {
+        match s {
+            "+" => Strand::Forward,
+            "-" => Strand::Reverse,
+            _ => Strand::Unknown,
+        }
+    }
+}
+
+/// A bed entry with its standard fields parsed. Fields that aren't in the
+/// entry are `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BedRecord {
+    pub start: u32,
+    pub end: u32,
+    pub name: Option<String>,
+    pub score: Option<u32>,
+    /// `Unknown` if there is no strand column, or it is `.`
+    pub strand: Strand,
+    pub thick_start: Option<u32>,
+    pub thick_end: Option<u32>,
+    pub item_rgb: Option<[u8; 3]>,
+    /// The absolute `(start, end)` of each block (such as exons), in order
+    pub blocks: Option<Vec<(u32, u32)>>,
+    /// Any fields after the standard bed fields
+    pub extra: Vec<String>,
+}
+
+fn parse_col<T: FromStr>(value: &str, field: &str, column: usize) -> Result<T, BedValueError> {
+    value.trim().parse::<T>().map_err(|_| {
+        BedValueError::InvalidInput(format!("Invalid {} (column {}): {}", field, column, value))
+    })
+}
+
+impl BedRecord {
+    /// Parses `entry`, where the first `bed_fields` fields (including the
+    /// chromosome, start, and end) are the standard bed fields. The
+    /// remaining fields are kept in `extra`. For example, a BED6+4 file
+    /// (like narrowPeak) has `6` bed fields. Standard fields missing from
+    /// the entry are `None`.
+    pub fn from_entry(entry: &BedEntry, bed_fields: usize) -> Result<Self, BedValueError> {
+        let bed_fields = bed_fields.clamp(3, 12);
+        if bed_fields == 10 || bed_fields == 11 {
+            return Err(BedValueError::InvalidInput(format!(
+                "Invalid number of bed fields: {}. blockCount, blockSizes, and chromStarts must all be present.",
+                bed_fields
+            )));
+        }
+        let rest = entry.rest.trim_end_matches(['\r', '\n']);
+        let mut cols = rest.split('\t').filter(|_| !rest.is_empty());
+        let standard: Vec<&str> = cols.by_ref().take(bed_fields - 3).collect();
+        let extra = cols.map(|c| c.to_string()).collect();
+        let col = |i: usize| standard.get(i - 4).copied();
+
+        let score = match col(5) {
+            None | Some(".") => None,
+            Some(score) => Some(parse_col(score, "score", 5)?),
+        };
+        let strand = match col(6) {
+            None => Strand::Unknown,
+            Some(strand @ ("+" | "-" | ".")) => Strand::parse(strand),
+            Some(strand) => {
+                return Err(BedValueError::InvalidInput(format!(
+                    "Invalid strand (column 6): {}",
+                    strand
+                )));
+            }
+        };
+        let thick_start = col(7).map(|v| parse_col(v, "thickStart", 7)).transpose()?;
+        let thick_end = col(8).map(|v| parse_col(v, "thickEnd", 8)).transpose()?;
+        let item_rgb = match col(9) {
+            None => None,
+            Some(rgb) if rgb.trim() == "0" => Some([0, 0, 0]),
+            Some(rgb) => {
+                let parts = rgb
+                    .split(',')
+                    .map(|v| parse_col::<u8>(v, "itemRgb", 9))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match parts[..] {
+                    [r, g, b] => Some([r, g, b]),
+                    _ => {
+                        return Err(BedValueError::InvalidInput(format!(
+                            "Invalid itemRgb (column 9): {}",
+                            rgb
+                        )));
+                    }
+                }
+            }
+        };
+        let blocks = match (col(10), col(11), col(12)) {
+            (Some(count), Some(sizes), Some(starts)) => {
+                let blocks = parse_blocks(entry, count, sizes, starts)?;
+                // Exons and introns are in genomic order
+                if blocks.windows(2).any(|w| w[1].0 < w[0].1) {
+                    return Err(BedValueError::InvalidInput(format!(
+                        "Invalid blocks for bed entry at {}-{}: Blocks must be in order and not overlap.",
+                        entry.start, entry.end
+                    )));
+                }
+                Some(blocks)
+            }
+            _ => None,
+        };
+
+        Ok(BedRecord {
+            start: entry.start,
+            end: entry.end,
+            name: col(4).map(|n| n.to_string()),
+            score,
+            strand,
+            thick_start,
+            thick_end,
+            item_rgb,
+            blocks,
+            extra,
+        })
+    }
+
+    /// The exons (blocks) of this entry, in genomic order. Entries without
+    /// blocks are a single exon.
+    pub fn exons(&self) -> impl DoubleEndedIterator<Item = (u32, u32)> + '_ {
+        let whole = self.blocks.is_none().then_some((self.start, self.end));
+        self.blocks.iter().flatten().copied().chain(whole)
+    }
+
+    /// The introns (gaps between blocks) of this entry, in genomic order
+    pub fn introns(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.exons()
+            .zip(self.exons().skip(1))
+            .map(|(prev, next)| (prev.1, next.0))
+            .filter(|(start, end)| start < end)
+    }
+
+    /// The exons of this entry from its 5' end to its 3' end: in genomic
+    /// order, unless the entry is on the reverse strand.
+    pub fn stranded_exons(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let reverse = self.strand == Strand::Reverse;
+        let forward = (!reverse).then(|| self.exons()).into_iter().flatten();
+        let backward = reverse.then(|| self.exons().rev()).into_iter().flatten();
+        forward.chain(backward)
+    }
+
+    /// The total length of the exons
+    pub fn exon_length(&self) -> u32 {
+        self.exons().map(|(start, end)| end - start).sum()
+    }
+
+    /// The offset of the genomic base `pos` within the spliced entry (its
+    /// exons joined together), counted from the 5' end. `None` if `pos`
+    /// isn't in an exon.
+    pub fn transcript_offset(&self, pos: u32) -> Option<u32> {
+        let reverse = self.strand == Strand::Reverse;
+        let mut offset = 0;
+        for (start, end) in self.stranded_exons() {
+            if start <= pos && pos < end {
+                return Some(match reverse {
+                    false => offset + (pos - start),
+                    true => offset + (end - 1 - pos),
+                });
+            }
+            offset += end - start;
+        }
+        None
+    }
+
+    /// The genomic base at `offset` within the spliced entry, counted from
+    /// the 5' end. The inverse of `transcript_offset`.
+    pub fn genomic_position(&self, mut offset: u32) -> Option<u32> {
+        let reverse = self.strand == Strand::Reverse;
+        for (start, end) in self.stranded_exons() {
+            if offset < end - start {
+                return Some(match reverse {
+                    false => start + offset,
+                    true => end - 1 - offset,
+                });
+            }
+            offset -= end - start;
+        }
+        None
+    }
+}
+
+impl TryFrom<&BedEntry> for BedRecord {
+    type Error = BedValueError;
+
+    /// Parses all of the standard bed fields in `entry` (up to BED12)
+    fn try_from(entry: &BedEntry) -> Result<Self, Self::Error> {
+        BedRecord::from_entry(entry, 12)
+    }
+}
+
+/// Parses the block columns (`blockCount`, `blockSizes`, and `chromStarts`)
+/// of `entry` into the absolute `(start, end)` of each block, in the order
+/// they are listed. Blocks must be within the entry.
+pub fn parse_blocks(
+    entry: &BedEntry,
+    count: &str,
+    sizes: &str,
+    starts: &str,
+) -> Result<Vec<(u32, u32)>, BedValueError> {
+    let invalid = |msg: &str| {
+        BedValueError::InvalidInput(format!(
+            "Invalid blocks for bed entry at {}-{}: {}",
+            entry.start, entry.end, msg
+        ))
+    };
+    let parse_list = |col: &str| -> Result<Vec<u32>, BedValueError> {
+        col.split(',')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid(&format!("Invalid block list `{}`.", col)))
+    };
+    let block_count = count
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| invalid(&format!("Invalid block count `{}`.", count)))?;
+    let sizes = parse_list(sizes)?;
+    let starts = parse_list(starts)?;
+    if sizes.len() != block_count || starts.len() != block_count {
+        return Err(invalid(
+            "The number of block sizes and starts must match the block count.",
+        ));
+    }
+    let mut blocks = Vec::with_capacity(block_count);
+    for (block_start, size) in starts.into_iter().zip(sizes) {
+        let start = entry.start.checked_add(block_start);
+        let end = start.and_then(|start| start.checked_add(size));
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if end <= entry.end => (start, end),
+            _ => return Err(invalid("Blocks must be within the entry.")),
+        };
+        blocks.push((start, end));
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start: u32, end: u32, rest: &str) -> BedEntry {
+        BedEntry {
+            start,
+            end,
+            rest: rest.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_bed12() {
+        let record = BedRecord::try_from(&entry(
+            100,
+            200,
+            "tx1\t960\t-\t110\t190\t255,0,0\t3\t10,20,30,\t0,40,70,\textra\n",
+        ))
+        .unwrap();
+        assert_eq!(record.name.as_deref(), Some("tx1"));
+        assert_eq!(record.score, Some(960));
+        assert_eq!(record.strand, Strand::Reverse);
+        assert_eq!(
+            (record.thick_start, record.thick_end),
+            (Some(110), Some(190))
+        );
+        assert_eq!(record.item_rgb, Some([255, 0, 0]));
+        assert_eq!(
+            record.blocks,
+            Some(vec![(100, 110), (140, 160), (170, 200)])
+        );
+        assert_eq!(record.extra, vec!["extra"]);
+
+        assert_eq!(
+            record.introns().collect::<Vec<_>>(),
+            vec![(110, 140), (160, 170)]
+        );
+        assert_eq!(
+            record.stranded_exons().collect::<Vec<_>>(),
+            vec![(170, 200), (140, 160), (100, 110)]
+        );
+        assert_eq!(record.exon_length(), 60);
+        // On the reverse strand, the 5' end is the last base
+        assert_eq!(record.transcript_offset(199), Some(0));
+        assert_eq!(record.transcript_offset(170), Some(29));
+        assert_eq!(record.transcript_offset(159), Some(30));
+        assert_eq!(record.transcript_offset(120), None);
+        for offset in 0..60 {
+            let pos = record.genomic_position(offset).unwrap();
+            assert_eq!(record.transcript_offset(pos), Some(offset));
+        }
+        assert_eq!(record.genomic_position(60), None);
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let record = BedRecord::try_from(&entry(0, 50, "")).unwrap();
+        assert_eq!(record.name, None);
+        assert_eq!(record.exons().collect::<Vec<_>>(), vec![(0, 50)]);
+        assert_eq!(record.introns().count(), 0);
+
+        // A narrowPeak (BED6+4) entry
+        let record =
+            BedRecord::from_entry(&entry(10, 30, ".\t0\t.\t11.08\t-1.0\t0.505\t12"), 6).unwrap();
+        assert_eq!(record.name.as_deref(), Some("."));
+        assert_eq!(record.score, Some(0));
+        assert_eq!(record.strand, Strand::Unknown);
+        assert_eq!(record.thick_start, None);
+        assert_eq!(record.extra, vec!["11.08", "-1.0", "0.505", "12"]);
+        assert_eq!(record.transcript_offset(12), Some(2));
+
+        // Those extra fields aren't valid as standard fields
+        assert!(BedRecord::try_from(&entry(10, 30, ".\t0\t.\t11.08\t-1.0\t0.505\t12")).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = |rest: &str| BedRecord::try_from(&entry(100, 200, rest)).is_err();
+        assert!(invalid("a\tscore"));
+        assert!(invalid("a\t0\tx"));
+        assert!(invalid("a\t0\t+\t100\t200\t1,2"));
+        // Block count mismatch, outside the entry, and overlapping
+        assert!(invalid("a\t0\t+\t100\t200\t0\t2\t10\t0,50"));
+        assert!(invalid("a\t0\t+\t100\t200\t0\t2\t10,100\t0,50"));
+        assert!(invalid("a\t0\t+\t100\t200\t0\t2\t20,10\t0,10"));
+        assert!(BedRecord::from_entry(&entry(100, 200, ""), 10).is_err());
+    }
+}
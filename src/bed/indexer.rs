@@ -4,52 +4,82 @@ Utilities for indexing a bed file that is start-sorted (chromosomes may be out o
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
 
+use crate::bed::bedparser::is_header_line;
+use crate::utils::file::bgzf::BgzfReader;
+use crate::utils::file::gzip::GzipReader;
 use crate::utils::indexlist::{Index, IndexList};
 use crate::utils::tell::Tell;
 
 /// Returns a Vec of offsets into a bed file, and the chromosome starting at each offset.
+///
+/// The file may be BGZF compressed (such as by `bgzip`), in which case the
+/// offsets are virtual offsets (see `GzipReader::seek_to`). Other gzip
+/// compressed files can't be indexed.
 pub fn index_chroms(file: File) -> io::Result<Vec<(u64, String)>> {
-    let mut file = BufReader::new(file);
-
-    let mut line = String::new();
-
-    file.read_line(&mut line)?;
+    match GzipReader::new(file)? {
+        GzipReader::Plain(file) => index_uncompressed(file),
+        GzipReader::Bgzf(file) => index_bgzf(file),
+        GzipReader::Gzip(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Only BGZF compressed files (such as those written by `bgzip`) can be indexed.",
+        )),
+    }
+}
 
-    if line.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Empty file".to_string(),
-        ));
+// Returns `None` at the end of the file or for header lines
+fn parse_line(s: &str) -> Result<Option<String>, io::Error> {
+    if is_header_line(s) {
+        return Ok(None);
     }
+    let mut split = s.splitn(4, '\t');
+    let chrom = split
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty file".to_string()))?;
+    let s = split.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Missing start: {:}", s))
+    })?;
+    let _start = s.parse::<u32>().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Invalid start: {:}", s))
+    })?;
+    let s = split.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Missing end: {:}", s))
+    })?;
+    let _end = s
+        .parse::<u32>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid end: {:}", s)))?;
 
-    let mut chroms = IndexList::new();
+    Ok(Some(chrom.to_string()))
+}
 
-    fn parse_line(s: &str) -> Result<Option<String>, io::Error> {
-        if s.is_empty() {
+// Reads lines until the next data line, returning its offset and chrom
+fn next_data_line<R: BufRead>(
+    file: &mut R,
+    line: &mut String,
+    tell: fn(&mut R) -> io::Result<u64>,
+) -> Result<Option<(u64, String)>, io::Error> {
+    loop {
+        line.clear();
+        let tell = tell(file)?;
+        if file.read_line(line)? == 0 {
             return Ok(None);
         }
-        let mut split = s.splitn(4, '\t');
-        let chrom = split
-            .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty file".to_string()))?;
-        let s = split.next().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("Missing start: {:}", s))
-        })?;
-        let _start = s.parse::<u32>().map_err(|_| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid start: {:}", s))
-        })?;
-        let s = split.next().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("Missing end: {:}", s))
-        })?;
-        let _end = s.parse::<u32>().map_err(|_| {
-            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid end: {:}", s))
-        })?;
-
-        Ok(Some(chrom.to_string()))
+        if let Some(chrom) = parse_line(line)? {
+            return Ok(Some((tell, chrom)));
+        }
     }
+}
+
+fn empty_file() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Empty file".to_string())
+}
 
-    let chrom = parse_line(&line)?.unwrap();
-    let first = chroms.push_front((0, chrom));
+fn index_uncompressed(mut file: BufReader<File>) -> io::Result<Vec<(u64, String)>> {
+    let mut line = String::new();
+
+    let mut chroms = IndexList::new();
+
+    let first = next_data_line(&mut file, &mut line, Tell::tell)?.ok_or_else(empty_file)?;
+    let first = chroms.push_front(first);
     let file_size = file.seek(SeekFrom::End(0))?;
 
     fn do_index(
@@ -67,15 +97,15 @@ pub fn index_chroms(file: File) -> io::Result<Vec<(u64, String)>> {
 
         let next_tell = next.map(|next| chroms[next].0).unwrap_or(file_size);
         let mid = (next_tell + chroms[prev].0) / 2;
-        file.seek(SeekFrom::Start(mid))?;
-        file.read_line(line)?;
-        line.clear();
-        let tell = file.tell()?;
+        // Skip to the start of the next line. Seeking to the byte before `mid`
+        // (which is still after `prev`) means that a line starting exactly at
+        // `mid` isn't skipped.
+        file.seek(SeekFrom::Start(mid - 1))?;
         file.read_line(line)?;
-        let chrom = parse_line(&*line)?;
-        if chrom.is_none() {
-            return Ok(());
-        }
+        let (tell, chrom) = match next_data_line(file, line, Tell::tell)? {
+            Some(next) => next,
+            None => return Ok(()),
+        };
 
         // There are three options:
         // 1) The chrom is the same as the previous one. We need to index
@@ -96,7 +126,7 @@ pub fn index_chroms(file: File) -> io::Result<Vec<(u64, String)>> {
         //    to continue to index between the previous and current as well as
         //    between the current and next.
 
-        let curr = chroms.insert_after(prev, (tell, chrom.unwrap())).unwrap();
+        let curr = chroms.insert_after(prev, (tell, chrom)).unwrap();
 
         if chroms[curr].1 != chroms[prev].1 && tell < next_tell {
             do_index(file_size, file, chroms, line, prev, Some(curr), limit - 1)?;
@@ -125,6 +155,109 @@ pub fn index_chroms(file: File) -> io::Result<Vec<(u64, String)>> {
     Ok(chroms)
 }
 
+fn index_bgzf(mut file: BgzfReader<BufReader<File>>) -> io::Result<Vec<(u64, String)>> {
+    let blocks = file.block_offsets()?;
+
+    let mut line = String::new();
+
+    let mut chroms = IndexList::new();
+
+    let tell: fn(&mut BgzfReader<BufReader<File>>) -> io::Result<u64> =
+        |file| Ok(file.virtual_offset());
+    let first = next_data_line(&mut file, &mut line, tell)?.ok_or_else(empty_file)?;
+    let first = chroms.push_front(first);
+
+    // This is the same as for uncompressed files, except that offsets are
+    // virtual offsets, and we can only seek to the start of a block. So,
+    // once there are no blocks between two indexed lines, the lines between
+    // them are read instead.
+    #[allow(clippy::too_many_arguments)]
+    fn do_index(
+        blocks: &[u64],
+        file: &mut BgzfReader<BufReader<File>>,
+        tell: fn(&mut BgzfReader<BufReader<File>>) -> io::Result<u64>,
+        chroms: &mut IndexList<(u64, String)>,
+        line: &mut String,
+        prev: Index<(u64, String)>,
+        next: Option<Index<(u64, String)>>,
+        limit: usize,
+    ) -> Result<(), io::Error> {
+        if limit == 0 {
+            panic!("Recursive depth limit reached");
+        }
+
+        // The index of the block containing a virtual offset
+        let block = |offset: u64| blocks.partition_point(|&b| b <= offset >> 16) - 1;
+        let prev_block = block(chroms[prev].0);
+        let next_tell = next.map(|next| chroms[next].0);
+        let next_block = next_tell.map(block).unwrap_or(blocks.len());
+
+        if next_block - prev_block < 2 {
+            file.seek_virtual(chroms[prev].0)?;
+            let mut last = prev;
+            while let Some((tell, chrom)) = next_data_line(file, line, tell)? {
+                if next_tell
+                    .map(|next_tell| tell >= next_tell)
+                    .unwrap_or(false)
+                {
+                    break;
+                }
+                if chrom != chroms[last].1 {
+                    last = chroms.insert_after(last, (tell, chrom)).unwrap();
+                }
+            }
+            return Ok(());
+        }
+
+        file.seek_virtual(blocks[(prev_block + next_block) / 2] << 16)?;
+        file.read_line(line)?;
+        let (curr_tell, chrom) = match next_data_line(file, line, tell)? {
+            Some(next) => next,
+            None => return Ok(()),
+        };
+
+        let curr = chroms.insert_after(prev, (curr_tell, chrom)).unwrap();
+
+        let before_next = next_tell
+            .map(|next_tell| curr_tell < next_tell)
+            .unwrap_or(true);
+        if chroms[curr].1 != chroms[prev].1 && before_next {
+            do_index(
+                blocks,
+                file,
+                tell,
+                chroms,
+                line,
+                prev,
+                Some(curr),
+                limit - 1,
+            )?;
+        }
+
+        if before_next {
+            do_index(blocks, file, tell, chroms, line, curr, next, limit - 1)?;
+        }
+
+        Ok(())
+    }
+
+    do_index(
+        &blocks,
+        &mut file,
+        tell,
+        &mut chroms,
+        &mut line,
+        first,
+        None,
+        100,
+    )?;
+
+    let mut chroms: Vec<_> = chroms.into_iter().collect();
+    chroms.dedup_by_key(|index| index.1.clone());
+
+    Ok(chroms)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,4 +302,73 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_index_with_header() -> io::Result<()> {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("resources/test");
+        dir.push("header.bedGraph");
+
+        let f = File::open(dir)?;
+        let indexed_chroms = index_chroms(f)?;
+        let chroms: Vec<&str> = indexed_chroms.iter().map(|c| c.1.as_str()).collect();
+        assert_eq!(chroms, ["chr17", "chr18", "chr19"]);
+        // The first chrom starts after the header lines
+        assert_eq!(indexed_chroms[0].0, 84);
+
+        Ok(())
+    }
+    #[test]
+    fn test_index_line_at_mid() -> io::Result<()> {
+        use std::io::Write;
+
+        // The first line of chr2 starts exactly halfway between the first
+        // line of chr1 and the first indexed line of chr2
+        let mut bedgraph = tempfile::NamedTempFile::new()?;
+        write!(
+            bedgraph,
+            "chr1\t0\t10\t0.5\nchr2\t0\t10\t0.5\nchr2\t20\t30\t0.5\n"
+        )?;
+
+        let indexed_chroms = index_chroms(File::open(bedgraph.path())?)?;
+        assert_eq!(
+            indexed_chroms,
+            [(0, "chr1".to_string()), (14, "chr2".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_bgzf() -> io::Result<()> {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("resources/test");
+
+        let uncompressed = index_chroms(File::open(dir.join("multi_chrom.bedGraph"))?)?;
+        let mut uncompressed_file = BufReader::new(File::open(dir.join("multi_chrom.bedGraph"))?);
+        let indexed_chroms = index_chroms(File::open(dir.join("multi_chrom.bedGraph.bgz"))?)?;
+        assert_eq!(indexed_chroms.len(), uncompressed.len());
+
+        // The lines at the virtual offsets are the same as those at the
+        // uncompressed offsets
+        let mut file = GzipReader::new(File::open(dir.join("multi_chrom.bedGraph.bgz"))?)?;
+        for ((offset, chrom), (uncompressed_offset, uncompressed_chrom)) in
+            indexed_chroms.iter().zip(uncompressed.iter())
+        {
+            assert_eq!(chrom, uncompressed_chrom);
+            file.seek_to(*offset)?;
+            let mut line = String::new();
+            file.read_line(&mut line)?;
+            uncompressed_file.seek(SeekFrom::Start(*uncompressed_offset))?;
+            let mut uncompressed_line = String::new();
+            uncompressed_file.read_line(&mut uncompressed_line)?;
+            assert_eq!(line, uncompressed_line);
+        }
+
+        // Other gzip files can't be indexed
+        let gzip = index_chroms(File::open(dir.join("multi_chrom.bedGraph.gz"))?);
+        assert_eq!(gzip.unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        Ok(())
+    }
 }
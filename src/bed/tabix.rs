@@ -0,0 +1,416 @@
+/*!
+Utilities for reading tabix (`.tbi`) and CSI (`.csi`) indices of BGZF compressed
+bed-like files, as written by `tabix`.
+
+The index gives the virtual offset where each chromosome starts (so a file can
+be split by chromosome without reading it, see
+[`BedParserParallelStreamingIterator::from_tabix`][crate::bedchromdata::BedParserParallelStreamingIterator::from_tabix]),
+and allows reading only the lines that overlap a region (see [`TabixReader`]).
+*/
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::utils::file::bgzf::BgzfReader;
+
+const TBI_MAGIC: [u8; 4] = *b"TBI\x01";
+const CSI_MAGIC: [u8; 4] = *b"CSI\x01";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The binned index of a single chromosome
+#[derive(Clone, Debug)]
+struct TabixRef {
+    name: String,
+    bins: HashMap<u32, Vec<(u64, u64)>>,
+    // For tbi indices, the smallest virtual offset of a line overlapping
+    // each 16kb window
+    linear: Vec<u64>,
+}
+
+/// A tabix or CSI index
+#[derive(Clone, Debug)]
+pub struct TabixIndex {
+    /// The (1-based) column of the chromosome
+    pub col_seq: usize,
+    /// The (1-based) column of the start
+    pub col_beg: usize,
+    /// The (1-based) column of the end, or `0` if there is none
+    pub col_end: usize,
+    /// Lines starting with this are skipped
+    pub meta: char,
+    /// The number of lines at the start of the file that are skipped
+    pub skip: usize,
+    /// Whether starts are 0-based (as for bed files), rather than 1-based
+    pub zero_based: bool,
+    min_shift: u32,
+    depth: u32,
+    refs: Vec<TabixRef>,
+}
+
+impl TabixIndex {
+    /// Reads a (BGZF compressed) tabix or CSI index.
+    pub fn read<R: Read>(index: R) -> io::Result<Self> {
+        let mut index = BgzfReader::new(index);
+        let mut magic = [0; 4];
+        index.read_exact(&mut magic)?;
+        match magic {
+            TBI_MAGIC => {
+                let n_ref = index.read_i32::<LittleEndian>()?;
+                let mut tabix = TabixIndex::read_header(&mut index, 14, 5)?;
+                let names = tabix.refs.split_off(0);
+                if names.len() != n_ref as usize {
+                    return Err(invalid("Invalid tabix index: wrong number of names"));
+                }
+                for TabixRef { name, .. } in names {
+                    let bins = read_bins(&mut index, false)?;
+                    let n_intv = index.read_i32::<LittleEndian>()?;
+                    let linear = (0..n_intv)
+                        .map(|_| index.read_u64::<LittleEndian>())
+                        .collect::<io::Result<_>>()?;
+                    tabix.refs.push(TabixRef { name, bins, linear });
+                }
+                Ok(tabix)
+            }
+            CSI_MAGIC => {
+                let min_shift = index.read_i32::<LittleEndian>()? as u32;
+                let depth = index.read_i32::<LittleEndian>()? as u32;
+                let l_aux = index.read_i32::<LittleEndian>()?;
+                if l_aux == 0 {
+                    return Err(invalid(
+                        "CSI index has no chromosome names (it may not be a tabix index)",
+                    ));
+                }
+                let mut aux = vec![0; l_aux as usize];
+                index.read_exact(&mut aux)?;
+                let mut tabix = TabixIndex::read_header(&mut &aux[..], min_shift, depth)?;
+                let names = tabix.refs.split_off(0);
+                let n_ref = index.read_i32::<LittleEndian>()?;
+                if names.len() != n_ref as usize {
+                    return Err(invalid("Invalid CSI index: wrong number of names"));
+                }
+                for TabixRef { name, .. } in names {
+                    let bins = read_bins(&mut index, true)?;
+                    tabix.refs.push(TabixRef {
+                        name,
+                        bins,
+                        linear: vec![],
+                    });
+                }
+                Ok(tabix)
+            }
+            _ => Err(invalid("Invalid magic (likely not a tabix or CSI index)")),
+        }
+    }
+
+    /// Opens the index of a BGZF file at `path`: `<path>.tbi` or, if that
+    /// doesn't exist, `<path>.csi`. Returns `None` if neither exists.
+    pub fn open_for(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let path = path.as_ref().as_os_str();
+        for ext in [".tbi", ".csi"] {
+            let mut index_path = path.to_owned();
+            index_path.push(ext);
+            match File::open(&index_path) {
+                Ok(file) => return TabixIndex::read(BufReader::new(file)).map(Some),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    // Reads the tabix header (shared by tbi indices and the aux data of CSI
+    // indices). The names are returned as refs with no bins.
+    fn read_header(mut header: impl Read, min_shift: u32, depth: u32) -> io::Result<Self> {
+        let format = header.read_i32::<LittleEndian>()?;
+        let col_seq = header.read_i32::<LittleEndian>()?;
+        let col_beg = header.read_i32::<LittleEndian>()?;
+        let col_end = header.read_i32::<LittleEndian>()?;
+        let meta = header.read_i32::<LittleEndian>()?;
+        let skip = header.read_i32::<LittleEndian>()?;
+        let l_nm = header.read_i32::<LittleEndian>()?;
+        let mut names = vec![0; l_nm as usize];
+        header.read_exact(&mut names)?;
+        if col_seq < 1 || col_beg < 1 || col_end < 0 || skip < 0 {
+            return Err(invalid("Invalid tabix columns"));
+        }
+        let refs = names
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let name = String::from_utf8(name.to_vec())
+                    .map_err(|_| invalid("Invalid chromosome name in tabix index"))?;
+                Ok(TabixRef {
+                    name,
+                    bins: HashMap::new(),
+                    linear: vec![],
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(TabixIndex {
+            col_seq: col_seq as usize,
+            col_beg: col_beg as usize,
+            col_end: col_end as usize,
+            meta: char::from(meta as u8),
+            skip: skip as usize,
+            zero_based: format & 0x10000 != 0,
+            min_shift,
+            depth,
+            refs,
+        })
+    }
+
+    // The bin holding metadata (not lines) for each chromosome
+    fn pseudo_bin(&self) -> u32 {
+        (((1u64 << (3 * self.depth + 3)) - 1) / 7 + 1) as u32
+    }
+
+    /// The chromosomes in the index, in the order they are in the file
+    pub fn chroms(&self) -> impl Iterator<Item = &str> {
+        self.refs.iter().map(|r| r.name.as_str())
+    }
+
+    /// The virtual offset of the first line of each chromosome, in the order
+    /// they are in the file. This can be used in place of
+    /// [`index_chroms`][crate::bed::indexer::index_chroms].
+    pub fn chrom_offsets(&self) -> Vec<(u64, String)> {
+        let pseudo_bin = self.pseudo_bin();
+        let mut offsets: Vec<(u64, String)> = self
+            .refs
+            .iter()
+            .filter_map(|r| {
+                let start = r
+                    .bins
+                    .iter()
+                    .filter(|(bin, _)| **bin != pseudo_bin)
+                    .flat_map(|(_, chunks)| chunks.iter().map(|c| c.0))
+                    .min()?;
+                Some((start, r.name.clone()))
+            })
+            .collect();
+        offsets.sort();
+        offsets
+    }
+
+    /// The sorted, merged chunks (pairs of virtual offsets) of the file that
+    /// contain the lines overlapping `chrom:start-end` (0-based, half-open).
+    /// These may also contain other lines.
+    pub fn query_chunks(&self, chrom: &str, start: u32, end: u32) -> Vec<(u64, u64)> {
+        let r = match self.refs.iter().find(|r| r.name == chrom) {
+            Some(r) => r,
+            None => return vec![],
+        };
+        if end <= start {
+            return vec![];
+        }
+        let window = (start >> self.min_shift) as usize;
+        let min_offset = match r.linear.len() {
+            0 => 0,
+            n => r.linear[window.min(n - 1)],
+        };
+        let mut chunks: Vec<(u64, u64)> = reg2bins(start, end, self.min_shift, self.depth)
+            .filter_map(|bin| r.bins.get(&bin))
+            .flatten()
+            .filter(|chunk| chunk.1 > min_offset)
+            .copied()
+            .collect();
+        chunks.sort();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(chunks.len());
+        for (chunk_start, chunk_end) in chunks {
+            match merged.last_mut() {
+                Some(last) if chunk_start <= last.1 => last.1 = last.1.max(chunk_end),
+                _ => merged.push((chunk_start, chunk_end)),
+            }
+        }
+        merged
+    }
+
+    /// The chromosome and (0-based, half-open) region of a line, using the
+    /// columns of the index. Returns `None` for lines that can't be parsed.
+    pub fn line_region<'a>(&self, line: &'a str) -> Option<(&'a str, u32, u32)> {
+        let columns: Vec<&str> = line
+            .trim_end()
+            .split('\t')
+            .take(self.col_seq.max(self.col_beg).max(self.col_end))
+            .collect();
+        let chrom = *columns.get(self.col_seq - 1)?;
+        let mut start = columns.get(self.col_beg - 1)?.parse::<u32>().ok()?;
+        if !self.zero_based {
+            start = start.checked_sub(1)?;
+        }
+        let end = match self.col_end {
+            0 => start + 1,
+            col_end => columns.get(col_end - 1)?.parse::<u32>().ok()?,
+        };
+        Some((chrom, start, end))
+    }
+}
+
+// Reads the bins (and their chunks) of a chromosome. CSI bins also have a
+// (unused) linear offset.
+fn read_bins(index: &mut impl Read, csi: bool) -> io::Result<HashMap<u32, Vec<(u64, u64)>>> {
+    let n_bin = index.read_i32::<LittleEndian>()?;
+    let mut bins = HashMap::with_capacity(n_bin.max(0) as usize);
+    for _ in 0..n_bin {
+        let bin = index.read_u32::<LittleEndian>()?;
+        if csi {
+            let _loffset = index.read_u64::<LittleEndian>()?;
+        }
+        let n_chunk = index.read_i32::<LittleEndian>()?;
+        let chunks = (0..n_chunk)
+            .map(|_| {
+                Ok((
+                    index.read_u64::<LittleEndian>()?,
+                    index.read_u64::<LittleEndian>()?,
+                ))
+            })
+            .collect::<io::Result<_>>()?;
+        bins.insert(bin, chunks);
+    }
+    Ok(bins)
+}
+
+// The bins that may contain lines overlapping `start..end`
+fn reg2bins(start: u32, end: u32, min_shift: u32, depth: u32) -> impl Iterator<Item = u32> {
+    let (start, end) = (u64::from(start), u64::from(end) - 1);
+    (0..=depth).flat_map(move |level| {
+        let shift = min_shift + 3 * (depth - level);
+        let first = ((1u64 << (3 * level)) - 1) / 7;
+        ((first + (start >> shift))..=(first + (end >> shift))).map(|bin| bin as u32)
+    })
+}
+
+/// Reads the lines of a BGZF compressed file that overlap a region, using its
+/// tabix or CSI index.
+pub struct TabixReader<R> {
+    pub index: TabixIndex,
+    file: BgzfReader<R>,
+}
+
+impl TabixReader<BufReader<File>> {
+    /// Opens a BGZF file and its index (see [`TabixIndex::open_for`]).
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let index = TabixIndex::open_for(&path)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No tabix (.tbi) or CSI (.csi) index was found.",
+            )
+        })?;
+        let file = BufReader::new(File::open(path)?);
+        Ok(TabixReader::new(file, index))
+    }
+}
+
+impl<R: Read + Seek> TabixReader<R> {
+    pub fn new(file: R, index: TabixIndex) -> Self {
+        TabixReader {
+            index,
+            file: BgzfReader::new(file),
+        }
+    }
+
+    /// Returns the lines that overlap `chrom:start-end` (0-based, half-open).
+    /// Lines are in the order they are in the file, and don't include the
+    /// trailing newline.
+    pub fn query(&mut self, chrom: &str, start: u32, end: u32) -> io::Result<Vec<String>> {
+        let mut lines = vec![];
+        let mut line = String::new();
+        for (chunk_start, chunk_end) in self.index.query_chunks(chrom, start, end) {
+            self.file.seek_virtual(chunk_start)?;
+            while self.file.virtual_offset() < chunk_end {
+                line.clear();
+                if self.file.read_line(&mut line)? == 0 {
+                    break;
+                }
+                if line.starts_with(self.index.meta) {
+                    continue;
+                }
+                let overlaps = match self.index.line_region(&line) {
+                    Some((line_chrom, line_start, line_end)) => {
+                        line_chrom == chrom
+                            && line_start < end
+                            && (line_end > start || (line_start == line_end && line_start >= start))
+                    }
+                    None => false,
+                };
+                if overlaps {
+                    lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+                }
+            }
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_reg2bins() {
+        // The bins of tabix indices, as in the SAM spec
+        let bins: Vec<u32> = reg2bins(0, 1, 14, 5).collect();
+        assert_eq!(bins, [0, 1, 9, 73, 585, 4681]);
+        let bins: Vec<u32> = reg2bins(16384, 16385, 14, 5).collect();
+        assert_eq!(bins, [0, 1, 9, 73, 585, 4682]);
+        let bins: Vec<u32> = reg2bins(0, 16385, 14, 5).collect();
+        assert_eq!(bins, [0, 1, 9, 73, 585, 4681, 4682]);
+    }
+
+    #[test]
+    fn test_tabix() -> io::Result<()> {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("resources/test");
+        let bgzf = dir.join("multi_chrom.bedGraph.bgz");
+
+        let index = TabixIndex::open_for(&bgzf)?.unwrap();
+        assert!(index.zero_based);
+        assert_eq!(
+            index.chroms().collect::<Vec<_>>(),
+            ["chr1", "chr2", "chr3", "chr4", "chr5", "chr6"]
+        );
+        let offsets = index.chrom_offsets();
+        let indexed = crate::bed::indexer::index_chroms(File::open(&bgzf)?)?;
+        assert_eq!(
+            offsets.iter().map(|o| &o.1).collect::<Vec<_>>(),
+            indexed.iter().map(|o| &o.1).collect::<Vec<_>>()
+        );
+
+        // Compare queries against the lines of the uncompressed file
+        let uncompressed = std::fs::read_to_string(dir.join("multi_chrom.bedGraph"))?;
+        let mut reader = TabixReader::open(&bgzf)?;
+        for (chrom, start, end) in [
+            ("chr1", 0, 1_000_000),
+            ("chr2", 5_000_000, 60_000_000),
+            ("chr6", 170_000_000, 170_805_979),
+            ("chr3", 1, 2),
+            ("chrX", 0, 100),
+        ] {
+            let expected: Vec<&str> = uncompressed
+                .lines()
+                .filter(|line| {
+                    let (line_chrom, line_start, line_end) = index.line_region(line).unwrap();
+                    line_chrom == chrom && line_start < end && line_end > start
+                })
+                .collect();
+            assert_eq!(reader.query(chrom, start, end)?, expected);
+        }
+
+        // The same file, with a CSI index
+        let csi = TabixIndex::read(File::open(dir.join("multi_chrom.bedGraph.bgz.csi"))?)?;
+        assert_eq!(csi.chrom_offsets(), offsets);
+        let mut reader = TabixReader::new(File::open(&bgzf)?, csi);
+        assert_eq!(
+            reader.query("chr2", 5_000_000, 60_000_000)?,
+            TabixReader::open(&bgzf)?.query("chr2", 5_000_000, 60_000_000)?
+        );
+
+        Ok(())
+    }
+}
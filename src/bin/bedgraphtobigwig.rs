@@ -1,32 +1,38 @@
-use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use bigtools::bed::indexer::index_chroms;
+use bigtools::bed::tabix::TabixIndex;
 use bigtools::bedchromdata::{BedParserParallelStreamingIterator, BedParserStreamingIterator};
-use bigtools::utils::cli::BBIWriteArgs;
+use bigtools::utils::chromsizes::{open_chrom_sizes, ChromSizesFormat};
+use bigtools::utils::cli::{BBIWriteArgs, DroppedChroms};
+use bigtools::utils::file::gzip::{self, GzipReader};
 use clap::Parser;
 
 use bigtools::bed::bedparser::{parse_bedgraph, BedParser};
-use bigtools::{BigWigWrite, InputSortType};
+use bigtools::{BigWigWrite, InputSortType, OverlapPolicy};
 
 #[derive(Parser)]
 #[command(about = "Converts an input bedGraph to a bigWig. Can be multi-threaded for substantial speedups. Note that ~11 temporary files are created/maintained.", long_about = None)]
 struct Cli {
     /// The bedgraph to convert to a bigwig. Can use `-` or `stdin` to read from stdin.
+    /// May be gzip compressed. For BGZF compressed files, a tabix (`.tbi`) or CSI (`.csi`)
+    /// index next to the file is used when reading in parallel.
     bedgraph: String,
 
     /// A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace.
+    /// A FASTA index (`.fai`), a `.2bit` file, or an existing bigWig or bigBed can also be used.
     chromsizes: String,
 
     /// The output bigwig path
     output: String,
 
     /// Set whether to read and convert the bedGraph in parallel.
-    /// Can take `auto` (default), `yes`, `no`. Ignored when input is stdin or when nthreads is `1`.
+    /// Can take `auto` (default), `yes`, `no`. Ignored when input is stdin, when nthreads is `1`,
+    /// or when the input is gzip compressed but not with BGZF (e.g. by `bgzip`).
     #[arg(short = 'p', long)]
     #[arg(default_value = "auto")]
     pub parallel: String,
@@ -37,6 +43,22 @@ struct Cli {
     #[arg(default_value_t = false)]
     single_pass: bool,
 
+    /// How to handle overlapping values within a chromosome. Can take `error` (default), `sum`,
+    /// `max`, `mean`, or `last`. Overlapping values are split where they overlap, and bases
+    /// that resolve to zero aren't written.
+    #[arg(long)]
+    #[arg(default_value = "error")]
+    #[arg(value_parser = ["error", "sum", "max", "mean", "last"])]
+    overlaps: String,
+
+    /// The chromosome sizes are in a `.2bit` file. Not needed, since the format is detected.
+    #[arg(long, hide = true)]
+    sizes_is_2bit: bool,
+
+    /// The chromosome sizes are in a bigWig or bigBed. Not needed, since the format is detected.
+    #[arg(long, hide = true)]
+    sizes_is_bb: bool,
+
     #[command(flatten)]
     write_args: BBIWriteArgs,
 }
@@ -47,7 +69,9 @@ fn main() -> Result<(), Box<dyn Error>> {
             replace:
                 "-unc", "--uncompressed";
                 "-blockSize", "--block-size";
-                "-itemsPerSlot", "--items-per-slot"
+                "-itemsPerSlot", "--items-per-slot";
+                "-sizesIs2Bit", "--sizes-is-2bit";
+                "-sizesIsBb", "--sizes-is-bb"
             ignore:
             unimplemented:
         )
@@ -76,24 +100,28 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut outb = BigWigWrite::create_file(bigwigpath);
     outb.options.max_zooms = matches.write_args.nzooms;
-    outb.options.compress = !matches.write_args.uncompressed;
+    outb.options.compression = matches.write_args.compression();
     outb.options.input_sort_type = input_sort_type;
     outb.options.block_size = matches.write_args.block_size;
-    let chrom_map: HashMap<String, u32> = BufReader::new(File::open(chrom_map)?)
-        .lines()
-        .filter(|l| match l {
-            Ok(s) => !s.is_empty(),
-            _ => true,
-        })
-        .map(|l| {
-            let words = l.expect("Split error");
-            let mut split = words.split_whitespace();
-            (
-                split.next().expect("Missing chrom").to_owned(),
-                split.next().expect("Missing size").parse::<u32>().unwrap(),
-            )
-        })
-        .collect();
+    outb.options.unknown_chroms = matches.write_args.unknown_chroms();
+    outb.options.overlap_policy = match matches.overlaps.as_ref() {
+        "sum" => OverlapPolicy::Sum,
+        "max" => OverlapPolicy::Max,
+        "mean" => OverlapPolicy::Mean,
+        "last" => OverlapPolicy::Last,
+        _ => OverlapPolicy::Error,
+    };
+    outb.chrom_aliases = matches.write_args.chrom_aliases()?;
+    let dropped = Arc::new(DroppedChroms::default());
+    outb.progress = Some(dropped.clone());
+    let sizes_format = if matches.sizes_is_2bit {
+        Some(ChromSizesFormat::TwoBit)
+    } else if matches.sizes_is_bb {
+        Some(ChromSizesFormat::BBI)
+    } else {
+        None
+    };
+    let chrom_map = open_chrom_sizes(&chrom_map, sizes_format)?;
 
     let pool = futures::executor::ThreadPoolBuilder::new()
         .pool_size(nthreads)
@@ -102,15 +130,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let allow_out_of_order_chroms = !matches!(outb.options.input_sort_type, InputSortType::ALL);
     if bedgraphpath == "-" || bedgraphpath == "stdin" {
-        let stdin = std::io::stdin().lock();
+        let stdin = GzipReader::new(std::io::stdin().lock())?;
         let vals_iter = BedParser::from_bedgraph_file(stdin);
 
         let chsi = BedParserStreamingIterator::new(vals_iter, allow_out_of_order_chroms);
         outb.write_singlethreaded(chrom_map, chsi, pool)?;
     } else {
-        let infile = File::open(&bedgraphpath)?;
+        let infile = GzipReader::new(File::open(&bedgraphpath)?)?;
+        let file_size = std::fs::metadata(&bedgraphpath)?.len();
         let parallel = match (nthreads, matches.parallel.as_ref()) {
-            (1, _) | (_, "auto") => infile.metadata()?.len() >= 200_000_000,
+            (1, _) | (_, "auto") => file_size >= 200_000_000,
             (_, "yes") => true,
             (_, "no") => false,
             (_, v) => {
@@ -121,8 +150,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                 true
             }
         };
+        // Only uncompressed and BGZF files can be seeked to each chromosome
+        let parallel = parallel && infile.compression() != gzip::Compression::Gzip;
         if parallel {
-            let chrom_indices: Vec<(u64, String)> = index_chroms(infile)?;
+            // BGZF files with a tabix index don't need to be indexed here
+            let tabix = match infile.compression() {
+                gzip::Compression::Bgzf => TabixIndex::open_for(&bedgraphpath)?,
+                _ => None,
+            };
+            let chrom_indices: Vec<(u64, String)> = match tabix {
+                Some(tabix) => tabix.chrom_offsets(),
+                None => index_chroms(File::open(&bedgraphpath)?)?,
+            };
 
             if matches.single_pass {
                 let chsi = BedParserParallelStreamingIterator::new(
@@ -157,7 +196,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             } else {
                 outb.write_multipass(
                     || {
-                        let infile = File::open(&bedgraphpath)?;
+                        let infile = GzipReader::new(File::open(&bedgraphpath)?)?;
                         let vals_iter = BedParser::from_bedgraph_file(infile);
                         let chsi =
                             BedParserStreamingIterator::new(vals_iter, allow_out_of_order_chroms);
@@ -170,6 +209,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
         }
     };
+    dropped.warn();
 
     Ok(())
 }
@@ -1,23 +1,26 @@
-use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::sync::Arc;
 
 use bigtools::bedchromdata::BedParserStreamingIterator;
-use bigtools::utils::cli::BBIWriteArgs;
+use bigtools::utils::chromsizes::{open_chrom_sizes, ChromSizesFormat};
+use bigtools::utils::cli::{BBIWriteArgs, DroppedChroms};
+use bigtools::utils::file::gzip::GzipReader;
 use clap::Parser;
 
+use bigtools::bed::autosql::{self, BedFieldValidator, BedFormat};
 use bigtools::bed::bedparser::BedParser;
-use bigtools::{BigBedWrite, InputSortType};
+use bigtools::{BedEntry, BigBedWrite, InputSortType};
 
 #[derive(Parser)]
 #[command(about = "Converts a bed to a bigBed.", long_about = None)]
 struct Cli {
-    /// The n to convert to a bigbed.
+    /// The n to convert to a bigbed. May be gzip compressed.
     bed: String,
 
     /// A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace.
+    /// A FASTA index (`.fai`), a `.2bit` file, or an existing bigWig or bigBed can also be used.
     chromsizes: String,
 
     /// The output bigwig path
@@ -26,6 +29,21 @@ struct Cli {
     #[arg(short = 'a', long)]
     autosql: Option<String>,
 
+    /// The format of the bed. Either a built-in format (`narrowPeak`, `broadPeak`, `gappedPeak`,
+    /// `bedMethyl`, `interact`/`bigInteract`, or `bigGenePred`), whose autosql is used unless
+    /// `--autosql` is set, or a bed type like `bed6+4`. If set, every entry is checked to have
+    /// the expected number of columns, with values matching their types.
+    #[arg(long)]
+    format: Option<String>,
+
+    /// The chromosome sizes are in a `.2bit` file. Not needed, since the format is detected.
+    #[arg(long, hide = true)]
+    sizes_is_2bit: bool,
+
+    /// The chromosome sizes are in a bigWig or bigBed. Not needed, since the format is detected.
+    #[arg(long, hide = true)]
+    sizes_is_bb: bool,
+
     #[command(flatten)]
     write_args: BBIWriteArgs,
 }
@@ -37,15 +55,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 "-unc", "--uncompressed";
                 "-blockSize", "--block-size";
                 "-itemsPerSlot", "--items-per-slot";
-                "-as", "-autosql"
+                "-as", "--autosql";
+                "-type", "--format";
+                "-sizesIs2Bit", "--sizes-is-2bit";
+                "-sizesIsBb", "--sizes-is-bb"
             ignore:
-                "-tab";
-                "-type"
+                "-tab"
             unimplemented:
                 "-extraIndex";
-                "-sizesIs2Bit";
                 "-sizesIsChromAliasBb";
-                "-sizesIsBb";
                 "-allow1bOverlap";
                 "-extraIndex";
                 "-udcDir"
@@ -75,46 +93,118 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut outb = BigBedWrite::create_file(bigwigpath);
     outb.options.max_zooms = matches.write_args.nzooms;
-    outb.options.compress = !matches.write_args.uncompressed;
+    outb.options.compression = matches.write_args.compression();
     outb.options.input_sort_type = input_sort_type;
-    let chrom_map: HashMap<String, u32> = BufReader::new(File::open(chrom_map)?)
-        .lines()
-        .filter(|l| match l {
-            Ok(s) => !s.is_empty(),
-            _ => true,
-        })
-        .map(|l| {
-            let words = l.expect("Split error");
-            let mut split = words.split_whitespace();
-            (
-                split.next().expect("Missing chrom").to_owned(),
-                split.next().expect("Missing size").parse::<u32>().unwrap(),
-            )
-        })
-        .collect();
+    outb.options.unknown_chroms = matches.write_args.unknown_chroms();
+    outb.chrom_aliases = matches.write_args.chrom_aliases()?;
+    let dropped = Arc::new(DroppedChroms::default());
+    outb.progress = Some(dropped.clone());
+    let sizes_format = if matches.sizes_is_2bit {
+        Some(ChromSizesFormat::TwoBit)
+    } else if matches.sizes_is_bb {
+        Some(ChromSizesFormat::BBI)
+    } else {
+        None
+    };
+    let chrom_map = open_chrom_sizes(&chrom_map, sizes_format)?;
 
     let pool = futures::executor::ThreadPoolBuilder::new()
         .pool_size(nthreads)
         .create()
         .expect("Unable to create thread pool.");
 
-    let infile = File::open(bedpath)?;
-    let mut vals_iter = BedParser::from_bed_file(infile);
+    let format = matches
+        .format
+        .as_deref()
+        .map(|f| (f, BedFormat::from_name(f)));
+    let autosql = match (matches.autosql.as_ref(), format) {
+        (Some(file), _) => Some(std::fs::read_to_string(file)?),
+        (None, Some((_, Some(preset)))) => Some(preset.autosql().to_string()),
+        _ => None,
+    };
+    let validator = match format {
+        None => None,
+        Some((format, preset)) => {
+            let bed_type = match preset {
+                Some(_) => None,
+                None => match BedFieldValidator::from_bed_type(format) {
+                    Some(bed_type) => Some(bed_type),
+                    None => {
+                        return Err(format!(
+                            "Invalid option for `format`: `{}`. Options are a bed type (like `bed6+4`) or one of: {}.",
+                            format,
+                            BedFormat::ALL.map(|f| f.name()).join(", ")
+                        )
+                        .into());
+                    }
+                },
+            };
+            match (&autosql, bed_type) {
+                (Some(autosql), bed_type) => {
+                    let validator = BedFieldValidator::from_autosql(autosql)?;
+                    if let Some(bed_type) = bed_type {
+                        // A type like `bed6+` allows any number of extra fields
+                        let matches_type = bed_type.bed_fields() == validator.bed_fields()
+                            && (bed_type.field_count().is_none()
+                                || bed_type.field_count() == validator.field_count());
+                        if !matches_type {
+                            return Err(format!(
+                                "The autosql doesn't match the bed type `{}`: it has {} fields, {} of which are standard bed fields.",
+                                format,
+                                validator.field_count().unwrap_or(0),
+                                validator.bed_fields()
+                            )
+                            .into());
+                        }
+                    }
+                    Some(validator)
+                }
+                (None, bed_type) => bed_type,
+            }
+        }
+    };
+
+    let infile = GzipReader::new(File::open(bedpath)?)?;
+    let mut vals_iter = match validator.clone() {
+        Some(validator) => BedParser::from_validated_bed_file(
+            infile,
+            Box::new(move |entry: &BedEntry| validator.validate(entry)),
+        ),
+        None => BedParser::from_bed_file(infile),
+    };
 
-    let autosql = match matches.autosql.as_ref() {
+    let autosql = match autosql {
+        Some(autosql) => autosql,
         None => {
             use bigtools::utils::chromvalues::ChromValues;
-            let (_, mut group) = vals_iter.next_chrom().unwrap().unwrap();
-            let first = group.peek().unwrap().unwrap();
-            bigtools::bed::autosql::bed_autosql(&first.rest)
+            let rest = {
+                let (_, mut group) = match vals_iter.next_chrom() {
+                    Some(first) => first?,
+                    None => return Err("The input bed is empty.".into()),
+                };
+                let first = match group.peek() {
+                    Some(Ok(first)) => first,
+                    Some(Err(e)) => return Err(e.to_string().into()),
+                    None => return Err("The input bed is empty.".into()),
+                };
+                first.rest.clone()
+            };
+            // Name the columns from a `#` header line, if there is one
+            match (validator, vals_iter.header().and_then(|h| h.column_names())) {
+                (Some(bed_type), _) => {
+                    autosql::bed_autosql_with_bed_fields(&rest, bed_type.bed_fields())
+                }
+                (None, Some(names)) => autosql::bed_autosql_with_names(&rest, &names),
+                (None, None) => autosql::bed_autosql(&rest),
+            }
         }
-        Some(file) => std::fs::read_to_string(file)?,
     };
     outb.autosql = Some(autosql);
 
     let allow_out_of_order_chroms = !matches!(outb.options.input_sort_type, InputSortType::ALL);
     let chsi = BedParserStreamingIterator::new(vals_iter, allow_out_of_order_chroms);
     outb.write(chrom_map, chsi, pool)?;
+    dropped.warn();
 
     Ok(())
 }
@@ -1,11 +1,14 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Write};
 
-use bigtools::{BBIRead, BigWigRead, BigWigReadOpenError};
-use clap::{Arg, Command};
+use bigtools::utils::rename::rename_chroms;
+use bigtools::utils::subset::{subset_bigbed, subset_bigwig, SubsetRegions};
+use bigtools::{BBIRead, BigBedWrite, BigWigRead, BigWigReadOpenError, BigWigWrite};
+use clap::{Arg, ArgAction, Command};
 
-use bigtools::utils::reopen::SeekableRead;
+use bigtools::utils::reopen::{ReopenableFile, SeekableRead};
 use bigtools::utils::streaming_linereader::StreamingLineReader;
 use bigtools::{BigBedRead, BigBedReadOpenError};
 
@@ -147,7 +150,93 @@ fn chromintersect(apath: String, bpath: String, outpath: String) -> io::Result<(
     Ok(())
 }
 
-fn main() -> Result<(), BigBedReadOpenError> {
+fn subset(
+    inpath: String,
+    outpath: String,
+    chroms: Vec<String>,
+    regionspath: Option<String>,
+    nthreads: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut regions = SubsetRegions {
+        chroms,
+        regions: vec![],
+    };
+    if let Some(regionspath) = regionspath {
+        let bedin = File::open(regionspath)?;
+        let mut bedstream = StreamingLineReader::new(BufReader::with_capacity(64 * 1024, bedin));
+        while let Some(line) = bedstream.read() {
+            let line = line?.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut split = line.splitn(4, '\t');
+            let (chrom, start, end) = match (split.next(), split.next(), split.next()) {
+                (Some(chrom), Some(start), Some(end)) => (chrom, start, end),
+                _ => return Err(format!("Invalid region: {}", line).into()),
+            };
+            let start = start
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid start: {}", line))?;
+            let end = end
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid end: {}", line))?;
+            regions.regions.push((chrom.to_string(), start, end));
+        }
+    }
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+    // Open the file directly, rather than with `open_file`, so that a bigBed
+    // doesn't print an error when it fails to open as a bigWig
+    let file = ReopenableFile {
+        path: inpath.clone(),
+        file: File::open(&inpath)?,
+    };
+    match BigWigRead::open(file) {
+        Ok(bigwig) => {
+            let out = BigWigWrite::create_file(outpath);
+            subset_bigwig(&bigwig, &regions, out, pool, nthreads)?;
+        }
+        Err(BigWigReadOpenError::NotABigWig) => {
+            let mut bigbed = BigBedRead::open_file(&inpath)?;
+            let out = BigBedWrite::create_file(outpath);
+            subset_bigbed(&mut bigbed, &regions, out, pool, nthreads)?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+fn renamechroms(
+    inpath: String,
+    outpath: String,
+    mappingpath: String,
+    hide_unmapped: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mappingin = File::open(mappingpath)?;
+    let mut mappingstream = StreamingLineReader::new(BufReader::new(mappingin));
+    let mut mapping = HashMap::new();
+    while let Some(line) = mappingstream.read() {
+        let line = line?.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut split = line.split_whitespace();
+        match (split.next(), split.next()) {
+            (Some(from), Some(to)) => {
+                mapping.insert(from.to_string(), to.to_string());
+            }
+            _ => return Err(format!("Invalid mapping: {}", line).into()),
+        }
+    }
+
+    rename_chroms(&inpath, &outpath, &mapping, hide_unmapped)?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
     let matches = Command::new("BigTools")
         .subcommand(
             Command::new("intersect")
@@ -192,6 +281,82 @@ fn main() -> Result<(), BigBedReadOpenError> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            Command::new("subset")
+                .about("Create a new bigWig or bigBed with only the data in the given chromosomes and regions")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("The bigWig or bigBed to take data from")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .help("The name of the output file, of the same type as the input")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("chrom")
+                        .short('c')
+                        .long("chrom")
+                        .help("A chromosome to keep entirely. Can be given multiple times, or as a comma-separated list.")
+                        .num_args(1)
+                        .value_delimiter(',')
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("regions")
+                        .short('r')
+                        .long("regions")
+                        .help("A bed file of regions to keep. bigWig values are clipped to the regions; bigBed entries overlapping them are kept whole.")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .long("nthreads")
+                        .help("Set the number of threads to use.")
+                        .num_args(1)
+                        .default_value("6")
+                        .value_parser(clap::value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            Command::new("renamechroms")
+                .about("Create a new bigWig or bigBed with renamed chromosomes, copying the data as-is")
+                .long_about("Create a new bigWig or bigBed with renamed chromosomes, copying the data as-is. A new chromosome tree is appended to a copy of the file, so the old tree also stays in the file, unused.")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .help("The bigWig or bigBed to rename the chromosomes of")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .help("The name of the output file")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("mapping")
+                        .short('m')
+                        .long("mapping")
+                        .help("A file where each line has a chromosome and its new name, separated by whitespace")
+                        .num_args(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("hide-unmapped")
+                        .long("hide-unmapped")
+                        .help("Hide chromosomes that aren't in the mapping, rather than keeping their names. Their data stays in the file and still counts in the total summary, but can't be queried.")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .get_matches();
 
     match matches.subcommand() {
@@ -214,6 +379,26 @@ fn main() -> Result<(), BigBedReadOpenError> {
 
             chromintersect(apath, bpath, outpath)?;
         }
+        Some(("subset", matches)) => {
+            let inpath = matches.get_one::<String>("input").unwrap().to_owned();
+            let outpath = matches.get_one::<String>("out").unwrap().to_owned();
+            let chroms = matches
+                .get_many::<String>("chrom")
+                .map(|c| c.cloned().collect())
+                .unwrap_or_default();
+            let regionspath = matches.get_one::<String>("regions").cloned();
+            let nthreads = *matches.get_one::<usize>("nthreads").unwrap();
+
+            subset(inpath, outpath, chroms, regionspath, nthreads)?;
+        }
+        Some(("renamechroms", matches)) => {
+            let inpath = matches.get_one::<String>("input").unwrap().to_owned();
+            let outpath = matches.get_one::<String>("out").unwrap().to_owned();
+            let mappingpath = matches.get_one::<String>("mapping").unwrap().to_owned();
+            let hide_unmapped = matches.get_flag("hide-unmapped");
+
+            renamechroms(inpath, outpath, mappingpath, hide_unmapped)?;
+        }
         None => {
             eprintln!("No command. Use bigtools -help to see help.");
         }
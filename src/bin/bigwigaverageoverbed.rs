@@ -1,20 +1,65 @@
 use std::collections::VecDeque;
 use std::env;
 use std::error::Error;
+use std::fmt::Write as _;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
 
-use bigtools::bed::bedparser::{parse_bed, BedParser};
+use bigtools::bed::bedparser::{is_header_line, parse_bed, BedParser};
 use bigtools::bed::indexer::index_chroms;
 use bigtools::utils::chromvalues::ChromValues;
+use bigtools::utils::file::gzip::GzipReader;
 use bigtools::utils::reopen::{Reopen, SeekableRead};
 use bigtools::utils::streaming_linereader::StreamingLineReader;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use bigtools::utils::misc::{stats_for_bed_item, Name};
+use bigtools::utils::misc::{stats_for_bed_item, BigWigAverageOverBedEntry, Name, StatsOptions};
 use bigtools::BigWigRead;
 use crossbeam_channel::TryRecvError;
 
+#[derive(Copy, Clone, ValueEnum)]
+enum Stat {
+    /// The size of the bed entry
+    Size,
+    /// The number of bases covered by the bigWig
+    Bases,
+    /// The sum of the values over the covered bases
+    Sum,
+    /// The mean over the bed entry, where uncovered bases are zero
+    Mean0,
+    /// The mean over the covered bases
+    Mean,
+    /// The minimum value over the covered bases
+    Min,
+    /// The maximum value over the covered bases
+    Max,
+    /// The standard deviation over the covered bases
+    Stdev,
+}
+
+fn format_stats(entry: &BigWigAverageOverBedEntry, stats: &[Stat]) -> String {
+    let mut out = String::new();
+    for stat in stats {
+        if !out.is_empty() {
+            out.push('\t');
+        }
+        let _ = match stat {
+            Stat::Size => write!(out, "{}", entry.size),
+            Stat::Bases => write!(out, "{}", entry.bases),
+            Stat::Sum => write!(out, "{:.3}", entry.sum),
+            Stat::Mean0 => write!(out, "{:.3}", entry.mean0),
+            Stat::Mean => write!(out, "{:.3}", entry.mean),
+            Stat::Min => write!(out, "{:.3}", entry.min),
+            Stat::Max => write!(out, "{:.3}", entry.max),
+            Stat::Stdev => write!(out, "{:.3}", entry.stdev),
+        };
+    }
+    for quantile in &entry.quantiles {
+        let _ = write!(out, "\t{:.3}", quantile);
+    }
+    out
+}
+
 #[derive(Parser)]
 #[command(about = "Gets statistics of a bigWig over a bed.", long_about = None)]
 struct Cli {
@@ -46,6 +91,27 @@ struct Cli {
     #[arg(long)]
     end: Option<u32>,
 
+    /// Also output the minimum and maximum values, after the default stats
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    min_max: bool,
+
+    /// The comma-separated stats to output after the name, in order.
+    /// By default, this is `size,bases,sum,mean0,mean` (followed by `min,max` if `--min-max` is set).
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "min_max")]
+    stats: Vec<Stat>,
+
+    /// Comma-separated quantiles (between 0 and 1) of the values over the covered bases to output
+    /// after the other stats. For example, `0.5` outputs the median.
+    #[arg(long, value_delimiter = ',')]
+    quantiles: Vec<f64>,
+
+    /// If set, compute stats over a window of this many bases centered on each bed entry,
+    /// rather than over the entry itself. Otherwise, for BED12 entries only bases within
+    /// the blocks (e.g. exons) are used.
+    #[arg(long)]
+    sample_around_center: Option<u32>,
+
     /// Set the number of threads to use. This tool will nearly always benefit from more cores (<= # chroms).
     /// Note: for parts of the runtime, the actual usage may be nthreads+1
     #[arg(short = 't', long)]
@@ -59,7 +125,9 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             replace:
                 "-chrom", "--chrom";
                 "-start", "--start";
-                "-end", "--end"
+                "-end", "--end";
+                "-minMax", "--min-max";
+                "-sampleAroundCenter", "--sample-around-center"
             ignore:
             unimplemented:
                 "-udcDir"
@@ -75,7 +143,7 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let outbed = File::create(bedoutpath)?;
     let mut bedoutwriter = BufWriter::new(outbed);
 
-    let bedin = BufReader::new(File::open(&bedinpath)?);
+    let bedin = GzipReader::new(File::open(&bedinpath)?)?;
     let mut bedstream = StreamingLineReader::new(bedin);
 
     let name = match matches.namecol.as_deref() {
@@ -98,6 +166,33 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         None => Name::Column(3),
     };
 
+    let stats = if !matches.stats.is_empty() {
+        matches.stats
+    } else if matches.min_max {
+        vec![
+            Stat::Size,
+            Stat::Bases,
+            Stat::Sum,
+            Stat::Mean0,
+            Stat::Mean,
+            Stat::Min,
+            Stat::Max,
+        ]
+    } else {
+        vec![Stat::Size, Stat::Bases, Stat::Sum, Stat::Mean0, Stat::Mean]
+    };
+    let options = StatsOptions {
+        quantiles: matches.quantiles,
+        sample_around_center: matches.sample_around_center,
+    };
+    if options.quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid quantile. Quantiles must be between 0 and 1.",
+        )
+        .into());
+    }
+
     let nthreads: usize = matches.nthreads;
     let parallel = nthreads > 1;
 
@@ -107,12 +202,14 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             chrom: String,
             bedinpath: String,
             name: Name,
+            stats: &[Stat],
+            options: &StatsOptions,
             inbigwig: &mut BigWigRead<R>,
         ) -> Result<File, Box<dyn Error + Send + Sync>> {
             let mut tmp = tempfile::tempfile()?;
 
-            let mut chrom_bed_file = File::open(bedinpath)?;
-            chrom_bed_file.seek(SeekFrom::Start(start))?;
+            let mut chrom_bed_file = GzipReader::new(File::open(bedinpath)?)?;
+            chrom_bed_file.seek_to(start)?;
             let mut bed_parser = BedParser::from_bed_file(chrom_bed_file);
             let mut data = match bed_parser.next_chrom() {
                 Some(Ok((next_chrom, data))) => {
@@ -144,17 +241,14 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                     Some(Ok(entry)) => entry,
                 };
 
-                let entry = match stats_for_bed_item(name, &chrom, entry, inbigwig) {
+                let entry = match stats_for_bed_item(name, &chrom, entry, inbigwig, options) {
                     Ok(stats) => stats,
                     Err(e) => {
                         return Err(e.into());
                     }
                 };
 
-                let stats = format!(
-                    "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
-                    entry.size, entry.bases, entry.sum, entry.mean0, entry.mean
-                );
+                let stats = format_stats(&entry, stats);
                 writeln!(&mut tmp, "{}\t{}", entry.name, stats)?
             }
 
@@ -178,6 +272,8 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         for _ in 0..(nthreads - 1) {
             let inbigwig_ = inbigwig.reopen()?;
             let chrom_data_receiver_ = chrom_data_receiver.clone();
+            let stats = stats.clone();
+            let options = options.clone();
             let do_process_chrom = move || {
                 let mut inbigwig = inbigwig_;
                 let chrom_data_receiver = chrom_data_receiver_;
@@ -188,7 +284,15 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                         Err(_) => break,
                     };
 
-                    let result = process_chrom(start, chrom, bedinpath, name, &mut inbigwig);
+                    let result = process_chrom(
+                        start,
+                        chrom,
+                        bedinpath,
+                        name,
+                        &stats,
+                        &options,
+                        &mut inbigwig,
+                    );
                     result_sender.send(result).unwrap();
                 }
             };
@@ -218,8 +322,15 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                                 }
                             };
 
-                            let result =
-                                process_chrom(start, chrom, bedinpath, name, &mut inbigwig);
+                            let result = process_chrom(
+                                start,
+                                chrom,
+                                bedinpath,
+                                name,
+                                &stats,
+                                &options,
+                                &mut inbigwig,
+                            );
                             result_sender.send(result).unwrap();
                         }
                     }
@@ -239,30 +350,31 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         }
     } else {
         loop {
-            let line = match bedstream.read() {
+            match bedstream.read() {
                 None => break,
                 Some(Err(e)) => {
                     return Err(e.into());
                 }
-                Some(Ok(line)) => line,
+                Some(Ok(line)) if is_header_line(line) => continue,
+                Some(Ok(_)) => {}
             };
+            let line = bedstream.current_line();
 
-            let (chrom, entry) = parse_bed(line).ok_or_else(|| {
-                io::Error::new(
+            let (chrom, entry) = parse_bed(line.trim_end())
+                .ok_or_else(|| {
+                    io::Error::new(
                     io::ErrorKind::InvalidData,
                     "Invalid bed: A minimum of 3 columns must be specified (chrom, start, end).",
                 )
-            })??;
+                })?
+                .map_err(|e| e.at(bedstream.position()))?;
 
-            let entry = match stats_for_bed_item(name, chrom, entry, &mut inbigwig) {
+            let entry = match stats_for_bed_item(name, chrom, entry, &mut inbigwig, &options) {
                 Ok(stats) => stats,
                 Err(e) => return Err(e.into()),
             };
 
-            let stats = format!(
-                "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
-                entry.size, entry.bases, entry.sum, entry.mean0, entry.mean
-            );
+            let stats = format_stats(&entry, &stats);
             writeln!(&mut bedoutwriter, "{}\t{}", entry.name, stats)?
         }
     }
@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+
+use clap::{Parser, ValueEnum};
+
+use bigtools::utils::chromvalues::BoxedChromValues;
+use bigtools::utils::compare::{compare_bigwigs, CompareOp, CompareOptions, MissingData};
+use bigtools::utils::reopen::Reopen;
+use bigtools::{BBIRead, BBIReadError, BigWigRead, BigWigWrite, QueuedChromData};
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Op {
+    /// log2 of the ratio of the first to the second bigwig
+    Log2,
+    /// The ratio of the first to the second bigwig
+    Ratio,
+    /// The first bigwig minus the second
+    Difference,
+    /// The ratio if it is at least 1, otherwise the negative of its reciprocal
+    FoldChange,
+}
+
+#[derive(Parser)]
+#[command(about = "Compares two bigwigs (such as a treatment and a control), writing the log2 ratio, ratio, difference, or fold change.", long_about = None)]
+struct Cli {
+    /// The first bigwig (the numerator for ratios)
+    bigwig1: String,
+
+    /// The second bigwig (the denominator for ratios)
+    bigwig2: String,
+
+    /// The output bigwig
+    output: String,
+
+    /// How the values of the two bigwigs are compared
+    #[arg(long, value_enum)]
+    #[arg(default_value_t = Op::Log2)]
+    op: Op,
+
+    /// The pseudocount added to values before taking a ratio. Either a single value for both
+    /// bigwigs, or two comma-separated values for the first and second bigwig.
+    #[arg(long, value_delimiter = ',', num_args = 1)]
+    #[arg(default_value = "1")]
+    pseudocount: Vec<f32>,
+
+    /// Skip regions where either bigwig has no value, rather than treating missing values as zero
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    skip_missing: bool,
+
+    /// Average the values of each bigwig over bins of this size before comparing them
+    #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    bin_size: Option<u32>,
+
+    /// Set the number of threads to use. This tool will nearly always benefit from more cores (<= # chroms).
+    #[arg(short = 't', long)]
+    #[arg(default_value_t = 6)]
+    nthreads: usize,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Cli::parse();
+
+    let pseudocounts = match matches.pseudocount[..] {
+        [p] => (p, p),
+        [p1, p2] => (p1, p2),
+        _ => return Err("Expected one or two pseudocounts.".into()),
+    };
+    let missing = if matches.skip_missing {
+        MissingData::Skip
+    } else {
+        MissingData::Zero
+    };
+    let options = CompareOptions {
+        op: match matches.op {
+            Op::Log2 => CompareOp::Log2Ratio,
+            Op::Ratio => CompareOp::Ratio,
+            Op::Difference => CompareOp::Difference,
+            Op::FoldChange => CompareOp::FoldChange,
+        },
+        pseudocounts,
+        missing,
+        bin_size: matches.bin_size,
+    };
+
+    let one = BigWigRead::open_file(&matches.bigwig1)?;
+    let two = BigWigRead::open_file(&matches.bigwig2)?;
+
+    // Chroms missing from one bigwig only have values when missing values are zero
+    let mut chrom_sizes: BTreeMap<String, (Option<u32>, Option<u32>)> = BTreeMap::new();
+    for chrom in one.get_chroms() {
+        chrom_sizes.entry(chrom.name).or_default().0 = Some(chrom.length);
+    }
+    for chrom in two.get_chroms() {
+        chrom_sizes.entry(chrom.name).or_default().1 = Some(chrom.length);
+    }
+    let mut chrom_map = HashMap::new();
+    for (chrom, sizes) in chrom_sizes {
+        let size = match (sizes, missing) {
+            ((Some(one), Some(two)), _) if one != two => {
+                return Err(format!(
+                    "Chrom '{}' had different sizes in the bigwig files. (Are you using the same assembly?)",
+                    chrom
+                )
+                .into());
+            }
+            ((Some(size), _), MissingData::Zero) | ((_, Some(size)), MissingData::Zero) => size,
+            ((Some(size), Some(_)), MissingData::Skip) => size,
+            _ => continue,
+        };
+        chrom_map.insert(chrom, size);
+    }
+    let mut chroms: Vec<String> = chrom_map.keys().cloned().collect();
+    chroms.sort();
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(matches.nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+    let outb = BigWigWrite::create_file(matches.output);
+    // Start comparing the next few chromosomes, so that they are processed in parallel
+    let chroms = chroms.into_iter().map(move |chrom| {
+        let iter = compare_bigwigs(one.reopen()?, two.reopen()?, &chrom, options)?;
+        Ok::<_, BBIReadError>((chrom, BoxedChromValues::new(iter)))
+    });
+    let all_values = QueuedChromData::new(chroms, matches.nthreads);
+    outb.write(chrom_map, all_values, pool)?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_cli_bigwigcompare() {
+    use clap::CommandFactory;
+    Cli::command().debug_assert()
+}
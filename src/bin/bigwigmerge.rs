@@ -1,55 +1,49 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env;
 use std::error::Error;
 use std::ffi::OsString;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
 use std::str::FromStr;
 
-use clap::Parser;
-use crossbeam_channel::unbounded;
+use clap::{Parser, ValueEnum};
+use futures::FutureExt;
 use thiserror::Error;
 
-use bigtools::utils::chromvalues::ChromValues;
-use bigtools::utils::merge::merge_sections_many;
-use bigtools::utils::reopen::ReopenableFile;
+use bigtools::utils::chromvalues::{BoxedChromValues, ChromValues};
+use bigtools::utils::merge::{merge_sections_many_with_op, MergeOp, MergeOpError};
+use bigtools::utils::reopen::{FilePool, PooledFile, Reopen};
+use bigtools::QueuedChromData;
 use bigtools::Value;
-use bigtools::{BBIRead, BBIReadError, BigWigRead, BigWigWrite};
-use bigtools::{ChromData, ChromDataState, ChromProcessingKey, ProcessChromError};
+use bigtools::{BBIRead, BBIReadError, BigWigRead, BigWigReadOpenError, BigWigWrite};
 
-pub struct MergingValues {
-    // We Box<dyn Iterator> because other this would be a mess to try to type
-    iter: std::iter::Peekable<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>>,
-}
+pub type MergingValues = BoxedChromValues<Value, MergingValuesError>;
 
-impl MergingValues {
-    pub fn new<I: 'static>(
-        iters: Vec<I>,
-        threshold: f32,
-        adjust: Option<f32>,
-        clip: Option<f32>,
-    ) -> Self
-    where
-        I: Iterator<Item = Result<Value, MergingValuesError>> + Send,
-    {
-        let adjust = adjust.unwrap_or(0.0);
-        let iter: Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send> = Box::new(
-            merge_sections_many(iters)
-                .map(move |x| {
-                    x.map(|mut v| {
-                        if let Some(clip) = clip {
-                            v.value = clip.min(v.value);
-                        }
-                        v.value += adjust;
-                        v
-                    })
-                })
-                .filter(move |x| x.as_ref().map_or(true, |v| v.value > threshold)),
-        );
-        MergingValues {
-            iter: iter.peekable(),
-        }
-    }
+/// Merges `iters` with `op`, then clips, adjusts, and drops values at or
+/// below `threshold`.
+pub fn merging_values<I: 'static>(
+    iters: Vec<I>,
+    op: MergeOp,
+    threshold: f32,
+    adjust: Option<f32>,
+    clip: Option<f32>,
+) -> Result<MergingValues, MergeOpError>
+where
+    I: Iterator<Item = Result<Value, MergingValuesError>> + Send,
+{
+    let adjust = adjust.unwrap_or(0.0);
+    let iter = merge_sections_many_with_op(iters, op)?
+        .map(move |x| {
+            x.map(|mut v| {
+                if let Some(clip) = clip {
+                    v.value = clip.min(v.value);
+                }
+                v.value += adjust;
+                v
+            })
+        })
+        .filter(move |x| x.as_ref().map_or(true, |v| v.value > threshold));
+    Ok(BoxedChromValues::new(iter))
 }
 
 #[derive(Error, Debug)]
@@ -62,32 +56,30 @@ pub enum MergingValuesError {
     Other(String),
     #[error("{}", .0)]
     IoError(#[from] io::Error),
+    #[error("{}", .0)]
+    MergeOpError(#[from] MergeOpError),
 }
 
-impl ChromValues for MergingValues {
-    type Value = Value;
-    type Error = MergingValuesError;
-
-    fn next(&mut self) -> Option<Result<Value, MergingValuesError>> {
-        match self.iter.next() {
-            Some(Ok(v)) => Some(Ok(v)),
-            Some(Err(e)) => Some(Err(e.into())),
-            None => None,
+/// The operation to merge only the given inputs (by index), out of `num_inputs` total,
+/// such that the result stays the same as when merging all inputs with `op`.
+fn subset_op(op: &MergeOp, num_inputs: usize, inputs: &[usize]) -> MergeOp {
+    match op {
+        MergeOp::MeanAll if inputs.len() != num_inputs => {
+            MergeOp::WeightedSum(vec![1.0 / num_inputs as f32; inputs.len()])
         }
-    }
-
-    fn peek(&mut self) -> Option<Result<&Value, &MergingValuesError>> {
-        match self.iter.peek() {
-            Some(Ok(v)) => Some(Ok(v)),
-            Some(Err(err)) => Some(Err(err)),
-            None => None,
+        MergeOp::WeightedSum(weights) => {
+            MergeOp::WeightedSum(inputs.iter().map(|i| weights[*i]).collect())
         }
+        _ => op.clone(),
     }
 }
 
+/// Merges the values of `bigwigs` by chromosome. Chromosomes are merged
+/// lazily, reading through `PooledFile`s, so the number of open files is
+/// bounded by their `FilePool` no matter how many bigwigs are merged.
 pub fn get_merged_vals(
-    bigwigs: Vec<BigWigRead<ReopenableFile>>,
-    max_zooms: usize,
+    bigwigs: Vec<BigWigRead<PooledFile>>,
+    op: MergeOp,
     threshold: f32,
     adjust: Option<f32>,
     clip: Option<f32>,
@@ -99,8 +91,6 @@ pub fn get_merged_vals(
     MergingValuesError,
 > {
     let (chrom_sizes, chrom_map) = {
-        // NOTE: We don't need to worry about max fds here because chroms are cached.
-
         // Get sizes for each and check that all files (that have the chrom) agree
         // Check that all chrom sizes match for all files
         let mut chrom_sizes = BTreeMap::new();
@@ -111,7 +101,8 @@ pub fn get_merged_vals(
             }
             let mut size = None;
             let mut bws = Vec::with_capacity(bigwigs.len());
-            for w in bigwigs.iter() {
+            let mut inputs = Vec::with_capacity(bigwigs.len());
+            for (idx, w) in bigwigs.iter().enumerate() {
                 let chroms = w.get_chroms();
                 let res = chroms.iter().find(|v| v.name == chrom);
                 let res = match res {
@@ -131,115 +122,55 @@ pub fn get_merged_vals(
                         size = Some(res.length);
                     }
                 }
-                // We don't want to a new file descriptor for every chrom
-                bws.push((w.get_info().clone(), w.inner_read().path.to_string()));
+                // Reopening a pooled file doesn't open a new file descriptor
+                bws.push(w.reopen()?);
+                inputs.push(idx);
             }
             let size = size.unwrap();
+            // Not all files may have this chrom
+            let op = subset_op(&op, bigwigs.len(), &inputs);
 
-            chrom_sizes.insert(chrom.clone(), (size, bws));
+            chrom_sizes.insert(chrom.clone(), (size, op, bws));
             chrom_map.insert(chrom.clone(), size);
         }
 
         (chrom_sizes, chrom_map)
     };
 
-    const MAX_FDS: usize = 1000;
-    const PARALLEL_CHROMS: usize = 1;
-    // This might be a *bit* conservative, but is really mostly an estimate
-    let max_bw_fds: usize = MAX_FDS
-        - 1 /* output bigWig (data) */
-        - 1 /* index */
-        - (1 /* data sections */ + 1  /* index sections */ + max_zooms /* zoom data sections */ + max_zooms /* zoom index sections */) * PARALLEL_CHROMS;
-
-    let iter = chrom_sizes.into_iter().map(move |(chrom, (size, bws))| {
-        if bws.len() > max_bw_fds {
-            eprintln!("Number of bigWigs to merge would exceed the maximum number of file descriptors. Splitting into chunks.");
-
-            let mut merges: Vec<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>> = bws
-                .into_iter()
-                .map(|b| {
-                    let f = ReopenableFile { file: File::open(&b.1)?, path: b.1 };
-                    let b = BigWigRead::with_info(b.0, f);
-                    let iter = b.get_interval_move(&chrom, 1, size).map(|i| i.map(|r| r.map_err(|e| MergingValuesError::BBIReadError(e))))?;
-                    Ok(Box::new(iter) as Box<_>)
-                })
-                .collect::<Result<Vec<_>, BBIReadError>>()?;
-
-            while merges.len() > max_bw_fds {
-                merges = {
-                    let len = merges.len();
-                    let mut vals = merges.into_iter().peekable();
-                    let mut merges: Vec<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>> = Vec::with_capacity(len/max_bw_fds+1);
-
-                    while vals.peek().is_some() {
-                        let chunk = vals.by_ref().take(max_bw_fds).collect::<Vec<_>>();
-                        let mut mergingvalues = MergingValues::new(chunk, threshold, adjust, clip);
-                        let (sender, receiver) = unbounded::<Value>();
-                        while let Some(val) = mergingvalues.next() {
-                            let val = val?;
-                            sender.send(val).unwrap();
-                        }
-
-                        merges.push(Box::new(receiver.into_iter().map(Result::Ok)));
-                    }
-                    merges
-                };
-            }
-
-            let mergingvalues = MergingValues::new(merges, threshold, adjust, clip);
-            Ok((chrom, size, mergingvalues))
-        } else {
+    let iter = chrom_sizes
+        .into_iter()
+        .map(move |(chrom, (size, op, bws))| {
             let iters: Vec<_> = bws
                 .into_iter()
                 .map(|b| {
-                    let f = ReopenableFile { file: File::open(&b.1)?, path: b.1 };
-                    let b = BigWigRead::with_info(b.0, f);
-                    b.get_interval_move(&chrom, 1, size).map(|i| i.map(|r| r.map_err(|e| MergingValuesError::BBIReadError(e))))
+                    b.get_interval_move(&chrom, 1, size)
+                        .map(|i| i.map(|r| r.map_err(MergingValuesError::BBIReadError)))
                 })
                 .collect::<Result<Vec<_>, _>>()?;
-            let mergingvalues = MergingValues::new(iters, threshold, adjust, clip);
+            let mergingvalues = merging_values(iters, op, threshold, adjust, clip)?;
 
             Ok((chrom, size, mergingvalues))
-        }
-    });
+        });
 
     Ok((iter, chrom_map))
 }
 
-struct ChromGroupReadImpl {
-    iter: Box<dyn Iterator<Item = Result<(String, u32, MergingValues), MergingValuesError>> + Send>,
-}
-
-impl ChromData for ChromGroupReadImpl {
-    type Values = MergingValues;
-
-    fn advance<
-        State,
-        F: FnMut(
-            String,
-            MergingValues,
-            &mut State,
-        ) -> Result<ChromProcessingKey, ProcessChromError<MergingValuesError>>,
-    >(
-        &mut self,
-        do_read: &mut F,
-        state: &mut State,
-    ) -> Result<
-        ChromDataState<ChromProcessingKey, MergingValuesError>,
-        ProcessChromError<MergingValuesError>,
-    > {
-        let next: Option<Result<(String, u32, MergingValues), MergingValuesError>> =
-            self.iter.next();
-        Ok(match next {
-            Some(Err(err)) => ChromDataState::Error(err.into()),
-            Some(Ok((chrom, _, mergingvalues))) => {
-                let read = do_read(chrom, mergingvalues, state)?;
-
-                ChromDataState::NewChrom(read)
-            }
-            None => ChromDataState::Finished,
-        })
-    }
+#[derive(Copy, Clone, ValueEnum)]
+enum Op {
+    /// The sum of the values
+    Sum,
+    /// The maximum value
+    Max,
+    /// The minimum value
+    Min,
+    /// The mean of the values of the files that have a value at a base
+    Mean,
+    /// The mean over all files, where files without a value at a base count as zero
+    MeanAll,
+    /// The number of files that have a value at a base
+    Count,
+    /// The sum of the values, each multiplied by the weight of its file (see `--weights`)
+    WeightedSum,
 }
 
 #[derive(Parser)]
@@ -269,8 +200,18 @@ struct Cli {
     #[arg(long)]
     clip: Option<f32>,
 
-    /// Merged value is maximum from input files rather than sum
-    #[arg(long)]
+    /// How values from the input files are combined at each base
+    #[arg(long, value_enum)]
+    #[arg(default_value_t = Op::Sum)]
+    op: Op,
+
+    /// Comma-separated weights for `--op weighted-sum`, one for each input bigwig in the
+    /// order given (`-b` files first, then those from `-l` lists)
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    weights: Vec<f32>,
+
+    /// Merged value is maximum from input files rather than sum. Same as `--op max`.
+    #[arg(long, conflicts_with = "op")]
     #[arg(default_value_t = false)]
     max: bool,
 
@@ -336,21 +277,37 @@ fn main() -> Result<(), Box<dyn Error>> {
             replace:
                 "-threshold", "--threshold";
                 "-adjust", "--adjust";
-                "-clip", "--clip"
+                "-clip", "--clip";
+                "-max", "--max"
             ignore:
                 "-inList"
             unimplemented:
-                "-max";
                 "-udcDir"
         )
     });
     let matches = Cli::parse_from(args);
 
     let output = matches.output;
-    let mut bigwigs: Vec<BigWigRead<ReopenableFile>> = vec![];
+    let nthreads = matches.nthreads;
+    let max_zooms = 10;
+
+    const MAX_FDS: usize = 1000;
+    // This might be a *bit* conservative, but is really mostly an estimate
+    let max_bw_fds: usize = MAX_FDS
+        .saturating_sub(
+            1 /* output bigWig (data) */
+            + 1 /* index */
+            + (1 /* data sections */ + 1  /* index sections */ + max_zooms /* zoom data sections */ + max_zooms /* zoom index sections */) * nthreads,
+        )
+        .max(1);
+    let file_pool = FilePool::new(max_bw_fds);
+    let open_bigwig = |name: &str| -> Result<BigWigRead<PooledFile>, BigWigReadOpenError> {
+        BigWigRead::open(file_pool.open(name)?)
+    };
+    let mut bigwigs: Vec<BigWigRead<PooledFile>> = vec![];
 
     for name in matches.bigwig {
-        match BigWigRead::open_file(&name) {
+        match open_bigwig(&name) {
             Ok(bw) => bigwigs.push(bw),
             Err(e) => {
                 eprintln!("Error when opening bigwig ({}): {:?}", name, e);
@@ -369,7 +326,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         let lines = BufReader::new(list_file).lines();
         for line in lines {
             let name = line?;
-            match BigWigRead::open_file(&name) {
+            match open_bigwig(&name) {
                 Ok(bw) => bigwigs.push(bw),
                 Err(e) => {
                     eprintln!("Error when opening bigwig ({}): {:?}", name, e);
@@ -379,39 +336,71 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let nthreads = matches.nthreads;
+    let op = match (matches.max, matches.op) {
+        (true, _) | (_, Op::Max) => MergeOp::Max,
+        (_, Op::Sum) => MergeOp::Sum,
+        (_, Op::Min) => MergeOp::Min,
+        (_, Op::Mean) => MergeOp::MeanCovering,
+        (_, Op::MeanAll) => MergeOp::MeanAll,
+        (_, Op::Count) => MergeOp::Count,
+        (_, Op::WeightedSum) => MergeOp::WeightedSum(matches.weights),
+    };
+    if let Err(e) = op.validate(bigwigs.len()) {
+        return Err(format!("Invalid weights for the input bigwigs: {}", e).into());
+    }
 
     let (iter, chrom_map) =
-        get_merged_vals(bigwigs, 10, matches.threshold, matches.adjust, matches.clip)?;
+        get_merged_vals(bigwigs, op, matches.threshold, matches.adjust, matches.clip)?;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
 
     match output {
         output if output.ends_with(".bw") || output.ends_with(".bigWig") => {
-            let outb = BigWigWrite::create_file(output);
-            let pool = futures::executor::ThreadPoolBuilder::new()
-                .pool_size(nthreads)
-                .create()
-                .expect("Unable to create thread pool.");
-            let all_values = ChromGroupReadImpl {
-                iter: Box::new(iter),
-            };
+            let mut outb = BigWigWrite::create_file(output);
+            outb.options.max_zooms = max_zooms as u32;
+            // Start merging the next few chromosomes, so that they are processed in parallel
+            let iter = iter.map(|r| r.map(|(chrom, _, mergingvalues)| (chrom, mergingvalues)));
+            let all_values = QueuedChromData::new(iter, nthreads);
             outb.write(chrom_map, all_values, pool)?;
         }
         output if output.ends_with(".bedGraph") => {
-            // TODO: convert to multi-threaded
-            use std::io::Write;
-
             let bedgraph = File::create(output)?;
             let mut writer = io::BufWriter::new(bedgraph);
 
-            for v in iter {
-                let (chrom, _, mut values) = v?;
+            // Chromosomes are merged in parallel into temporary files, which are then copied in order
+            let write_chrom = |chrom: String, mut values: MergingValues| {
+                let mut tmp = io::BufWriter::new(tempfile::tempfile()?);
                 while let Some(val) = values.next() {
                     let val = val?;
-                    writer.write_fmt(format_args!(
+                    tmp.write_fmt(format_args!(
                         "{}\t{}\t{}\t{}\n",
                         chrom, val.start, val.end, val.value
                     ))?;
                 }
+                let mut tmp = tmp.into_inner().map_err(|e| e.into_error())?;
+                tmp.seek(SeekFrom::Start(0))?;
+                Ok::<_, MergingValuesError>(tmp)
+            };
+            let mut iter = iter;
+            let mut queued = VecDeque::with_capacity(nthreads);
+            loop {
+                while queued.len() < nthreads {
+                    let (chrom, _, values) = match iter.next() {
+                        Some(v) => v?,
+                        None => break,
+                    };
+                    let (fut, handle) = async move { write_chrom(chrom, values) }.remote_handle();
+                    pool.spawn_ok(fut);
+                    queued.push_back(handle);
+                }
+                let mut tmp = match queued.pop_front() {
+                    Some(handle) => futures::executor::block_on(handle)?,
+                    None => break,
+                };
+                io::copy(&mut tmp, &mut writer)?;
             }
         }
         _ => {
@@ -420,7 +409,6 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    //TODO: fails with too many open files
     Ok(())
 }
 
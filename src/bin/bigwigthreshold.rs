@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use clap::Parser;
+
+use bigtools::utils::chromvalues::BoxedChromValues;
+use bigtools::utils::reopen::Reopen;
+use bigtools::utils::threshold::{bigwig_threshold_regions, ThresholdOptions, ThresholdRegion};
+use bigtools::{BBIRead, BBIReadError, BedEntry, BigBedWrite, BigWigRead, QueuedChromData};
+
+const THRESHOLD_AUTOSQL: &str = "\
+table thresholdRegions
+\"Regions of a bigWig above a threshold\"
+(
+    string chrom;       \"Reference sequence chromosome or scaffold\"
+    uint   chromStart;  \"Start position in chromosome\"
+    uint   chromEnd;    \"End position in chromosome\"
+    float  maxValue;    \"Maximum value in the region\"
+    float  meanValue;   \"Mean value over the bases with a value\"
+    uint   summit;      \"Position of the maximum value\"
+)
+";
+
+fn to_bed_entry(region: ThresholdRegion) -> BedEntry {
+    BedEntry {
+        start: region.start,
+        end: region.end,
+        rest: format!("{}\t{}\t{}", region.max, region.mean, region.summit),
+    }
+}
+
+#[derive(Parser)]
+#[command(about = "Finds the regions of a bigwig above a threshold, writing them as a bed or bigBed with the max, mean, and summit of each region.", long_about = None)]
+struct Cli {
+    /// The input bigwig
+    bigwig: String,
+
+    /// The output file. Written as a bigBed if it ends with `.bb` or `.bigBed`, otherwise as a bed.
+    output: String,
+
+    /// Only values greater than this are part of a region
+    #[arg(long)]
+    threshold: f32,
+
+    /// Regions shorter than this (after merging) are dropped
+    #[arg(long)]
+    #[arg(default_value_t = 0)]
+    min_length: u32,
+
+    /// Merge regions separated by fewer than this many bases
+    #[arg(long)]
+    #[arg(default_value_t = 0)]
+    merge_gap: u32,
+
+    /// Set the number of threads to use when writing a bigBed.
+    #[arg(short = 't', long)]
+    #[arg(default_value_t = 6)]
+    nthreads: usize,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Cli::parse();
+
+    let options = ThresholdOptions {
+        threshold: matches.threshold,
+        min_length: matches.min_length,
+        merge_gap: matches.merge_gap,
+    };
+    let bigwig = BigWigRead::open_file(&matches.bigwig)?;
+    let mut chroms = bigwig.get_chroms();
+    chroms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let output = matches.output.to_lowercase();
+    if output.ends_with(".bb") || output.ends_with(".bigbed") {
+        let chrom_map: HashMap<String, u32> =
+            chroms.iter().map(|c| (c.name.clone(), c.length)).collect();
+        let pool = futures::executor::ThreadPoolBuilder::new()
+            .pool_size(matches.nthreads)
+            .create()
+            .expect("Unable to create thread pool.");
+        let mut outb = BigBedWrite::create_file(matches.output);
+        outb.autosql = Some(THRESHOLD_AUTOSQL.to_string());
+        // Start calling regions on the next few chromosomes, so that they are processed in parallel
+        let regions = chroms.into_iter().map(move |chrom| {
+            let regions = bigwig_threshold_regions(bigwig.reopen()?, &chrom.name, options)?;
+            let values = BoxedChromValues::new(regions.map(|r| r.map(to_bed_entry)));
+            Ok::<_, BBIReadError>((chrom.name, values))
+        });
+        let all_values = QueuedChromData::new(regions, matches.nthreads);
+        outb.write(chrom_map, all_values, pool)?;
+    } else {
+        let mut out = BufWriter::new(File::create(&matches.output)?);
+        for chrom in chroms {
+            let regions = bigwig_threshold_regions(bigwig.reopen()?, &chrom.name, options)?;
+            for region in regions {
+                let region = region?;
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    chrom.name, region.start, region.end, region.max, region.mean, region.summit
+                )?;
+            }
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn verify_cli_bigwigthreshold() {
+    use clap::CommandFactory;
+    Cli::command().debug_assert()
+}
@@ -1,28 +1,83 @@
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::path::Path;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 use bigtools::utils::reopen::SeekableRead;
 use bigtools::utils::streaming_linereader::StreamingLineReader;
-use bigtools::BBIReadError;
 use bigtools::BigWigRead;
 
+#[derive(Copy, Clone, PartialEq, ValueEnum)]
+enum Format {
+    /// One line of delimited values per bed entry. Missing values are written as 0.
+    Text,
+    /// A float32 `.npy` matrix with one row per bed entry. All entries (or their bins) must have the same length.
+    Npy,
+    /// Raw little-endian float32 values of every bed entry, one after another, with an index of the offset and number of values of each entry written to `<output>.offsets`.
+    Binary,
+}
+
 struct Options {
     withnames: bool,
     delimiter: String,
+    format: Format,
+    bin_size: Option<u32>,
+}
+
+// The size of the npy header, including the magic string. The header is
+// padded to this size so it can be rewritten once the number of rows is known.
+const NPY_HEADER_LEN: usize = 128;
+
+fn npy_header(rows: u64, cols: usize) -> Vec<u8> {
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    let dict = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, cols
+    );
+    let dict_len = NPY_HEADER_LEN - header.len() - 2;
+    header.extend_from_slice(&(dict_len as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(NPY_HEADER_LEN - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+/// Averages `values` over bins of `bin_size`, ignoring missing (`NaN`) values.
+/// The last bin may be smaller than `bin_size`.
+fn bin_values(values: Vec<f32>, bin_size: Option<u32>) -> Vec<f32> {
+    let bin_size = match bin_size {
+        Some(bin_size) => bin_size as usize,
+        None => return values,
+    };
+    values
+        .chunks(bin_size)
+        .map(|bin| {
+            let (sum, count) = bin
+                .iter()
+                .filter(|v| !v.is_nan())
+                .fold((0.0f64, 0usize), |(sum, count), v| {
+                    (sum + f64::from(*v), count + 1)
+                });
+            if count == 0 {
+                f32::NAN
+            } else {
+                (sum / count as f64) as f32
+            }
+        })
+        .collect()
 }
 
 fn write<R: SeekableRead + 'static>(
     bedinpath: &Path,
     mut bigwigin: BigWigRead<R>,
     out: File,
+    outputpath: &str,
     options: Options,
-) -> Result<(), BBIReadError> {
+) -> Result<(), Box<dyn Error>> {
     let uniquenames = {
-        if !options.withnames {
+        if !options.withnames && options.format != Format::Binary {
             true
         } else {
             let reader = BufReader::new(File::open(bedinpath)?);
@@ -31,17 +86,7 @@ fn write<R: SeekableRead + 'static>(
                 .take(10)
                 .map(|line| -> io::Result<Option<String>> {
                     let l = line?;
-                    let mut split = l.splitn(5, '\t');
-                    let _chrom = split
-                        .next()
-                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData));
-                    let _start = split
-                        .next()
-                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData));
-                    let _end = split
-                        .next()
-                        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData));
-                    let name = split.next();
+                    let name = l.trim().split('\t').nth(3);
                     Ok(name.map(|name| name.to_string()))
                 })
                 .collect::<io::Result<Vec<_>>>()?;
@@ -51,42 +96,112 @@ fn write<R: SeekableRead + 'static>(
         }
     };
 
-    let bedin = File::open(bedinpath).unwrap();
+    let bedin = File::open(bedinpath)?;
     let mut bedstream = StreamingLineReader::new(BufReader::new(bedin));
     let mut outwriter = BufWriter::new(out);
+    let mut index = match options.format {
+        Format::Binary => Some(BufWriter::new(File::create(format!(
+            "{}.offsets",
+            outputpath
+        ))?)),
+        _ => None,
+    };
+    if options.format == Format::Npy {
+        outwriter.write_all(&npy_header(0, 0))?;
+    }
+    let mut rows: u64 = 0;
+    let mut cols: Option<usize> = None;
+    let mut offset: u64 = 0;
+    let mut line_number = 0;
 
     while let Some(line) = bedstream.read() {
         let line = line?;
+        line_number += 1;
+        let invalid = |what: &str| {
+            format!(
+                "Invalid bed line {} ({}): {}",
+                line_number,
+                what,
+                line.trim_end()
+            )
+        };
         let mut split = line.trim().splitn(5, '\t');
-        let chrom = split.next().expect("Missing chrom");
-        let start = split.next().expect("Missing start").parse::<u32>().unwrap();
-        let end = split.next().expect("Missing end").parse::<u32>().unwrap();
+        let chrom = split.next().ok_or_else(|| invalid("missing chrom"))?;
+        let start = split
+            .next()
+            .ok_or_else(|| invalid("missing start"))?
+            .parse::<u32>()
+            .map_err(|_| invalid("invalid start"))?;
+        let end = split
+            .next()
+            .ok_or_else(|| invalid("missing end"))?
+            .parse::<u32>()
+            .map_err(|_| invalid("invalid end"))?;
+        if end < start {
+            return Err(invalid("end is before start").into());
+        }
         let name = split.next();
-        let interval = bigwigin
-            .get_interval(chrom, start, end)?
-            .collect::<Result<Vec<_>, _>>()?;
-        let size = end - start;
-        let mut vals: Vec<f32> = vec![0f32; size as usize];
-        for val in interval {
-            for i in val.start..val.end {
-                vals[(i - start) as usize] = val.value;
+        let uniquename = match name {
+            Some(name) if uniquenames => name.to_owned(),
+            _ => format!("{}:{}-{}", chrom, start, end),
+        };
+        let vals = bin_values(bigwigin.values(chrom, start, end)?, options.bin_size);
+
+        match options.format {
+            Format::Text => {
+                let vals_strings: Vec<String> = vals
+                    .into_iter()
+                    .map(|v| if v.is_nan() { 0.0 } else { v })
+                    .map(|v| v.to_string())
+                    .collect();
+                let vals_string = &vals_strings[..].join(&options.delimiter);
+                if options.withnames {
+                    outwriter.write_fmt(format_args!(
+                        "{}{}{}\n",
+                        uniquename, &options.delimiter, vals_string
+                    ))?;
+                } else {
+                    outwriter.write_fmt(format_args!("{}\n", vals_string))?;
+                }
+            }
+            Format::Npy => {
+                match cols {
+                    Some(cols) if cols != vals.len() => {
+                        return Err(format!(
+                            "Bed line {} has {} values, but previous lines had {}. All rows of an npy matrix must have the same length.",
+                            line_number,
+                            vals.len(),
+                            cols
+                        )
+                        .into());
+                    }
+                    _ => cols = Some(vals.len()),
+                }
+                for v in vals {
+                    outwriter.write_all(&v.to_le_bytes())?;
+                }
+            }
+            Format::Binary => {
+                let index = index.as_mut().unwrap();
+                writeln!(index, "{}\t{}\t{}", uniquename, offset, vals.len())?;
+                offset += vals.len() as u64;
+                for v in vals {
+                    outwriter.write_all(&v.to_le_bytes())?;
+                }
             }
         }
-        let vals_strings: Vec<String> = vals.into_iter().map(|v| v.to_string()).collect();
-        let vals_string = &vals_strings[..].join(&options.delimiter);
-        if options.withnames {
-            let uniquename = if uniquenames {
-                name.expect("Bad bed format (no name).").to_owned()
-            } else {
-                format!("{}:{}-{}", chrom, start, end)
-            };
-            outwriter.write_fmt(format_args!(
-                "{}{}{}\n",
-                uniquename, &options.delimiter, vals_string
-            ))?;
-        } else {
-            outwriter.write_fmt(format_args!("{}\n", vals_string))?;
-        }
+        rows += 1;
+    }
+
+    if let Some(mut index) = index {
+        index.flush()?;
+    }
+    if options.format == Format::Npy {
+        let mut out = outwriter.into_inner()?;
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&npy_header(rows, cols.unwrap_or(0)))?;
+    } else {
+        outwriter.flush()?;
     }
     Ok(())
 }
@@ -100,7 +215,7 @@ struct Cli {
     /// The input bed file
     bedin: String,
 
-    /// The output bed file
+    /// The output file
     output: String,
 
     /// If set, the output file will print the name of each bed entry (or `chrom:start-end` if names are not unique) in the first column of each output line.
@@ -111,6 +226,16 @@ struct Cli {
     #[arg(short = 'd', long)]
     #[arg(default_value = "\t")]
     delimiter: String,
+
+    /// The format of the output file
+    #[arg(long, value_enum)]
+    #[arg(default_value_t = Format::Text)]
+    format: Format,
+
+    /// Average the values over bins of this size (ignoring missing values). The last bin of each entry may be smaller.
+    #[arg(long)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    bin_size: Option<u32>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -132,10 +257,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
-    let out = File::create(outputpath)?;
+    let out = File::create(&outputpath)?;
     let options = Options {
         withnames,
         delimiter,
+        format: matches.format,
+        bin_size: matches.bin_size,
     };
 
     #[cfg(feature = "remote")]
@@ -144,17 +271,111 @@ fn main() -> Result<(), Box<dyn Error>> {
             use bigtools::utils::remote_file::RemoteFile;
             let f = RemoteFile::new(&bigwigpath);
             let inbigwig = BigWigRead::open(f)?;
-            write(bedin, inbigwig, out, options)?;
+            write(bedin, inbigwig, out, &outputpath, options)?;
         } else {
             let inbigwig = BigWigRead::open_file(&bigwigpath)?;
-            write(bedin, inbigwig, out, options)?;
+            write(bedin, inbigwig, out, &outputpath, options)?;
         }
     }
     #[cfg(not(feature = "remote"))]
     {
         let inbigwig = BigWigRead::open_file(bigwigpath)?;
-        write(&bedin, inbigwig, out, options)?;
+        write(bedin, inbigwig, out, &outputpath, options)?;
     }
 
     Ok(())
 }
+
+#[test]
+fn verify_cli_bigwigvaluesoverbed() {
+    use clap::CommandFactory;
+    Cli::command().debug_assert()
+}
+
+#[cfg(test)]
+fn write_test_output(
+    bed: &str,
+    format: Format,
+) -> Result<(tempfile::TempDir, String), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bedpath = dir.path().join("regions.bed");
+    std::fs::write(&bedpath, bed)?;
+    let outputpath = dir.path().join("out").to_string_lossy().into_owned();
+
+    let mut bigwig = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    bigwig.push("resources/test/valid.bigWig");
+    let options = Options {
+        withnames: false,
+        delimiter: "\t".to_string(),
+        format,
+        bin_size: None,
+    };
+    write(
+        &bedpath,
+        BigWigRead::open_file(bigwig.to_str().unwrap())?,
+        File::create(&outputpath)?,
+        &outputpath,
+        options,
+    )?;
+    Ok((dir, outputpath))
+}
+
+#[cfg(test)]
+fn read_f32s(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_npy_header() {
+    let header = npy_header(12345, 100);
+    assert_eq!(header.len(), NPY_HEADER_LEN);
+    assert_eq!(header.len() % 64, 0);
+    assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+    let dict_len = u16::from_le_bytes([header[8], header[9]]) as usize;
+    assert_eq!(10 + dict_len, header.len());
+    let dict = std::str::from_utf8(&header[10..]).unwrap();
+    assert!(dict.ends_with('\n'));
+    assert_eq!(
+        dict.trim_end(),
+        "{'descr': '<f4', 'fortran_order': False, 'shape': (12345, 100), }"
+    );
+}
+
+#[test]
+fn test_write_npy() -> Result<(), Box<dyn Error>> {
+    // chr17 has 0.16627 over 59900-59947 and 0.85137 over 59947-59999
+    let bed = "chr17\t59900\t59904\nchr17\t59945\t59949\n";
+    let (_dir, outputpath) = write_test_output(bed, Format::Npy)?;
+    let out = std::fs::read(outputpath)?;
+    assert_eq!(out[..NPY_HEADER_LEN], npy_header(2, 4)[..]);
+    assert_eq!(
+        read_f32s(&out[NPY_HEADER_LEN..]),
+        [0.16627, 0.16627, 0.16627, 0.16627, 0.16627, 0.16627, 0.85137, 0.85137]
+    );
+
+    let bed = "chr17\t59900\t59904\nchr17\t59945\t59950\n";
+    let err = write_test_output(bed, Format::Npy).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Bed line 2 has 5 values, but previous lines had 4. All rows of an npy matrix must have the same length."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_write_binary() -> Result<(), Box<dyn Error>> {
+    let bed = "chr17\t59900\t59902\nchr17\t59945\t59949\n";
+    let (_dir, outputpath) = write_test_output(bed, Format::Binary)?;
+    let out = std::fs::read(&outputpath)?;
+    assert_eq!(
+        read_f32s(&out),
+        [0.16627, 0.16627, 0.16627, 0.16627, 0.85137, 0.85137]
+    );
+    // Names aren't unique (there are fewer than 10), so the regions are used
+    let index = std::fs::read_to_string(format!("{}.offsets", outputpath))?;
+    assert_eq!(index, "chr17:59900-59902\t0\t2\nchr17:59945-59949\t2\t4\n");
+    Ok(())
+}
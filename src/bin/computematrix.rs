@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bed::bedrecord::{BedRecord, Strand};
+use bigtools::utils::chromvalues::ChromValues;
+use bigtools::utils::file::gzip::GzipReader;
+use bigtools::utils::matrix::{
+    compute_matrix, BinStat, MatrixMode, MatrixOptions, MatrixRegion, ReferencePoint,
+};
+use bigtools::{BedEntry, BigWigRead};
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Point {
+    /// The start of each region (with respect to its strand)
+    Tss,
+    /// The end of each region (with respect to its strand)
+    Tes,
+    /// The center of each region
+    Center,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Stat {
+    Mean,
+    Min,
+    Max,
+    Sum,
+}
+
+#[derive(Subcommand)]
+enum Mode {
+    /// Bin the values upstream and downstream of a reference point of each region
+    ReferencePoint {
+        /// The point of each region that bins are relative to
+        #[arg(long, value_enum)]
+        #[arg(default_value_t = Point::Tss)]
+        reference_point: Point,
+
+        #[command(flatten)]
+        args: MatrixArgs,
+    },
+    /// Scale each region to the same length, and bin the values over it and its flanks
+    ScaleRegions {
+        /// The length that each region is scaled to
+        #[arg(long)]
+        #[arg(default_value_t = 1000)]
+        region_body_length: u32,
+
+        #[command(flatten)]
+        args: MatrixArgs,
+    },
+}
+
+#[derive(Args)]
+struct MatrixArgs {
+    /// The regions, as a bed file. The name (4th) and strand (6th) columns are used if present.
+    #[arg(short = 'R', long, required = true)]
+    regions: String,
+
+    /// The bigwigs to compute values of
+    #[arg(short = 'S', long, required = true, num_args = 1..)]
+    bigwigs: Vec<String>,
+
+    /// The output matrix, tab-separated. Gzipped if the file name ends with `.gz`.
+    #[arg(short = 'o', long)]
+    output: String,
+
+    /// The number of bases upstream of each region (or reference point)
+    #[arg(long)]
+    #[arg(default_value_t = 500)]
+    upstream: u32,
+
+    /// The number of bases downstream of each region (or reference point)
+    #[arg(long)]
+    #[arg(default_value_t = 500)]
+    downstream: u32,
+
+    /// The size of each bin. Must divide the upstream, downstream, and region body lengths.
+    #[arg(long)]
+    #[arg(default_value_t = 10)]
+    bin_size: u32,
+
+    /// The statistic of the values in each bin
+    #[arg(long, value_enum)]
+    #[arg(default_value_t = Stat::Mean)]
+    stat: Stat,
+
+    /// Write bins without values as 0, rather than NaN
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    missing_as_zero: bool,
+
+    /// Set the number of threads to use.
+    #[arg(short = 't', long)]
+    #[arg(default_value_t = 6)]
+    nthreads: usize,
+}
+
+#[derive(Parser)]
+#[command(about = "Computes a matrix of binned bigwig values around each region of a bed file.", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    mode: Mode,
+}
+
+fn read_regions(path: &str) -> Result<Vec<MatrixRegion>, Box<dyn Error>> {
+    // Lines are checked as they are read, so that errors have their position
+    let validate = |entry: &BedEntry| BedRecord::from_entry(entry, 6).map(|_| ());
+    let mut parser =
+        BedParser::from_validated_bed_file(GzipReader::new(File::open(path)?)?, Box::new(validate));
+    let mut regions = vec![];
+    while let Some(next) = parser.next_chrom() {
+        let (chrom, mut group) = next?;
+        while let Some(entry) = group.next() {
+            let record = BedRecord::from_entry(&entry?, 6)?;
+            regions.push(MatrixRegion {
+                chrom: chrom.clone(),
+                start: record.start,
+                end: record.end,
+                name: record.name.unwrap_or_else(|| ".".to_string()),
+                strand: record.strand,
+            });
+        }
+    }
+    Ok(regions)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Cli::parse();
+
+    let (mode, args) = match matches.mode {
+        Mode::ReferencePoint {
+            reference_point,
+            args,
+        } => {
+            let point = match reference_point {
+                Point::Tss => ReferencePoint::Start,
+                Point::Tes => ReferencePoint::End,
+                Point::Center => ReferencePoint::Center,
+            };
+            (MatrixMode::ReferencePoint(point), args)
+        }
+        Mode::ScaleRegions {
+            region_body_length,
+            args,
+        } => (
+            MatrixMode::ScaleRegions {
+                body_length: region_body_length,
+            },
+            args,
+        ),
+    };
+    let options = MatrixOptions {
+        mode,
+        upstream: args.upstream,
+        downstream: args.downstream,
+        bin_size: args.bin_size,
+        stat: match args.stat {
+            Stat::Mean => BinStat::Mean,
+            Stat::Min => BinStat::Min,
+            Stat::Max => BinStat::Max,
+            Stat::Sum => BinStat::Sum,
+        },
+        missing_as_zero: args.missing_as_zero,
+    };
+    options.validate()?;
+
+    let regions = read_regions(&args.regions)?;
+    let bigwigs = args
+        .bigwigs
+        .iter()
+        .map(|path| BigWigRead::open_file(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let rows = compute_matrix(&bigwigs, &regions, &options, args.nthreads)?;
+
+    let mut file = BufWriter::new(File::create(&args.output)?);
+    if args.output.ends_with(".gz") {
+        let mut out = GzEncoder::new(file, Compression::default());
+        write_matrix(&mut out, &args.bigwigs, &regions, &rows, options.num_bins())?;
+        out.finish()?.flush()?;
+    } else {
+        write_matrix(
+            &mut file,
+            &args.bigwigs,
+            &regions,
+            &rows,
+            options.num_bins(),
+        )?;
+        file.flush()?;
+    }
+
+    Ok(())
+}
+
+fn write_matrix(
+    out: &mut dyn Write,
+    bigwigs: &[String],
+    regions: &[MatrixRegion],
+    rows: &[Vec<f32>],
+    num_bins: usize,
+) -> io::Result<()> {
+    write!(out, "#chrom\tstart\tend\tname\tstrand")?;
+    for bigwig in bigwigs {
+        for bin in 0..num_bins {
+            write!(out, "\t{}:{}", bigwig, bin)?;
+        }
+    }
+    writeln!(out)?;
+    for (region, row) in regions.iter().zip(rows) {
+        let strand = match region.strand {
+            Strand::Forward => "+",
+            Strand::Reverse => "-",
+            Strand::Unknown => ".",
+        };
+        write!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            region.chrom, region.start, region.end, region.name, strand
+        )?;
+        for value in row {
+            write!(out, "\t{}", value)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn verify_cli_computematrix() {
+    use clap::CommandFactory;
+    Cli::command().debug_assert()
+}
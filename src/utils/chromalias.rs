@@ -0,0 +1,267 @@
+//! Alternate chromosome names, such as `1` for `chr1`, or `MT` for `chrM`.
+//!
+//! A `ChromAliases` can be attached to a `BigWigRead` or `BigBedRead` (with
+//! `set_chrom_aliases`), after which queries using any alias of a chromosome
+//! in the file work as if the name in the file was used. When attached to a
+//! `BigWigWrite` or `BigBedWrite`, input chromosomes missing from the
+//! chromosome sizes are renamed to an alias that is there. Aliases can be read
+//! from UCSC's `chromAlias.txt` and `chromAlias.bb` files, added manually, or
+//! come from built-in rules.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+#[cfg(feature = "read")]
+use crate::bbi::{BBIRead, BBIReadError, BigBedRead, ChromInfo, BIGBED_MAGIC};
+#[cfg(feature = "read")]
+use crate::utils::reopen::SeekableRead;
+
+/// A table of chromosome names that refer to the same chromosome
+#[derive(Clone, Debug, Default)]
+pub struct ChromAliases {
+    // Each group is a set of names for one chromosome
+    groups: Vec<Vec<String>>,
+    // The group each name is in
+    names: HashMap<String, usize>,
+    builtin_rules: bool,
+}
+
+impl ChromAliases {
+    /// Creates an empty table, without the built-in rules.
+    pub fn new() -> Self {
+        ChromAliases::default()
+    }
+
+    /// Creates a table with only the built-in rules: UCSC-style names map to
+    /// Ensembl-style names by removing the `chr` prefix (and vice versa), and
+    /// `chrM` maps to `MT` (or `M`).
+    pub fn builtin() -> Self {
+        ChromAliases {
+            builtin_rules: true,
+            ..ChromAliases::default()
+        }
+    }
+
+    /// Sets whether the built-in rules are used, in addition to any aliases
+    /// that were added.
+    pub fn set_builtin_rules(&mut self, builtin_rules: bool) {
+        self.builtin_rules = builtin_rules;
+    }
+
+    /// Adds a set of names that all refer to the same chromosome. If any of
+    /// them already have aliases, all of the names are merged together.
+    pub fn add<S: AsRef<str>>(&mut self, names: &[S]) {
+        let mut group: Option<usize> = None;
+        for name in names {
+            let Some(&existing) = self.names.get(name.as_ref()) else {
+                continue;
+            };
+            match group {
+                None => group = Some(existing),
+                Some(group) if group != existing => {
+                    let moved = std::mem::take(&mut self.groups[existing]);
+                    for name in &moved {
+                        self.names.insert(name.clone(), group);
+                    }
+                    self.groups[group].extend(moved);
+                }
+                Some(_) => {}
+            }
+        }
+        let group = group.unwrap_or_else(|| {
+            self.groups.push(vec![]);
+            self.groups.len() - 1
+        });
+        for name in names {
+            let name = name.as_ref();
+            if name.is_empty() || self.names.contains_key(name) {
+                continue;
+            }
+            self.names.insert(name.to_string(), group);
+            self.groups[group].push(name.to_string());
+        }
+    }
+
+    /// All the other names for `name`, in order of preference: those that
+    /// were added, then those from the built-in rules.
+    pub fn aliases(&self, name: &str) -> Vec<String> {
+        let mut aliases: Vec<String> = match self.names.get(name) {
+            Some(&group) => self.groups[group]
+                .iter()
+                .filter(|n| *n != name)
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        if self.builtin_rules {
+            let mut ruled = builtin_aliases(name);
+            for alias in &aliases {
+                ruled.extend(builtin_aliases(alias));
+            }
+            for alias in ruled {
+                if alias != name && !aliases.contains(&alias) {
+                    aliases.push(alias);
+                }
+            }
+        }
+        aliases
+    }
+
+    /// Finds the name that `name` refers to, out of those where `known`
+    /// returns `true`: either `name` itself, or its first alias that is known.
+    pub fn find(&self, name: &str, known: impl Fn(&str) -> bool) -> Option<String> {
+        if known(name) {
+            return Some(name.to_string());
+        }
+        self.aliases(name).into_iter().find(|alias| known(alias))
+    }
+
+    /// Finds the chromosome in `chroms` that `name` refers to: either the one
+    /// with that exact name, or the first alias that is in `chroms`.
+    #[cfg(feature = "read")]
+    pub fn resolve<'a>(&self, name: &str, chroms: &'a [ChromInfo]) -> Option<&'a ChromInfo> {
+        if let Some(chrom) = chroms.iter().find(|c| c.name == name) {
+            return Some(chrom);
+        }
+        self.aliases(name)
+            .iter()
+            .find_map(|alias| chroms.iter().find(|c| &c.name == alias))
+    }
+
+    /// Reads a UCSC `chromAlias.txt` file. Current files start with a `#`
+    /// header naming the sources, and have one chromosome per line with its
+    /// names from each source (possibly empty). Older files, without the
+    /// header, instead have one alias per line: the alias, the UCSC name,
+    /// and the source.
+    pub fn read_chrom_alias_txt<R: BufRead>(file: R) -> io::Result<Self> {
+        let mut aliases = ChromAliases::new();
+        let mut lines = file.lines().peekable();
+        let with_header = match lines.peek() {
+            Some(Ok(l)) => l.starts_with('#'),
+            _ => false,
+        };
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let names: Vec<&str> = line.split('\t').map(|n| n.trim()).collect();
+            match with_header {
+                true => aliases.add(&names),
+                false => aliases.add(&names[..names.len().min(2)]),
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// Reads a UCSC `chromAlias.bb` file. Each item covers a whole
+    /// chromosome, with the extra columns having its names from each source.
+    #[cfg(feature = "read")]
+    pub fn read_chrom_alias_bigbed<R: SeekableRead>(
+        bigbed: &mut BigBedRead<R>,
+    ) -> Result<Self, BBIReadError> {
+        let mut aliases = ChromAliases::new();
+        for chrom in bigbed.get_chroms() {
+            for entry in bigbed.get_interval(&chrom.name, 0, chrom.length)? {
+                let entry = entry?;
+                let mut names = vec![chrom.name.as_str()];
+                names.extend(entry.rest.split('\t').map(|n| n.trim()));
+                aliases.add(&names);
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// Reads the aliases at `path`, which can either be a `chromAlias.txt`
+    /// or (with the `read` feature) a `chromAlias.bb` file.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        #[cfg(feature = "read")]
+        {
+            let header = file.fill_buf()?;
+            let is_bigbed = header.len() >= 4 && {
+                let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+                magic == BIGBED_MAGIC || magic == BIGBED_MAGIC.swap_bytes()
+            };
+            if is_bigbed {
+                let to_io = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+                let mut bigbed = BigBedRead::open_file(path).map_err(|e| to_io(e.to_string()))?;
+                return ChromAliases::read_chrom_alias_bigbed(&mut bigbed)
+                    .map_err(|e| to_io(e.to_string()));
+            }
+        }
+        ChromAliases::read_chrom_alias_txt(&mut file)
+    }
+}
+
+fn builtin_aliases(name: &str) -> Vec<String> {
+    match name {
+        "chrM" => vec!["MT".to_string(), "M".to_string()],
+        "MT" | "M" => vec!["chrM".to_string()],
+        _ => match name.strip_prefix("chr") {
+            Some(stripped) if !stripped.is_empty() => vec![stripped.to_string()],
+            _ => vec![format!("chr{}", name)],
+        },
+    }
+}
+
+#[cfg(all(test, feature = "read"))]
+mod tests {
+    use super::*;
+
+    fn chroms(names: &[&str]) -> Vec<ChromInfo> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| ChromInfo {
+                name: name.to_string(),
+                length: 1000,
+                id: id as u32,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let aliases = ChromAliases::builtin();
+        let ucsc = chroms(&["chr1", "chrX", "chrM"]);
+        assert_eq!(aliases.resolve("1", &ucsc).unwrap().name, "chr1");
+        assert_eq!(aliases.resolve("chrX", &ucsc).unwrap().name, "chrX");
+        assert_eq!(aliases.resolve("MT", &ucsc).unwrap().name, "chrM");
+        assert!(aliases.resolve("2", &ucsc).is_none());
+
+        let ensembl = chroms(&["1", "X", "MT"]);
+        assert_eq!(aliases.resolve("chr1", &ensembl).unwrap().name, "1");
+        assert_eq!(aliases.resolve("chrM", &ensembl).unwrap().name, "MT");
+
+        assert!(ChromAliases::new().resolve("1", &ucsc).is_none());
+
+        let known = |n: &str| n == "chr1" || n == "MT";
+        assert_eq!(aliases.find("1", known).as_deref(), Some("chr1"));
+        assert_eq!(aliases.find("chrM", known).as_deref(), Some("MT"));
+        assert_eq!(aliases.find("chr2", known), None);
+    }
+
+    #[test]
+    fn test_chrom_alias_txt() -> io::Result<()> {
+        let txt = b"# ucsc\tassembly\tensembl\tgenbank\n\
+            chr1\t1\t1\tCM000663.2\n\
+            chr1_KI270706v1_random\tHSCHR1_RANDOM_CTG1\t\tKI270706.1\n";
+        let aliases = ChromAliases::read_chrom_alias_txt(&txt[..])?;
+        let ucsc = chroms(&["chr1", "chr1_KI270706v1_random"]);
+        assert_eq!(aliases.resolve("CM000663.2", &ucsc).unwrap().name, "chr1");
+        assert_eq!(
+            aliases.resolve("KI270706.1", &ucsc).unwrap().name,
+            "chr1_KI270706v1_random"
+        );
+        assert_eq!(aliases.aliases("1"), vec!["chr1", "CM000663.2"]);
+
+        // The older format, with a source column
+        let txt = b"1\tchr1\tensembl\nCM000663.1\tchr1\tgenbank\n2\tchr2\tensembl\n";
+        let aliases = ChromAliases::read_chrom_alias_txt(&txt[..])?;
+        assert_eq!(aliases.aliases("CM000663.1"), vec!["1", "chr1"]);
+        assert!(aliases.aliases("ensembl").is_empty());
+
+        Ok(())
+    }
+}
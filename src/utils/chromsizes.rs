@@ -0,0 +1,288 @@
+//! Reading chromosome sizes, as needed to write a bigWig or bigBed.
+//!
+//! Sizes can come from a `chrom.sizes` file (a chromosome and its length per
+//! line), a FASTA index (`.fai`, whose first two columns are the same), the
+//! header of a `.2bit` file, or the chromosomes of an existing bigWig or bigBed.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use thiserror::Error;
+
+#[cfg(feature = "read")]
+use crate::bbi::{read_info, BBIFileReadInfoError};
+#[cfg(feature = "read")]
+use crate::bbi::{BIGBED_MAGIC, BIGWIG_MAGIC};
+
+const TWOBIT_MAGIC: u32 = 0x1A41_2743;
+
+#[derive(Error, Debug)]
+pub enum ChromSizesError {
+    #[error("Invalid chromosome sizes at line {}: {}", .line, .message)]
+    InvalidLine { line: u64, message: String },
+    #[error("Chromosome {} is listed more than once (line {}).", .chrom, .line)]
+    DuplicateChrom { chrom: String, line: u64 },
+    #[error("Invalid 2bit file: {}", .0)]
+    Invalid2Bit(String),
+    #[error("Invalid bigWig or bigBed file: {}", .0)]
+    InvalidBBI(String),
+    #[error("Error occurred: {}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// The formats chromosome sizes can be read from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChromSizesFormat {
+    /// A `chrom.sizes` file or a FASTA index
+    Text,
+    TwoBit,
+    /// A bigWig or bigBed
+    BBI,
+}
+
+impl ChromSizesFormat {
+    /// Guesses the format from the first bytes of a file. Anything that isn't
+    /// a `.2bit`, bigWig or bigBed is assumed to be text.
+    pub fn detect(header: &[u8]) -> ChromSizesFormat {
+        let magic = match header.get(0..4) {
+            Some(m) => u32::from_le_bytes([m[0], m[1], m[2], m[3]]),
+            None => return ChromSizesFormat::Text,
+        };
+        if magic == TWOBIT_MAGIC || magic == TWOBIT_MAGIC.swap_bytes() {
+            return ChromSizesFormat::TwoBit;
+        }
+        #[cfg(feature = "read")]
+        if [BIGWIG_MAGIC, BIGBED_MAGIC]
+            .iter()
+            .any(|m| magic == *m || magic == m.swap_bytes())
+        {
+            return ChromSizesFormat::BBI;
+        }
+        ChromSizesFormat::Text
+    }
+}
+
+/// Reads the chromosome sizes at `path`. If `format` is `None`, it is
+/// detected from the start of the file.
+pub fn open_chrom_sizes(
+    path: &str,
+    format: Option<ChromSizesFormat>,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let format = match format {
+        Some(format) => format,
+        None => ChromSizesFormat::detect(file.fill_buf()?),
+    };
+    match format {
+        ChromSizesFormat::Text => read_chrom_sizes(file),
+        ChromSizesFormat::TwoBit => read_2bit_chrom_sizes(file),
+        #[cfg(feature = "read")]
+        ChromSizesFormat::BBI => read_bbi_chrom_sizes(file),
+        #[cfg(not(feature = "read"))]
+        ChromSizesFormat::BBI => Err(ChromSizesError::InvalidBBI(
+            "Reading bigWigs and bigBeds requires the `read` feature".to_string(),
+        )),
+    }
+}
+
+/// Reads a `chrom.sizes` file or a FASTA index (`.fai`). Each line has a
+/// chromosome name and its length, separated by whitespace. Any further
+/// columns are ignored, as are empty lines and lines starting with `#`.
+pub fn read_chrom_sizes<R: BufRead>(file: R) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let mut sizes = HashMap::new();
+    for (line, l) in (1..).zip(file.lines()) {
+        let l = l?;
+        if l.trim().is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let mut split = l.split_whitespace();
+        let chrom = split.next().unwrap();
+        let size = match split.next() {
+            Some(size) => size,
+            None => {
+                return Err(ChromSizesError::InvalidLine {
+                    line,
+                    message: format!("Missing length for chromosome {}", chrom),
+                });
+            }
+        };
+        let size = size
+            .parse::<u32>()
+            .map_err(|_| ChromSizesError::InvalidLine {
+                line,
+                message: format!("Invalid length for chromosome {}: {}", chrom, size),
+            })?;
+        if sizes.insert(chrom.to_string(), size).is_some() {
+            return Err(ChromSizesError::DuplicateChrom {
+                chrom: chrom.to_string(),
+                line,
+            });
+        }
+    }
+    Ok(sizes)
+}
+
+/// Reads the sequence names and lengths from the header of a `.2bit` file.
+/// The sequences themselves aren't read.
+pub fn read_2bit_chrom_sizes<R: Read + Seek>(
+    mut file: R,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let magic = file.read_u32::<LittleEndian>()?;
+    if magic == TWOBIT_MAGIC {
+        read_2bit_index::<LittleEndian, _>(file)
+    } else if magic == TWOBIT_MAGIC.swap_bytes() {
+        read_2bit_index::<BigEndian, _>(file)
+    } else {
+        Err(ChromSizesError::Invalid2Bit("Invalid magic".to_string()))
+    }
+}
+
+fn read_2bit_index<B: byteorder::ByteOrder, R: Read + Seek>(
+    mut file: R,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let version = file.read_u32::<B>()?;
+    if version > 1 {
+        return Err(ChromSizesError::Invalid2Bit(format!(
+            "Unknown version {}",
+            version
+        )));
+    }
+    let count = file.read_u32::<B>()?;
+    let _reserved = file.read_u32::<B>()?;
+
+    let mut index = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = file.read_u8()?;
+        let mut name = vec![0; len as usize];
+        file.read_exact(&mut name)?;
+        let name = String::from_utf8(name)
+            .map_err(|_| ChromSizesError::Invalid2Bit("Invalid sequence name".to_string()))?;
+        // Version 1 files use 64-bit offsets
+        let offset = match version {
+            0 => file.read_u32::<B>()? as u64,
+            _ => file.read_u64::<B>()?,
+        };
+        index.push((name, offset));
+    }
+
+    let mut sizes = HashMap::with_capacity(index.len());
+    for (name, offset) in index {
+        // Each sequence record starts with its length
+        file.seek(SeekFrom::Start(offset))?;
+        let size = file.read_u32::<B>()?;
+        if sizes.contains_key(&name) {
+            return Err(ChromSizesError::Invalid2Bit(format!(
+                "Sequence {} is listed more than once",
+                name
+            )));
+        }
+        sizes.insert(name, size);
+    }
+    Ok(sizes)
+}
+
+/// Reads the chromosomes of a bigWig or bigBed.
+#[cfg(feature = "read")]
+pub fn read_bbi_chrom_sizes<R: Read + Seek>(
+    mut file: R,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let info = read_info(&mut file).map_err(|e| match e {
+        BBIFileReadInfoError::UnknownMagic => {
+            ChromSizesError::InvalidBBI("Invalid magic".to_string())
+        }
+        BBIFileReadInfoError::InvalidChroms => {
+            ChromSizesError::InvalidBBI("Invalid chromosomes section".to_string())
+        }
+        BBIFileReadInfoError::IoError(e) => ChromSizesError::IoError(e),
+    })?;
+    Ok(info
+        .chrom_info
+        .into_iter()
+        .map(|c| (c.name, c.length))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_chrom_sizes() {
+        let sizes = read_chrom_sizes(&b"chr1\t1000\n\n# comment\nchr2 200\textra\n"[..]).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["chr1"], 1000);
+        assert_eq!(sizes["chr2"], 200);
+
+        // A FASTA index
+        let sizes =
+            read_chrom_sizes(&b"chr1\t1000\t6\t60\t61\nchrM\t16569\t1030\t60\t61\n"[..]).unwrap();
+        assert_eq!(sizes["chrM"], 16569);
+
+        let err = read_chrom_sizes(&b"chr1\t1000\nchr1\t1000\n"[..]).unwrap_err();
+        assert!(matches!(
+            err,
+            ChromSizesError::DuplicateChrom { line: 2, .. }
+        ));
+        let err = read_chrom_sizes(&b"chr1\t-5\n"[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid chromosome sizes at line 1: Invalid length for chromosome chr1: -5"
+        );
+        let err = read_chrom_sizes(&b"chr1\t1000\nchr2\n"[..]).unwrap_err();
+        assert!(matches!(err, ChromSizesError::InvalidLine { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_read_2bit_chrom_sizes() {
+        // A header and index for two sequences, followed by the start of
+        // their records (only the lengths are read)
+        let mut twobit = vec![];
+        twobit.write_u32::<LittleEndian>(TWOBIT_MAGIC).unwrap();
+        twobit.write_u32::<LittleEndian>(0).unwrap();
+        twobit.write_u32::<LittleEndian>(2).unwrap();
+        twobit.write_u32::<LittleEndian>(0).unwrap();
+        let first = 16 + (1 + 4 + 4) + (1 + 4 + 4);
+        for (name, offset) in [("chrA", first), ("chrB", first + 4)] {
+            twobit.write_u8(name.len() as u8).unwrap();
+            twobit.extend_from_slice(name.as_bytes());
+            twobit.write_u32::<LittleEndian>(offset).unwrap();
+        }
+        twobit.write_u32::<LittleEndian>(12345).unwrap();
+        twobit.write_u32::<LittleEndian>(678).unwrap();
+
+        assert_eq!(ChromSizesFormat::detect(&twobit), ChromSizesFormat::TwoBit);
+        let sizes = read_2bit_chrom_sizes(Cursor::new(twobit)).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["chrA"], 12345);
+        assert_eq!(sizes["chrB"], 678);
+
+        assert!(matches!(
+            read_2bit_chrom_sizes(Cursor::new(b"chr1\t1000\n".to_vec())),
+            Err(ChromSizesError::Invalid2Bit(_))
+        ));
+    }
+
+    #[cfg(feature = "read")]
+    #[test]
+    fn test_read_bbi_chrom_sizes() -> Result<(), ChromSizesError> {
+        use crate::BBIRead;
+        use std::path::PathBuf;
+
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("resources/test");
+        dir.push("valid.bigWig");
+
+        let sizes = open_chrom_sizes(&dir.to_string_lossy(), None)?;
+        let bigwig = crate::BigWigRead::open_file(&dir.to_string_lossy()).unwrap();
+        let chroms = bigwig.get_chroms();
+        assert_eq!(sizes.len(), chroms.len());
+        for chrom in chroms {
+            assert_eq!(sizes[&chrom.name], chrom.length);
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::iter::Peekable;
 
 pub trait ChromValues {
     type Value;
@@ -7,3 +8,35 @@ pub trait ChromValues {
     fn next(&mut self) -> Option<Result<Self::Value, Self::Error>>;
     fn peek(&mut self) -> Option<Result<&Self::Value, &Self::Error>>;
 }
+
+/// `ChromValues` from any iterator of values. The iterator is boxed, since
+/// the types of iterators built from adapters are a mess to write out.
+pub struct BoxedChromValues<V, E> {
+    iter: Peekable<Box<dyn Iterator<Item = Result<V, E>> + Send>>,
+}
+
+impl<V, E> BoxedChromValues<V, E> {
+    pub fn new(iter: impl Iterator<Item = Result<V, E>> + Send + 'static) -> Self {
+        let iter: Box<dyn Iterator<Item = Result<V, E>> + Send> = Box::new(iter);
+        BoxedChromValues {
+            iter: iter.peekable(),
+        }
+    }
+}
+
+impl<V, E: Send + From<io::Error> + 'static> ChromValues for BoxedChromValues<V, E> {
+    type Value = V;
+    type Error = E;
+
+    fn next(&mut self) -> Option<Result<V, E>> {
+        self.iter.next()
+    }
+
+    fn peek(&mut self) -> Option<Result<&V, &E>> {
+        match self.iter.peek() {
+            Some(Ok(v)) => Some(Ok(v)),
+            Some(Err(err)) => Some(Err(err)),
+            None => None,
+        }
+    }
+}
@@ -1,4 +1,12 @@
-use crate::bbiwrite::{DEFAULT_BLOCK_SIZE, DEFAULT_ITEMS_PER_SLOT};
+use crate::bbiwrite::{
+    Compression, UnknownChroms, WriteProgress, DEFAULT_BLOCK_SIZE, DEFAULT_COMPRESSION_LEVEL,
+    DEFAULT_ITEMS_PER_SLOT, MAX_COMPRESSION_LEVEL,
+};
+use crate::utils::chromalias::ChromAliases;
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::Mutex;
 
 use clap::Parser;
 
@@ -19,6 +27,13 @@ pub struct BBIWriteArgs {
     #[arg(default_value_t = false)]
     pub uncompressed: bool,
 
+    /// The zlib compression level (0-12) to use. Lower levels are faster, higher levels give smaller files.
+    /// Ignored if `--uncompressed` is set.
+    #[arg(long)]
+    #[arg(default_value_t = DEFAULT_COMPRESSION_LEVEL)]
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=MAX_COMPRESSION_LEVEL as i64))]
+    pub compression_level: u8,
+
     /// Sets whether the input is sorted. Can take `all`, `start`, or `none`.
     /// `all` means that the input bedGraph is sorted by chroms and start (`sort -k1,1 -k2,2n`).
     /// `start` means that the the chroms are out of order but the starts within a chrom is sorted.
@@ -38,6 +53,78 @@ pub struct BBIWriteArgs {
     #[arg(long)]
     #[arg(default_value_t = DEFAULT_ITEMS_PER_SLOT)]
     pub items_per_slot: u32,
+
+    /// A chromosome alias file (a UCSC `chromAlias.txt` or `chromAlias.bb`). Input chromosomes
+    /// that aren't in the chromosome sizes are renamed to an alias that is.
+    #[arg(long)]
+    pub chrom_alias: Option<String>,
+
+    /// Rename input chromosomes that aren't in the chromosome sizes using built-in rules:
+    /// adding or removing the `chr` prefix, and `chrM` to or from `MT`.
+    #[arg(long)]
+    #[arg(default_value_t = false)]
+    pub chrom_alias_rules: bool,
+
+    /// What to do with input chromosomes that aren't in the chromosome sizes (after renaming).
+    /// Can take `error` or `drop`. With `drop`, the number of dropped chromosomes is printed.
+    #[arg(long)]
+    #[arg(default_value = "error")]
+    #[arg(value_parser = ["error", "drop"])]
+    pub unknown_chroms: String,
+}
+
+impl BBIWriteArgs {
+    /// The aliases to rename input chromosomes with, if any were set
+    pub fn chrom_aliases(&self) -> io::Result<Option<ChromAliases>> {
+        let mut aliases = match &self.chrom_alias {
+            Some(path) => ChromAliases::open(path)?,
+            None if self.chrom_alias_rules => ChromAliases::new(),
+            None => return Ok(None),
+        };
+        aliases.set_builtin_rules(self.chrom_alias_rules);
+        Ok(Some(aliases))
+    }
+
+    pub fn compression(&self) -> Compression {
+        match self.uncompressed {
+            true => Compression::None,
+            false => Compression::Zlib {
+                level: self.compression_level,
+            },
+        }
+    }
+
+    pub fn unknown_chroms(&self) -> UnknownChroms {
+        match self.unknown_chroms.as_ref() {
+            "drop" => UnknownChroms::Drop,
+            _ => UnknownChroms::Error,
+        }
+    }
+}
+
+/// Collects the input chromosomes dropped while writing, to warn about them
+/// once writing is done. Chromosomes dropped in multiple passes are only
+/// counted once.
+#[derive(Default)]
+pub struct DroppedChroms(Mutex<HashSet<String>>);
+
+impl DroppedChroms {
+    /// Prints a warning if any chromosomes were dropped
+    pub fn warn(&self) {
+        let dropped = self.0.lock().unwrap().len();
+        if dropped > 0 {
+            eprintln!(
+                "Warning: dropped {} input chromosome(s) that aren't in the chromosome sizes",
+                dropped
+            );
+        }
+    }
+}
+
+impl WriteProgress for DroppedChroms {
+    fn chrom_dropped(&self, chrom: &str) {
+        self.0.lock().unwrap().insert(chrom.to_string());
+    }
 }
 
 #[doc(hidden)]
@@ -0,0 +1,396 @@
+use crate::bbi::{BBIRead, BBIReadError, BigWigRead, Value};
+use crate::utils::file::reopen::SeekableRead;
+use crate::utils::merge::{merge_sections, Combine};
+
+/// The operation used to compare the values of two bigWigs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CompareOp {
+    /// `log2((one + pseudocount) / (two + pseudocount))`
+    Log2Ratio,
+    /// `(one + pseudocount) / (two + pseudocount)`
+    Ratio,
+    /// `one - two`
+    Difference,
+    /// The ratio if it is at least `1`, otherwise the negative of its reciprocal
+    FoldChange,
+}
+
+impl CompareOp {
+    /// Compares two values. The pseudocounts are added to `one` and `two`
+    /// respectively for all operations but `Difference`.
+    pub fn apply(&self, one: f32, two: f32, pseudocounts: (f32, f32)) -> f32 {
+        let ratio = || (one + pseudocounts.0) / (two + pseudocounts.1);
+        match self {
+            CompareOp::Log2Ratio => ratio().log2(),
+            CompareOp::Ratio => ratio(),
+            CompareOp::Difference => one - two,
+            CompareOp::FoldChange => {
+                let ratio = ratio();
+                if ratio >= 1.0 {
+                    ratio
+                } else {
+                    -1.0 / ratio
+                }
+            }
+        }
+    }
+}
+
+/// How intervals where only one bigWig has a value are handled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MissingData {
+    /// Missing values are treated as `0`
+    Zero,
+    /// Intervals where either bigWig is missing a value are skipped
+    Skip,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CompareOptions {
+    pub op: CompareOp,
+    /// The pseudocounts added to the first and second values, respectively
+    pub pseudocounts: (f32, f32),
+    pub missing: MissingData,
+    /// If set, values are averaged over bins of this size before being
+    /// compared. With `MissingData::Zero`, the average is over the whole bin;
+    /// with `MissingData::Skip`, it is over the bases that have a value.
+    pub bin_size: Option<u32>,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        CompareOptions {
+            op: CompareOp::Log2Ratio,
+            pseudocounts: (1.0, 1.0),
+            missing: MissingData::Zero,
+            bin_size: None,
+        }
+    }
+}
+
+// Averages the values of a section over bins of `bin_size`
+struct BinnedIter<I> {
+    inner: I,
+    bin_size: u32,
+    chrom_length: u32,
+    missing: MissingData,
+    // The current bin start, and the sum and number of bases covered
+    bin: Option<(u32, f64, u32)>,
+    // The remainder of a value that extends past the current bin
+    next: Option<Value>,
+}
+
+impl<E, I: Iterator<Item = Result<Value, E>>> Iterator for BinnedIter<I> {
+    type Item = Result<Value, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = match self.next.take() {
+                Some(next) => Some(next),
+                None => match self.inner.next() {
+                    Some(Ok(next)) => Some(next),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => None,
+                },
+            };
+            let next = match next {
+                Some(next) if next.start >= next.end => continue,
+                Some(next) => next,
+                None => {
+                    let bin = self.bin.take()?;
+                    return Some(Ok(self.finish_bin(bin)));
+                }
+            };
+
+            let next_bin_start = next.start - next.start % self.bin_size;
+            let (bin_start, sum, bases) = match self.bin {
+                Some((bin_start, _, _)) if bin_start != next_bin_start => {
+                    let bin = self.bin.take().unwrap();
+                    self.next = Some(next);
+                    return Some(Ok(self.finish_bin(bin)));
+                }
+                Some(ref mut bin) => bin,
+                None => self.bin.insert((next_bin_start, 0.0, 0)),
+            };
+
+            let bin_end = bin_start.saturating_add(self.bin_size);
+            let end = next.end.min(bin_end);
+            *sum += f64::from(next.value) * f64::from(end - next.start);
+            *bases += end - next.start;
+            if next.end > bin_end {
+                self.next = Some(Value {
+                    start: bin_end,
+                    ..next
+                });
+            }
+        }
+    }
+}
+
+impl<I> BinnedIter<I> {
+    fn finish_bin(&self, (bin_start, sum, bases): (u32, f64, u32)) -> Value {
+        let bin_end = bin_start
+            .saturating_add(self.bin_size)
+            .min(self.chrom_length);
+        let value = match self.missing {
+            MissingData::Zero => sum / f64::from(bin_end - bin_start),
+            MissingData::Skip => sum / f64::from(bases),
+        };
+        Value {
+            start: bin_start,
+            end: bin_end,
+            value: value as f32,
+        }
+    }
+}
+
+// Compares two sections with `merge_sections`. The counts are a bitmask of
+// which sections have a value at a base.
+struct CompareCombine(CompareOptions);
+
+impl Combine for CompareCombine {
+    fn accumulate(&self, data: &mut [f32], counts: &mut [u32], section: usize, value: f32) {
+        let options = &self.0;
+        for (d, c) in data.iter_mut().zip(counts.iter_mut()) {
+            if section == 0 {
+                *d = value;
+            } else {
+                let one = if *c & 1 != 0 { *d } else { 0.0 };
+                *d = options.op.apply(one, value, options.pseudocounts);
+            }
+            *c |= 1 << section;
+        }
+    }
+
+    fn finish(&self, value: f32, count: u32, _num_sections: usize) -> Option<f32> {
+        let options = &self.0;
+        let value = match (count, options.missing) {
+            (0b11, _) | (0b10, MissingData::Zero) => value,
+            (0b01, MissingData::Zero) => options.op.apply(value, 0.0, options.pseudocounts),
+            _ => return None,
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+/// Compares the values of two sorted sections of non-overlapping values.
+/// Intervals where neither section has a value, or where the comparison is
+/// not finite (such as a ratio with a zero denominator), are not output.
+///
+/// # Panics
+/// Panics if `options.bin_size` is `Some(0)`.
+pub fn compare_sections<I1, I2, E>(
+    one: I1,
+    two: I2,
+    chrom_length: u32,
+    options: CompareOptions,
+) -> impl Iterator<Item = Result<Value, E>> + Send
+where
+    I1: Iterator<Item = Result<Value, E>> + Send + 'static,
+    I2: Iterator<Item = Result<Value, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    type Section<E> = Box<dyn Iterator<Item = Result<Value, E>> + Send>;
+    let binned = |section: Section<E>| -> Section<E> {
+        match options.bin_size {
+            Some(bin_size) => {
+                assert!(bin_size > 0, "The bin size must be greater than zero.");
+                Box::new(BinnedIter {
+                    inner: section,
+                    bin_size,
+                    chrom_length,
+                    missing: options.missing,
+                    bin: None,
+                    next: None,
+                })
+            }
+            None => section,
+        }
+    };
+    let sections = vec![binned(Box::new(one)), binned(Box::new(two))];
+    let mut compared = merge_sections(sections, CompareCombine(options));
+
+    // Merge adjacent intervals that compare to the same value
+    let mut last: Option<Value> = None;
+    std::iter::from_fn(move || loop {
+        match compared.next() {
+            Some(Ok(v)) => match &mut last {
+                Some(l) if l.end == v.start && l.value == v.value => l.end = v.end,
+                _ => {
+                    if let Some(l) = last.replace(v) {
+                        return Some(Ok(l));
+                    }
+                }
+            },
+            Some(Err(e)) => return Some(Err(e)),
+            None => return last.take().map(Ok),
+        }
+    })
+}
+
+/// Compares the values of two bigWigs over a chromosome. If the chromosome is
+/// only in one of the bigWigs, the other is treated as having no values.
+pub fn compare_bigwigs<R1, R2>(
+    one: BigWigRead<R1>,
+    two: BigWigRead<R2>,
+    chrom: &str,
+    options: CompareOptions,
+) -> Result<impl Iterator<Item = Result<Value, BBIReadError>> + Send, BBIReadError>
+where
+    R1: SeekableRead + Send + 'static,
+    R2: SeekableRead + Send + 'static,
+{
+    let length = |chroms: Vec<crate::ChromInfo>| {
+        chroms
+            .into_iter()
+            .find(|c| c.name == chrom)
+            .map(|c| c.length)
+    };
+    let chrom_length = match (length(one.get_chroms()), length(two.get_chroms())) {
+        (Some(one), Some(two)) if one != two => {
+            return Err(BBIReadError::InvalidChromosome(format!(
+                "{} has different lengths in the two bigWigs ({} and {}).",
+                chrom, one, two
+            )))
+        }
+        (Some(length), _) | (_, Some(length)) => length,
+        (None, None) => return Err(BBIReadError::InvalidChromosome(chrom.to_string())),
+    };
+
+    type Values = Box<dyn Iterator<Item = Result<Value, BBIReadError>> + Send>;
+    fn values<R: SeekableRead + Send + 'static>(
+        bigwig: BigWigRead<R>,
+        chrom: &str,
+        chrom_length: u32,
+    ) -> Result<Values, BBIReadError> {
+        if bigwig.get_chroms().iter().any(|c| c.name == chrom) {
+            Ok(Box::new(bigwig.get_interval_move(
+                chrom,
+                0,
+                chrom_length,
+            )?))
+        } else {
+            Ok(Box::new(std::iter::empty()))
+        }
+    }
+    let one = values(one, chrom, chrom_length)?;
+    let two = values(two, chrom, chrom_length)?;
+    Ok(compare_sections(one, two, chrom_length, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(
+        one: &[(u32, u32, f32)],
+        two: &[(u32, u32, f32)],
+        options: CompareOptions,
+    ) -> Vec<(u32, u32, f32)> {
+        let to_iter = |s: &[(u32, u32, f32)]| {
+            s.iter()
+                .map(|&(start, end, value)| Ok::<_, ()>(Value { start, end, value }))
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        compare_sections(to_iter(one), to_iter(two), 100, options)
+            .map(|v| v.map(|v| (v.start, v.end, v.value)))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compare_ops() {
+        let one = [(0, 10, 3.0), (10, 20, 1.0), (30, 40, 7.0)];
+        let two = [(0, 20, 1.0), (50, 60, 1.0)];
+        let options = |op| CompareOptions {
+            op,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            compare(&one, &two, options(CompareOp::Log2Ratio)),
+            vec![(0, 10, 1.0), (10, 20, 0.0), (30, 40, 3.0), (50, 60, -1.0)]
+        );
+        assert_eq!(
+            compare(&one, &two, options(CompareOp::Ratio)),
+            vec![(0, 10, 2.0), (10, 20, 1.0), (30, 40, 8.0), (50, 60, 0.5)]
+        );
+        assert_eq!(
+            compare(&one, &two, options(CompareOp::Difference)),
+            vec![(0, 10, 2.0), (10, 20, 0.0), (30, 40, 7.0), (50, 60, -1.0)]
+        );
+        assert_eq!(
+            compare(&one, &two, options(CompareOp::FoldChange)),
+            vec![(0, 10, 2.0), (10, 20, 1.0), (30, 40, 8.0), (50, 60, -2.0)]
+        );
+    }
+
+    #[test]
+    fn test_compare_missing_and_pseudocounts() {
+        let one = [(0, 10, 3.0), (10, 20, 1.0), (30, 40, 7.0)];
+        let two = [(0, 20, 1.0), (50, 60, 1.0)];
+
+        let options = CompareOptions {
+            op: CompareOp::Ratio,
+            pseudocounts: (0.0, 0.0),
+            missing: MissingData::Skip,
+            bin_size: None,
+        };
+        assert_eq!(
+            compare(&one, &two, options),
+            vec![(0, 10, 3.0), (10, 20, 1.0)]
+        );
+
+        // 7 / 0 is not finite, and 0 / 1 is
+        let options = CompareOptions {
+            missing: MissingData::Zero,
+            ..options
+        };
+        assert_eq!(
+            compare(&one, &two, options),
+            vec![(0, 10, 3.0), (10, 20, 1.0), (50, 60, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_compare_binned() {
+        let one = [(0, 5, 4.0), (10, 30, 2.0), (95, 100, 1.0)];
+        let two = [(0, 20, 2.0)];
+
+        let options = CompareOptions {
+            op: CompareOp::Difference,
+            bin_size: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(
+            compare(&one, &two, options),
+            vec![(0, 20, 0.0), (20, 40, 1.0), (80, 100, 0.25)]
+        );
+
+        let options = CompareOptions {
+            missing: MissingData::Skip,
+            ..options
+        };
+        assert_eq!(
+            compare(&one, &two, options),
+            vec![(0, 20, (40.0f64 / 15.0) as f32 - 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_compare_long_values() {
+        // Values that span the chunks `merge_sections` works over are not split
+        let one = [(0, 120000, 3.0)];
+        let two = [(60000, 70000, 1.0)];
+
+        let options = CompareOptions {
+            op: CompareOp::Difference,
+            ..Default::default()
+        };
+        assert_eq!(
+            compare(&one, &two, options),
+            vec![(0, 60000, 3.0), (60000, 70000, 2.0), (70000, 120000, 3.0)]
+        );
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bgzf;
+#[cfg(any(feature = "write", feature = "cli"))]
+pub mod gzip;
 pub mod reopen;
 pub mod streaming_linereader;
 pub mod tell;
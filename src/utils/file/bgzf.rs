@@ -0,0 +1,264 @@
+//! Reading of BGZF files: gzip files made of independently compressed blocks
+//! (of at most 64 KiB), as written by `bgzip`. Because each block can be
+//! decompressed on its own, positions in the uncompressed data can be
+//! addressed by a "virtual offset": the (compressed) offset of a block in the
+//! upper 48 bits, and the offset within the uncompressed block in the lower 16.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use libdeflater::Decompressor;
+
+const HEADER_SIZE: usize = 12;
+
+/// Returns whether `header` (the start of a file) is the header of a BGZF block
+pub fn is_bgzf(header: &[u8]) -> bool {
+    // The magic, the deflate method, and the FEXTRA flag, followed by (after
+    // MTIME, XFL, OS and XLEN) the `BC` subfield that has the block size.
+    header.len() >= 16
+        && header[0..4] == [0x1f, 0x8b, 0x08, 0x04]
+        && header[12..16] == [b'B', b'C', 0x02, 0x00]
+}
+
+/// Reads the uncompressed data of a BGZF file
+pub struct BgzfReader<R> {
+    inner: R,
+    decompressor: Decompressor,
+    compressed: Vec<u8>,
+    block: Vec<u8>,
+    pos: usize,
+    // The compressed offset of the current block
+    block_offset: u64,
+    // The compressed offset of the next block
+    next_block_offset: u64,
+}
+
+impl<R: Read> BgzfReader<R> {
+    /// Creates a reader for `inner`, which must be at the start of a block.
+    pub fn new(inner: R) -> Self {
+        BgzfReader {
+            inner,
+            decompressor: Decompressor::new(),
+            compressed: vec![],
+            block: vec![],
+            pos: 0,
+            block_offset: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// The virtual offset of the next byte that will be read
+    pub fn virtual_offset(&self) -> u64 {
+        if self.pos == self.block.len() {
+            // Reads will continue from the next block
+            return self.next_block_offset << 16;
+        }
+        (self.block_offset << 16) | self.pos as u64
+    }
+
+    /// Reads the next block. Returns `false` at the end of the file.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut header = [0; HEADER_SIZE];
+        let mut read = 0;
+        while read < HEADER_SIZE {
+            match self.inner.read(&mut header[read..])? {
+                0 if read == 0 => return Ok(false),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        if header[0..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid BGZF block header",
+            ));
+        }
+        let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0; xlen];
+        self.inner.read_exact(&mut extra)?;
+        let block_size = block_size(&extra).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "BGZF block is missing its size")
+        })?;
+        if block_size < HEADER_SIZE + xlen + 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid BGZF block size",
+            ));
+        }
+
+        self.compressed.clear();
+        self.compressed.extend_from_slice(&header);
+        self.compressed.extend_from_slice(&extra);
+        self.compressed.resize(block_size, 0);
+        self.inner
+            .read_exact(&mut self.compressed[HEADER_SIZE + xlen..])?;
+        let isize = &self.compressed[block_size - 4..];
+        let uncompressed_size = u32::from_le_bytes([isize[0], isize[1], isize[2], isize[3]]);
+
+        self.block.resize(uncompressed_size as usize, 0);
+        self.decompressor
+            .gzip_decompress(&self.compressed, &mut self.block)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.pos = 0;
+        self.block_offset = self.next_block_offset;
+        self.next_block_offset += block_size as u64;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Seeks to a virtual offset (for example, one returned by
+    /// `virtual_offset`).
+    pub fn seek_virtual(&mut self, virtual_offset: u64) -> io::Result<()> {
+        let block_offset = virtual_offset >> 16;
+        let pos = (virtual_offset & 0xffff) as usize;
+        if block_offset != self.block_offset || self.block.is_empty() {
+            self.inner.seek(SeekFrom::Start(block_offset))?;
+            self.block.clear();
+            self.pos = 0;
+            self.next_block_offset = block_offset;
+            if !self.read_block()? && pos == 0 {
+                return Ok(());
+            }
+        }
+        if pos > self.block.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Virtual offset is past the end of its block",
+            ));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// The compressed offsets of every block in the file. Only the block
+    /// headers are read. Afterwards, the reader is at the start of the file.
+    pub fn block_offsets(&mut self) -> io::Result<Vec<u64>> {
+        let mut offsets = vec![];
+        let mut offset = self.inner.seek(SeekFrom::Start(0))?;
+        loop {
+            let mut header = [0; HEADER_SIZE];
+            match self.inner.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+            let mut extra = vec![0; xlen];
+            self.inner.read_exact(&mut extra)?;
+            let block_size = block_size(&extra).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "BGZF block is missing its size")
+            })?;
+            offsets.push(offset);
+            offset += block_size as u64;
+            self.inner.seek(SeekFrom::Start(offset))?;
+        }
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.block.clear();
+        self.pos = 0;
+        self.block_offset = 0;
+        self.next_block_offset = 0;
+        Ok(offsets)
+    }
+}
+
+// Finds the total block size in the `BC` subfield of the extra field
+fn block_size(mut extra: &[u8]) -> Option<usize> {
+    while extra.len() >= 4 {
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        if extra[0..2] == [b'B', b'C'] && len == 2 && extra.len() >= 6 {
+            return Some(u16::from_le_bytes([extra[4], extra[5]]) as usize + 1);
+        }
+        extra = extra.get(4 + len..)?;
+    }
+    None
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // Blocks may be empty (such as the end-of-file marker block)
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.block.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libdeflater::{CompressionLvl, Compressor};
+    use std::io::Cursor;
+
+    // Writes `data` as BGZF blocks of (at most) `block_size` uncompressed
+    // bytes, followed by the empty end-of-file block
+    fn bgzf(data: &[u8], block_size: usize) -> Vec<u8> {
+        let mut compressor = Compressor::new(CompressionLvl::default());
+        let mut out = vec![];
+        for chunk in data.chunks(block_size).chain(std::iter::once(&[][..])) {
+            let mut deflated = vec![0; compressor.deflate_compress_bound(chunk.len())];
+            let size = compressor.deflate_compress(chunk, &mut deflated).unwrap();
+            let bsize = (HEADER_SIZE + 6 + size + 8 - 1) as u16;
+            out.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0]);
+            out.extend_from_slice(&[b'B', b'C', 2, 0]);
+            out.extend_from_slice(&bsize.to_le_bytes());
+            out.extend_from_slice(&deflated[..size]);
+            out.extend_from_slice(&libdeflater::crc32(chunk).to_le_bytes());
+            out.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_bgzf_reader() -> io::Result<()> {
+        let data: Vec<u8> = (0..1000)
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect();
+        let compressed = bgzf(&data, 100);
+        assert!(is_bgzf(&compressed));
+
+        let mut reader = BgzfReader::new(Cursor::new(compressed));
+        let mut read = vec![];
+        reader.read_to_end(&mut read)?;
+        assert_eq!(read, data);
+
+        let offsets = reader.block_offsets()?;
+        // One block per 100 bytes, and the end-of-file block
+        assert_eq!(offsets.len(), data.len().div_ceil(100) + 1);
+
+        // Remember the virtual offset of each line, then seek back to them
+        let mut lines = vec![];
+        loop {
+            let offset = reader.virtual_offset();
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            lines.push((offset, line));
+        }
+        assert_eq!(lines.len(), 1000);
+        for (offset, line) in lines.iter().rev().step_by(7) {
+            reader.seek_virtual(*offset)?;
+            let mut read = String::new();
+            reader.read_line(&mut read)?;
+            assert_eq!(&read, line);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::utils::file::bgzf::{is_bgzf, BgzfReader};
+
+/// The compression of a file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    /// Gzip compressed in independent blocks (see `bgzf`), which can be seeked
+    Bgzf,
+}
+
+/// A reader that transparently decompresses gzip (including BGZF) data. The
+/// compression is detected from the first bytes, and uncompressed data is
+/// read as-is.
+pub enum GzipReader<R: Read> {
+    Plain(BufReader<R>),
+    Gzip(Box<BufReader<MultiGzDecoder<BufReader<R>>>>),
+    Bgzf(BgzfReader<BufReader<R>>),
+}
+
+impl<R: Read> GzipReader<R> {
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut inner = BufReader::new(inner);
+        let header = inner.fill_buf()?;
+        Ok(if is_bgzf(header) {
+            GzipReader::Bgzf(BgzfReader::new(inner))
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            GzipReader::Gzip(Box::new(BufReader::new(MultiGzDecoder::new(inner))))
+        } else {
+            GzipReader::Plain(inner)
+        })
+    }
+
+    pub fn compression(&self) -> Compression {
+        match self {
+            GzipReader::Plain(_) => Compression::None,
+            GzipReader::Gzip(_) => Compression::Gzip,
+            GzipReader::Bgzf(_) => Compression::Bgzf,
+        }
+    }
+}
+
+impl<R: Read + Seek> GzipReader<R> {
+    /// Seeks to `offset`, which is a byte offset for uncompressed data and a
+    /// virtual offset for BGZF data. Other gzip data can't be seeked.
+    pub fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        match self {
+            GzipReader::Plain(inner) => inner.seek(SeekFrom::Start(offset)).map(|_| ()),
+            GzipReader::Gzip(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Only BGZF compressed files (such as those written by `bgzip`) can be seeked.",
+            )),
+            GzipReader::Bgzf(inner) => inner.seek_virtual(offset),
+        }
+    }
+}
+
+impl<R: Read> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            GzipReader::Plain(inner) => inner.read(buf),
+            GzipReader::Gzip(inner) => inner.read(buf),
+            GzipReader::Bgzf(inner) => inner.read(buf),
+        }
+    }
+}
+
+impl<R: Read> BufRead for GzipReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            GzipReader::Plain(inner) => inner.fill_buf(),
+            GzipReader::Gzip(inner) => inner.fill_buf(),
+            GzipReader::Bgzf(inner) => inner.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            GzipReader::Plain(inner) => inner.consume(amt),
+            GzipReader::Gzip(inner) => inner.consume(amt),
+            GzipReader::Bgzf(inner) => inner.consume(amt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    #[test]
+    fn test_gzip_reader() {
+        // Two gzip members, which are read as one file
+        let mut compressed = vec![];
+        for line in [&b"first\n"[..], b"second\n"] {
+            let mut writer = GzEncoder::new(vec![], flate2::Compression::default());
+            writer.write_all(line).unwrap();
+            compressed.extend(writer.finish().unwrap());
+        }
+
+        let mut reader = GzipReader::new(&compressed[..]).unwrap();
+        assert_eq!(reader.compression(), Compression::Gzip);
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "first\nsecond\n");
+
+        let mut reader = GzipReader::new(&b"first\n"[..]).unwrap();
+        assert_eq!(reader.compression(), Compression::None);
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "first\n");
+    }
+}
@@ -1,5 +1,8 @@
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{self, Read, Seek};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
 
 /// A helper trait that for things that implement `Read`, `Seek`, and `Send`
 pub trait SeekableRead: Seek + Read {}
@@ -52,3 +55,248 @@ impl Read for ReopenableFile {
         self.file.read_exact(buf)
     }
 }
+
+// The handle of a `PooledFile`, which the pool closes when the slot is
+// needed for another file
+type FileSlot = Arc<Mutex<Option<File>>>;
+
+struct FilePoolState {
+    max_open: usize,
+    // Least recently opened first. Slots of dropped files are removed lazily.
+    open: VecDeque<Weak<Mutex<Option<File>>>>,
+}
+
+/// A bounded pool of file handles shared by `PooledFile`s. At most `max_open`
+/// files are open at any time. Each `PooledFile` keeps its handle between
+/// reads (such as for all the reads of a chromosome), until the pool needs
+/// the slot for another file: then the least recently opened handle that
+/// isn't being read is closed, and is reopened when that file is next read.
+#[derive(Clone)]
+pub struct FilePool {
+    inner: Arc<(Mutex<FilePoolState>, Condvar)>,
+}
+
+impl FilePool {
+    /// # Panics
+    /// Panics if `max_open` is `0`.
+    pub fn new(max_open: usize) -> Self {
+        assert!(
+            max_open > 0,
+            "A file pool must allow at least one open file."
+        );
+        FilePool {
+            inner: Arc::new((
+                Mutex::new(FilePoolState {
+                    max_open,
+                    open: VecDeque::new(),
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Returns a `PooledFile` for `path`. The file is opened to check that it
+    /// exists, and its handle is kept for the first reads.
+    pub fn open(&self, path: &str) -> io::Result<PooledFile> {
+        let file = PooledFile {
+            pool: self.clone(),
+            path: path.to_string(),
+            pos: 0,
+            slot: Arc::new(Mutex::new(None)),
+        };
+        {
+            let mut handle = file.slot.lock().unwrap();
+            *handle = Some(self.acquire(path, &file.slot)?);
+        }
+        Ok(file)
+    }
+
+    /// Opens `path` for `slot`, which the caller must have locked. If
+    /// `max_open` files are already open, the least recently opened one that
+    /// isn't being read is closed first.
+    fn acquire(&self, path: &str, slot: &FileSlot) -> io::Result<File> {
+        let (lock, cvar) = &*self.inner;
+        let mut state = lock.lock().unwrap();
+        loop {
+            state.open.retain(|s| s.strong_count() > 0);
+            if state.open.len() < state.max_open {
+                break;
+            }
+            // Slots that are locked are being read (or are `slot` itself)
+            let idle = state.open.iter().position(|s| match s.upgrade() {
+                Some(s) => s.try_lock().map(|mut f| f.take()).is_ok(),
+                None => true,
+            });
+            if let Some(idx) = idle {
+                state.open.remove(idx);
+                break;
+            }
+            // Reads are short, so check again soon even if no file is dropped
+            state = cvar
+                .wait_timeout(state, Duration::from_millis(1))
+                .unwrap()
+                .0;
+        }
+        state.open.push_back(Arc::downgrade(slot));
+        drop(state);
+
+        File::open(path).inspect_err(|_| {
+            let mut state = lock.lock().unwrap();
+            state
+                .open
+                .retain(|s| !std::ptr::eq(s.as_ptr(), Arc::as_ptr(slot)));
+            cvar.notify_one();
+        })
+    }
+}
+
+/// A file that reads through a handle from its `FilePool`. The handle is
+/// kept between reads, unless the pool closes it to open another file.
+pub struct PooledFile {
+    pool: FilePool,
+    pub path: String,
+    pos: u64,
+    slot: FileSlot,
+}
+
+impl PooledFile {
+    fn with_file<T>(
+        &mut self,
+        f: impl FnOnce(&mut File) -> io::Result<T>,
+        read: impl FnOnce(&T) -> u64,
+    ) -> io::Result<T> {
+        let mut handle = self.slot.lock().unwrap();
+        let file = match &mut *handle {
+            Some(file) => file,
+            None => {
+                let mut file = self.pool.acquire(&self.path, &self.slot)?;
+                file.seek(SeekFrom::Start(self.pos))?;
+                handle.insert(file)
+            }
+        };
+        match f(file) {
+            Ok(res) => {
+                self.pos += read(&res);
+                Ok(res)
+            }
+            Err(e) => {
+                // Part of the data may have been read
+                self.pos = file.stream_position()?;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for PooledFile {
+    fn drop(&mut self) {
+        // Dropping the handle frees its slot for any file waiting for one
+        self.slot.lock().unwrap().take();
+        self.pool.inner.1.notify_all();
+    }
+}
+
+impl Reopen for PooledFile {
+    fn reopen(&self) -> io::Result<Self> {
+        Ok(PooledFile {
+            pool: self.pool.clone(),
+            path: self.path.clone(),
+            pos: 0,
+            slot: Arc::new(Mutex::new(None)),
+        })
+    }
+}
+
+impl Seek for PooledFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid seek to a negative or overflowing position",
+                )
+            })?,
+            SeekFrom::End(_) => {
+                let end = self.with_file(|file| file.seek(pos), |_| 0)?;
+                self.pos = end;
+                return Ok(end);
+            }
+        };
+        if new_pos != self.pos {
+            // Seek the open handle (if any) too, so it stays at `pos`
+            if let Some(file) = &mut *self.slot.lock().unwrap() {
+                file.seek(SeekFrom::Start(new_pos))?;
+            }
+            self.pos = new_pos;
+        }
+        Ok(self.pos)
+    }
+}
+
+impl Read for PooledFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_file(|file| file.read(buf), |read| *read as u64)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let len = buf.len() as u64;
+        self.with_file(|file| file.read_exact(buf), |_| len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn open_files(pool: &FilePool) -> usize {
+        let state = pool.inner.0.lock().unwrap();
+        state.open.iter().filter(|s| s.strong_count() > 0).count()
+    }
+
+    #[test]
+    fn test_file_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = vec![];
+        for i in 0..5u8 {
+            let path = dir.path().join(format!("{}.txt", i));
+            File::create(&path).unwrap().write_all(&[i; 10]).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+        }
+
+        let pool = FilePool::new(2);
+        let mut files: Vec<PooledFile> = paths.iter().map(|p| pool.open(p).unwrap()).collect();
+        for _ in 0..2 {
+            for (i, file) in files.iter_mut().enumerate() {
+                let mut buf = [0u8; 3];
+                file.read_exact(&mut buf).unwrap();
+                assert_eq!(buf, [i as u8; 3]);
+                assert!(open_files(&pool) <= 2);
+            }
+        }
+        assert_eq!(files[0].seek(SeekFrom::End(-1)).unwrap(), 9);
+        assert_eq!(files[0].seek(SeekFrom::Current(-3)).unwrap(), 6);
+        let mut rest = vec![];
+        files[0].read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![0u8; 4]);
+        assert!(open_files(&pool) <= 2);
+
+        // A file keeps its handle while no other file needs the slot, so it
+        // can still be read after it's removed
+        #[cfg(unix)]
+        {
+            let mut buf = [0u8; 2];
+            files[4].read_exact(&mut buf).unwrap();
+            std::fs::remove_file(&paths[4]).unwrap();
+            files[4].read_exact(&mut buf).unwrap();
+            assert_eq!(buf, [4u8; 2]);
+        }
+
+        assert!(pool.open("/does/not/exist").is_err());
+        assert_eq!(open_files(&pool), 1);
+
+        drop(files);
+        assert_eq!(open_files(&pool), 0);
+    }
+}
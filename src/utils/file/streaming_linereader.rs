@@ -1,9 +1,64 @@
+use std::fmt;
 use std::io::{self, BufRead};
 
+/// Where the line numbers of a `LinePosition` count from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineStart {
+    /// The start of the file
+    File,
+    /// The first line of a chromosome, when reading started in the middle of
+    /// a file (such as when chromosomes are read in parallel). For BGZF
+    /// files, `virtual_offset` is the virtual offset of that line.
+    Chrom {
+        chrom: String,
+        virtual_offset: Option<u64>,
+    },
+}
+
+/// The position of a line in a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinePosition {
+    /// The (1-based) line number, counted from `start`
+    pub line: u64,
+    pub start: LineStart,
+    /// The byte offset of the start of the line, if known. It isn't known
+    /// when reading BGZF data from the middle of a file.
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for LinePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let LineStart::Chrom { chrom, .. } = &self.start {
+            write!(f, " of chromosome {}", chrom)?;
+        }
+        match (&self.start, self.offset) {
+            (_, Some(offset)) => write!(f, " (byte offset {})", offset),
+            (
+                LineStart::Chrom {
+                    virtual_offset: Some(virtual_offset),
+                    ..
+                },
+                None,
+            ) => write!(
+                f,
+                " (the chromosome starts at virtual offset {})",
+                virtual_offset
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StreamingLineReader<B> {
     current_line: String,
     buf_read: B,
+    lines_read: u64,
+    line_offset: u64,
+    next_offset: u64,
+    start: LineStart,
+    offsets_known: bool,
 }
 
 impl<B: BufRead> StreamingLineReader<B> {
@@ -11,6 +66,39 @@ impl<B: BufRead> StreamingLineReader<B> {
         StreamingLineReader {
             current_line: String::new(),
             buf_read: bf,
+            lines_read: 0,
+            line_offset: 0,
+            next_offset: 0,
+            start: LineStart::File,
+            offsets_known: true,
+        }
+    }
+
+    /// Creates a reader for `bf`, which has already been seeked to `offset`,
+    /// the first line of `chrom`. Lines are counted from there.
+    pub fn for_chrom(bf: B, chrom: String, offset: u64) -> StreamingLineReader<B> {
+        StreamingLineReader {
+            line_offset: offset,
+            next_offset: offset,
+            start: LineStart::Chrom {
+                chrom,
+                virtual_offset: None,
+            },
+            ..StreamingLineReader::new(bf)
+        }
+    }
+
+    /// Creates a reader for BGZF data in `bf`, which has already been seeked
+    /// to `virtual_offset`, the first line of `chrom`. Lines are counted from
+    /// there, but byte offsets aren't tracked.
+    pub fn for_bgzf_chrom(bf: B, chrom: String, virtual_offset: u64) -> StreamingLineReader<B> {
+        StreamingLineReader {
+            start: LineStart::Chrom {
+                chrom,
+                virtual_offset: Some(virtual_offset),
+            },
+            offsets_known: false,
+            ..StreamingLineReader::new(bf)
         }
     }
 
@@ -18,17 +106,36 @@ impl<B: BufRead> StreamingLineReader<B> {
         self.current_line.clear();
         match self.buf_read.read_line(&mut self.current_line) {
             Ok(size) if size == 0 => None,
-            Ok(_) => Some(Ok(&self.current_line)),
+            Ok(size) => {
+                self.lines_read += 1;
+                self.line_offset = self.next_offset;
+                self.next_offset += size as u64;
+                Some(Ok(&self.current_line))
+            }
             Err(e) => Some(Err(e)),
         }
     }
+
+    /// The line last returned by `read`
+    pub fn current_line(&self) -> &str {
+        &self.current_line
+    }
+
+    /// The position of the line last returned by `read`
+    pub fn position(&self) -> LinePosition {
+        LinePosition {
+            line: self.lines_read,
+            start: self.start.clone(),
+            offset: self.offsets_known.then_some(self.line_offset),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs::File;
-    use std::io::{self, BufReader};
+    use std::io::{self, BufReader, Seek, SeekFrom};
     use std::path::PathBuf;
 
     #[test]
@@ -41,6 +148,14 @@ mod tests {
         let mut slr = StreamingLineReader::new(bf);
         assert_eq!("chr17\t1\t100\t0.5\n", slr.read().unwrap().unwrap());
         assert_eq!("chr17\t101\t200\t0.5\n", slr.read().unwrap().unwrap());
+        assert_eq!(
+            slr.position(),
+            LinePosition {
+                line: 2,
+                start: LineStart::File,
+                offset: Some(16)
+            }
+        );
         assert_eq!("chr17\t201\t300\t0.5\n", slr.read().unwrap().unwrap());
         assert_eq!("chr18\t1\t100\t0.5\n", slr.read().unwrap().unwrap());
         assert_eq!("chr18\t101\t200\t0.5\n", slr.read().unwrap().unwrap());
@@ -48,4 +163,35 @@ mod tests {
         assert!(slr.read().is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_chrom_position() -> io::Result<()> {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("resources/test");
+        dir.push("small.bedGraph");
+        let mut f = File::open(dir)?;
+        f.seek(SeekFrom::Start(52))?;
+        let bf = BufReader::new(f);
+        let mut slr = StreamingLineReader::for_chrom(bf, "chr18".to_string(), 52);
+        assert_eq!("chr18\t1\t100\t0.5\n", slr.read().unwrap().unwrap());
+        assert_eq!("chr18\t101\t200\t0.5\n", slr.read().unwrap().unwrap());
+        assert_eq!(
+            slr.position().to_string(),
+            "line 2 of chromosome chr18 (byte offset 68)"
+        );
+
+        let position = LinePosition {
+            line: 3,
+            start: LineStart::Chrom {
+                chrom: "chr2".to_string(),
+                virtual_offset: Some(1310720),
+            },
+            offset: None,
+        };
+        assert_eq!(
+            position.to_string(),
+            "line 3 of chromosome chr2 (the chromosome starts at virtual offset 1310720)"
+        );
+        Ok(())
+    }
 }
@@ -165,16 +165,9 @@ impl<R> Drop for TempFileBufferWriter<R> {
     fn drop(&mut self) {
         let &(ref lock, ref cvar) = &*self.closed;
         let mut closed = lock.lock().unwrap();
+        // Even if nothing was written, the buffer is closed
         let buffer_state = std::mem::replace(&mut self.buffer_state, BufferState::NotStarted);
-        match buffer_state {
-            BufferState::NotStarted => {}
-            BufferState::Temp(temp) => {
-                *closed = Some(BufferState::Temp(temp));
-            }
-            BufferState::Real(real) => {
-                *closed = Some(BufferState::Real(real));
-            }
-        }
+        *closed = Some(buffer_state);
         cvar.notify_one();
         drop(closed);
     }
@@ -234,4 +227,14 @@ mod tests {
         assert_eq!(out_bytes.len(), NUM_BYTES, "All bytes not accounted for.");
         Ok(())
     }
+
+    #[test]
+    fn test_nothing_written() -> io::Result<()> {
+        let (mut buf, writer) = TempFileBuffer::new();
+        let _writethread = std::thread::spawn(move || drop(writer));
+
+        buf.switch(Vec::<u8>::new());
+        assert!(buf.await_real_file().is_empty());
+        Ok(())
+    }
 }
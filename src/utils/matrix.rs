@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::io;
+use std::thread;
+
+use thiserror::Error;
+
+use crate::bbi::{BBIRead, BBIReadError, BigWigRead, Value};
+use crate::bed::bedrecord::Strand;
+use crate::utils::file::reopen::{Reopen, SeekableRead};
+
+/// A region to compute a matrix row for. Regions with an unknown strand are
+/// treated as being on the forward strand.
+#[derive(Clone, Debug)]
+pub struct MatrixRegion {
+    pub chrom: String,
+    pub start: u32,
+    pub end: u32,
+    pub name: String,
+    pub strand: Strand,
+}
+
+/// The point of each region that bins are relative to, with respect to the
+/// region's strand (so `Start` is the TSS of a gene).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReferencePoint {
+    Start,
+    End,
+    Center,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MatrixMode {
+    /// Bins are upstream and downstream of a reference point of each region
+    ReferencePoint(ReferencePoint),
+    /// Each region is scaled to `body_length` bases, with bins upstream of its
+    /// start and downstream of its end
+    ScaleRegions { body_length: u32 },
+}
+
+/// The statistic of each bin, over the bases in the bin with a value.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinStat {
+    Mean,
+    Min,
+    Max,
+    Sum,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MatrixOptions {
+    pub mode: MatrixMode,
+    pub upstream: u32,
+    pub downstream: u32,
+    pub bin_size: u32,
+    pub stat: BinStat,
+    /// If set, bins without values are `0` rather than `NaN`
+    pub missing_as_zero: bool,
+}
+
+impl MatrixOptions {
+    pub fn validate(&self) -> Result<(), MatrixError> {
+        let mut lengths = vec![("upstream", self.upstream), ("downstream", self.downstream)];
+        if let MatrixMode::ScaleRegions { body_length } = self.mode {
+            lengths.push(("region body", body_length));
+        }
+        if self.bin_size == 0 {
+            return Err(MatrixError::InvalidOptions(
+                "The bin size must be greater than zero.".to_string(),
+            ));
+        }
+        for (name, length) in lengths {
+            if length % self.bin_size != 0 {
+                return Err(MatrixError::InvalidOptions(format!(
+                    "The {} length ({}) must be a multiple of the bin size ({}).",
+                    name, length, self.bin_size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of bins in each region, for each bigWig
+    pub fn num_bins(&self) -> usize {
+        let body = match self.mode {
+            MatrixMode::ReferencePoint(_) => 0,
+            MatrixMode::ScaleRegions { body_length } => body_length / self.bin_size,
+        };
+        (self.upstream / self.bin_size + body + self.downstream / self.bin_size) as usize
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MatrixError {
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    InvalidOptions(String),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// The genomic `(start, end)` of each bin of a region, from 5' to 3'. Bins may
+/// extend outside of the chromosome.
+fn region_bins(region: &MatrixRegion, options: &MatrixOptions) -> Vec<(i64, i64)> {
+    let reverse = region.strand == Strand::Reverse;
+    let (start, end) = (i64::from(region.start), i64::from(region.end));
+    let (five_prime, three_prime) = if reverse { (end, start) } else { (start, end) };
+    // Maps a range relative to `at`, in the direction of the strand, to genomic coordinates
+    let to_genomic = |at: i64, (a, b): (i64, i64)| {
+        if reverse {
+            (at - b, at - a)
+        } else {
+            (at + a, at + b)
+        }
+    };
+    let bin_size = i64::from(options.bin_size);
+    let flank = |length: u32, upstream: bool| {
+        let length = i64::from(length);
+        (0..length / bin_size).map(move |i| {
+            let a = if upstream { -length } else { 0 } + i * bin_size;
+            (a, a + bin_size)
+        })
+    };
+
+    let mut bins = Vec::with_capacity(options.num_bins());
+    match options.mode {
+        MatrixMode::ReferencePoint(point) => {
+            let at = match point {
+                ReferencePoint::Start => five_prime,
+                ReferencePoint::End => three_prime,
+                ReferencePoint::Center => start + (end - start) / 2,
+            };
+            bins.extend(flank(options.upstream, true).map(|bin| to_genomic(at, bin)));
+            bins.extend(flank(options.downstream, false).map(|bin| to_genomic(at, bin)));
+        }
+        MatrixMode::ScaleRegions { body_length } => {
+            bins.extend(flank(options.upstream, true).map(|bin| to_genomic(five_prime, bin)));
+            let length = end - start;
+            let body_bins = i64::from(body_length) / bin_size;
+            bins.extend((0..body_bins).map(|i| {
+                let a = length * i / body_bins;
+                let b = length * (i + 1) / body_bins;
+                to_genomic(five_prime, (a, b))
+            }));
+            bins.extend(flank(options.downstream, false).map(|bin| to_genomic(three_prime, bin)));
+        }
+    }
+    bins
+}
+
+fn bin_values<R: SeekableRead>(
+    bigwig: &mut BigWigRead<R>,
+    chrom_length: Option<u32>,
+    region: &MatrixRegion,
+    options: &MatrixOptions,
+) -> Result<Vec<f32>, BBIReadError> {
+    let missing = if options.missing_as_zero {
+        0.0
+    } else {
+        f32::NAN
+    };
+    let bins = region_bins(region, options);
+    let chrom_length = match chrom_length {
+        Some(length) => i64::from(length),
+        // Bigwigs without the chromosome have no values
+        None => return Ok(vec![missing; bins.len()]),
+    };
+    let bins: Vec<(u32, u32)> = bins
+        .into_iter()
+        .map(|(start, end)| {
+            (
+                start.clamp(0, chrom_length) as u32,
+                end.clamp(0, chrom_length) as u32,
+            )
+        })
+        .collect();
+
+    let query_start = bins.iter().map(|b| b.0).min().unwrap_or(0);
+    let query_end = bins.iter().map(|b| b.1).max().unwrap_or(0);
+    let values: Vec<Value> = if query_start < query_end {
+        bigwig
+            .get_interval(&region.chrom, query_start, query_end)?
+            .collect::<Result<_, _>>()?
+    } else {
+        vec![]
+    };
+
+    let bin_values = bins
+        .into_iter()
+        .map(|(start, end)| {
+            let first = values.partition_point(|v| v.end <= start);
+            let mut bases = 0;
+            let mut sum = 0.0f64;
+            let mut min = f32::INFINITY;
+            let mut max = f32::NEG_INFINITY;
+            for v in values[first..].iter().take_while(|v| v.start < end) {
+                let overlap = v.end.min(end) - v.start.max(start);
+                bases += overlap;
+                sum += f64::from(v.value) * f64::from(overlap);
+                min = min.min(v.value);
+                max = max.max(v.value);
+            }
+            if bases == 0 {
+                return missing;
+            }
+            match options.stat {
+                BinStat::Mean => (sum / f64::from(bases)) as f32,
+                BinStat::Min => min,
+                BinStat::Max => max,
+                BinStat::Sum => sum as f32,
+            }
+        })
+        .collect();
+    Ok(bin_values)
+}
+
+/// Gets the binned values of `bigwig` for a region, from 5' to 3'. Bins
+/// without values (including those outside of the chromosome) are `NaN`,
+/// unless `options.missing_as_zero` is set.
+pub fn region_values<R: SeekableRead>(
+    bigwig: &mut BigWigRead<R>,
+    region: &MatrixRegion,
+    options: &MatrixOptions,
+) -> Result<Vec<f32>, BBIReadError> {
+    let chrom_length = bigwig
+        .get_chroms()
+        .into_iter()
+        .find(|c| c.name == region.chrom)
+        .map(|c| c.length);
+    bin_values(bigwig, chrom_length, region, options)
+}
+
+/// Computes a matrix with one row per region. Each row has the binned values
+/// (see `region_values`) of each bigWig, one after another. Regions are split
+/// between `nthreads` threads, each with its own reopened bigWigs.
+pub fn compute_matrix<R>(
+    bigwigs: &[BigWigRead<R>],
+    regions: &[MatrixRegion],
+    options: &MatrixOptions,
+    nthreads: usize,
+) -> Result<Vec<Vec<f32>>, MatrixError>
+where
+    R: Reopen + SeekableRead + Send,
+{
+    options.validate()?;
+    let chunk_size = regions.len().div_ceil(nthreads.max(1)).max(1);
+    let chunks = regions
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let bigwigs = bigwigs
+                .iter()
+                .map(|b| b.reopen())
+                .collect::<io::Result<Vec<_>>>()?;
+            Ok((chunk, bigwigs))
+        })
+        .collect::<Result<Vec<_>, MatrixError>>()?;
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|(chunk, mut bigwigs)| {
+                scope.spawn(move || -> Result<Vec<Vec<f32>>, MatrixError> {
+                    let chrom_lengths: Vec<HashMap<String, u32>> = bigwigs
+                        .iter()
+                        .map(|b| {
+                            b.get_chroms()
+                                .into_iter()
+                                .map(|c| (c.name, c.length))
+                                .collect()
+                        })
+                        .collect();
+                    chunk
+                        .iter()
+                        .map(|region| {
+                            let mut row = Vec::with_capacity(options.num_bins() * bigwigs.len());
+                            for (bigwig, lengths) in bigwigs.iter_mut().zip(chrom_lengths.iter()) {
+                                let length = lengths.get(&region.chrom).copied();
+                                row.extend(bin_values(bigwig, length, region, options)?);
+                            }
+                            Ok(row)
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+
+        let mut rows = Vec::with_capacity(regions.len());
+        for handle in handles {
+            rows.extend(handle.join().expect("Matrix thread panicked.")?);
+        }
+        Ok(rows)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start: u32, end: u32, strand: Strand) -> MatrixRegion {
+        MatrixRegion {
+            chrom: "chr1".to_string(),
+            start,
+            end,
+            name: "region".to_string(),
+            strand,
+        }
+    }
+
+    #[test]
+    fn test_region_bins() {
+        let options = MatrixOptions {
+            mode: MatrixMode::ReferencePoint(ReferencePoint::Start),
+            upstream: 20,
+            downstream: 10,
+            bin_size: 10,
+            stat: BinStat::Mean,
+            missing_as_zero: false,
+        };
+        assert_eq!(options.num_bins(), 3);
+        assert_eq!(
+            region_bins(&region(100, 200, Strand::Forward), &options),
+            vec![(80, 90), (90, 100), (100, 110)]
+        );
+        assert_eq!(
+            region_bins(&region(100, 200, Strand::Reverse), &options),
+            vec![(210, 220), (200, 210), (190, 200)]
+        );
+
+        let options = MatrixOptions {
+            mode: MatrixMode::ReferencePoint(ReferencePoint::Center),
+            ..options
+        };
+        assert_eq!(
+            region_bins(&region(100, 200, Strand::Unknown), &options),
+            vec![(130, 140), (140, 150), (150, 160)]
+        );
+
+        let options = MatrixOptions {
+            mode: MatrixMode::ScaleRegions { body_length: 20 },
+            upstream: 10,
+            ..options
+        };
+        assert_eq!(options.num_bins(), 4);
+        assert_eq!(
+            region_bins(&region(100, 200, Strand::Forward), &options),
+            vec![(90, 100), (100, 150), (150, 200), (200, 210)]
+        );
+        assert_eq!(
+            region_bins(&region(100, 200, Strand::Reverse), &options),
+            vec![(200, 210), (150, 200), (100, 150), (90, 100)]
+        );
+
+        let options = MatrixOptions {
+            bin_size: 15,
+            ..options
+        };
+        assert!(options.validate().is_err());
+    }
+
+    fn valid_bigwig() -> BigWigRead<crate::utils::file::reopen::ReopenableFile> {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/valid.bigWig");
+        BigWigRead::open_file(&path.to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn test_bin_values() -> Result<(), BBIReadError> {
+        // chr17 has values from 59898:
+        //   59898-59900: 0.06792, 59900-59947: 0.16627, 59947-59999: 0.85137,
+        //   59999-60044: 0.86883
+        let mut bigwig = valid_bigwig();
+        let options = MatrixOptions {
+            mode: MatrixMode::ReferencePoint(ReferencePoint::Start),
+            upstream: 10,
+            downstream: 20,
+            bin_size: 10,
+            stat: BinStat::Mean,
+            missing_as_zero: false,
+        };
+        let values = region_values(
+            &mut bigwig,
+            &MatrixRegion {
+                chrom: "chr17".to_string(),
+                ..region(59890, 60000, Strand::Forward)
+            },
+            &options,
+        )?;
+        assert!(values[0].is_nan());
+        assert_eq!(values[1..], [0.06792, 0.16627]);
+
+        let options = MatrixOptions {
+            stat: BinStat::Sum,
+            ..options
+        };
+        let values = region_values(
+            &mut bigwig,
+            &MatrixRegion {
+                chrom: "chr17".to_string(),
+                ..region(59890, 60000, Strand::Forward)
+            },
+            &options,
+        )?;
+        assert!(values[0].is_nan());
+        assert_eq!(values[1..], [0.06792 * 2.0, 0.16627 * 10.0]);
+
+        // On the reverse strand, the start is the end of the region
+        let options = MatrixOptions {
+            upstream: 10,
+            downstream: 10,
+            stat: BinStat::Max,
+            ..options
+        };
+        let reverse = MatrixRegion {
+            chrom: "chr17".to_string(),
+            ..region(59900, 60000, Strand::Reverse)
+        };
+        assert_eq!(
+            region_values(&mut bigwig, &reverse, &options)?,
+            [0.86883, 0.86883]
+        );
+        let options = MatrixOptions {
+            stat: BinStat::Min,
+            ..options
+        };
+        assert_eq!(
+            region_values(&mut bigwig, &reverse, &options)?,
+            [0.86883, 0.85137]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_matrix() -> Result<(), MatrixError> {
+        let bigwigs = [valid_bigwig(), valid_bigwig()];
+        let options = MatrixOptions {
+            mode: MatrixMode::ReferencePoint(ReferencePoint::End),
+            upstream: 10,
+            downstream: 20,
+            bin_size: 10,
+            stat: BinStat::Mean,
+            missing_as_zero: false,
+        };
+        let regions = [
+            MatrixRegion {
+                chrom: "chr17".to_string(),
+                ..region(59800, 59905, Strand::Forward)
+            },
+            // The downstream bins are past the end of the chromosome
+            MatrixRegion {
+                chrom: "chr17".to_string(),
+                ..region(83257400, 83257441, Strand::Forward)
+            },
+            // Not in the bigWig
+            region(59800, 59910, Strand::Forward),
+        ];
+        let rows = compute_matrix(&bigwigs, &regions, &options, 2)?;
+        assert_eq!(rows.len(), 3);
+        // One after another for each bigWig
+        // The first bin only has values for 7 bases
+        let mean = (f64::from(0.06792f32) * 2.0 + f64::from(0.16627f32) * 5.0) / 7.0;
+        assert_eq!(rows[0].len(), 6);
+        assert_eq!(rows[0][..3], [mean as f32, 0.16627, 0.16627]);
+        assert_eq!(rows[0][..3], rows[0][3..]);
+        assert!(rows[1].iter().all(|v| v.is_nan()));
+        assert!(rows[2].iter().all(|v| v.is_nan()));
+
+        let options = MatrixOptions {
+            missing_as_zero: true,
+            ..options
+        };
+        let rows = compute_matrix(&bigwigs, &regions, &options, 2)?;
+        assert_eq!(rows[1], [0.0; 6]);
+        assert_eq!(rows[2], [0.0; 6]);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,130 @@
+use thiserror::Error;
+
 use crate::bbi::Value;
 
+/// How the values of overlapping sections are combined when merging.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergeOp {
+    /// The sum of the values at a base
+    Sum,
+    /// The maximum of the values at a base
+    Max,
+    /// The minimum of the values at a base
+    Min,
+    /// The mean of the values of the sections that have a value at a base
+    MeanCovering,
+    /// The mean over all sections, where sections without a value at a base count as `0`
+    MeanAll,
+    /// The number of sections that have a value at a base
+    Count,
+    /// The sum of the values at a base, each multiplied by the weight of its
+    /// section. There must be one weight per section.
+    WeightedSum(Vec<f32>),
+    /// The value of the last section that has a value at a base
+    Last,
+}
+
+/// Possible errors when merging sections with a `MergeOp`
+#[derive(Error, Debug, PartialEq)]
+pub enum MergeOpError {
+    #[error("Expected {} weights (one per section), but got {}.", .expected, .got)]
+    WeightsMismatch { expected: usize, got: usize },
+}
+
+impl MergeOp {
+    /// Checks that this op can be used to merge `num_sections` sections: for
+    /// `MergeOp::WeightedSum`, there must be one weight per section.
+    pub fn validate(&self, num_sections: usize) -> Result<(), MergeOpError> {
+        match self {
+            MergeOp::WeightedSum(weights) if weights.len() != num_sections => {
+                Err(MergeOpError::WeightsMismatch {
+                    expected: num_sections,
+                    got: weights.len(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds `value` from the section at index `section` to a run of bases.
+    fn accumulate(&self, data: &mut [f32], counts: &mut [u32], section: usize, value: f32) {
+        match self {
+            MergeOp::Sum | MergeOp::MeanCovering | MergeOp::MeanAll => {
+                data.iter_mut().for_each(|d| *d += value);
+            }
+            MergeOp::Max => {
+                for (d, c) in data.iter_mut().zip(counts.iter()) {
+                    if *c == 0 || value > *d {
+                        *d = value;
+                    }
+                }
+            }
+            MergeOp::Min => {
+                for (d, c) in data.iter_mut().zip(counts.iter()) {
+                    if *c == 0 || value < *d {
+                        *d = value;
+                    }
+                }
+            }
+            MergeOp::Count => {}
+            MergeOp::Last => {
+                data.iter_mut().for_each(|d| *d = value);
+            }
+            MergeOp::WeightedSum(weights) => {
+                let value = value * weights[section];
+                data.iter_mut().for_each(|d| *d += value);
+            }
+        }
+        counts.iter_mut().for_each(|c| *c += 1);
+    }
+
+    /// The merged value of a base, given the accumulated value and the number
+    /// of sections with a value at that base.
+    fn finish(&self, value: f32, count: u32, num_sections: usize) -> f32 {
+        match self {
+            MergeOp::MeanCovering if count > 0 => value / count as f32,
+            MergeOp::MeanAll => value / num_sections as f32,
+            MergeOp::Count => count as f32,
+            _ => value,
+        }
+    }
+
+    /// Combines the values at a base, with one value per section (in order).
+    #[cfg(feature = "write")]
+    pub(crate) fn combine(&self, values: impl Iterator<Item = f32>) -> f32 {
+        let mut data = [0.0];
+        let mut counts = [0];
+        let mut num_sections = 0;
+        for value in values {
+            self.accumulate(&mut data, &mut counts, num_sections, value);
+            num_sections += 1;
+        }
+        self.finish(data[0], counts[0], num_sections)
+    }
+}
+
+/// How the values of the sections merged by `merge_sections` are combined at
+/// each base.
+pub(crate) trait Combine {
+    /// Adds `value` from the section at index `section` to a run of bases.
+    /// Sections are added in order at each base.
+    fn accumulate(&self, data: &mut [f32], counts: &mut [u32], section: usize, value: f32);
+
+    /// The merged value of a base, or `None` if the base should not be output.
+    fn finish(&self, value: f32, count: u32, num_sections: usize) -> Option<f32>;
+}
+
+impl Combine for MergeOp {
+    fn accumulate(&self, data: &mut [f32], counts: &mut [u32], section: usize, value: f32) {
+        MergeOp::accumulate(self, data, counts, section, value)
+    }
+
+    fn finish(&self, value: f32, count: u32, num_sections: usize) -> Option<f32> {
+        // TODO: 'real' zeros
+        Some(MergeOp::finish(self, value, count, num_sections)).filter(|v| *v != 0.0)
+    }
+}
+
 /// Returns:
 ///  (val, None, None, overhang or None) when merging two does not break up one, and may or may not add an overhang (one.start == two.start)
 ///  (val, val, val or None, overhang or None) when merging two breaks up one, and may or may not add an overhang (one.start < two.start or one.end > two.end)
@@ -306,20 +431,22 @@ pub fn merge_into(one: Value, two: Value) -> (Value, Option<Value>, Option<Value
     }
 }
 
-struct ValueIter<E, I>
+struct ValueIter<E, I, C>
 where
     I: Iterator<Item = Result<Value, E>> + Send,
 {
     error: bool,
+    op: C,
     sections: Vec<(I, Option<Value>)>,
     next_sections: Option<Box<dyn Iterator<Item = Value> + Send>>,
     last_val: Option<Value>,
     next_start: u32,
 }
 
-impl<E, I> Iterator for ValueIter<E, I>
+impl<E, I, C> Iterator for ValueIter<E, I, C>
 where
     I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combine,
 {
     type Item = Result<Value, E>;
 
@@ -341,9 +468,10 @@ where
             self.next_start = current_start + DATA_SIZE as u32;
 
             let mut data = vec![0f32; DATA_SIZE];
+            let mut counts = vec![0u32; DATA_SIZE];
             let mut max_sections: usize = 0;
             let mut all_none = true;
-            'sections: for (section, last) in &mut self.sections {
+            'sections: for (section_idx, (section, last)) in self.sections.iter_mut().enumerate() {
                 'section: loop {
                     let next_val = match last.take() {
                         Some(next_val) => next_val,
@@ -364,10 +492,12 @@ where
                         break 'section;
                     }
                     let data_end = DATA_SIZE.min((next_val.end - current_start) as usize);
-                    let value = next_val.value;
-                    for i in &mut data[data_start..data_end] {
-                        *i += value
-                    }
+                    self.op.accumulate(
+                        &mut data[data_start..data_end],
+                        &mut counts[data_start..data_end],
+                        section_idx,
+                        next_val.value,
+                    );
                     max_sections += 1;
                     if (next_val.end - current_start) as usize >= DATA_SIZE {
                         *last = Some(next_val);
@@ -376,44 +506,54 @@ where
                 }
             }
 
-            // TODO: coverage so can take average, or 'real' zeros
+            let num_sections = self.sections.len();
+            let data = data
+                .iter()
+                .zip(counts.iter())
+                .map(|(d, c)| self.op.finish(*d, *c, num_sections));
+
             let mut next_sections: Vec<Value> = Vec::with_capacity(max_sections * 2);
-            let mut current: Option<(u32, u32, f32)> = None;
-            for (idx, i) in data[..].iter().enumerate() {
+            let mut current: Option<(u32, u32, Option<f32>)> = None;
+            for (idx, i) in data.enumerate() {
                 match &mut current {
                     None => {
                         current = Some((
                             idx as u32 + current_start,
                             idx as u32 + current_start + 1,
-                            *i,
+                            i,
                         ))
                     }
                     Some(c) => {
-                        if (c.2 - *i).abs() < std::f32::EPSILON {
+                        let same = match (c.2, i) {
+                            (None, None) => true,
+                            (Some(a), Some(b)) => (a - b).abs() < f32::EPSILON,
+                            _ => false,
+                        };
+                        if same {
                             c.1 += 1;
                         } else {
-                            if c.2 != 0.0 {
+                            if let Some(value) = c.2 {
                                 next_sections.push(Value {
                                     start: c.0,
                                     end: c.1,
-                                    value: c.2,
+                                    value,
                                 });
                             }
                             current = Some((
                                 idx as u32 + current_start,
                                 idx as u32 + current_start + 1,
-                                *i,
+                                i,
                             ));
                         }
                     }
                 }
             }
             if let Some(c) = &mut current {
-                if c.2 != 0.0 {
+                if let Some(value) = c.2 {
                     next_sections.push(Value {
                         start: c.0,
                         end: c.1,
-                        value: c.2,
+                        value,
                     });
                 }
             }
@@ -499,12 +639,42 @@ where
     }
 }
 
+/// Merges the values of multiple sorted sections by summing overlapping values.
 pub fn merge_sections_many<I, E>(sections: Vec<I>) -> impl Iterator<Item = Result<Value, E>> + Send
 where
     I: Iterator<Item = Result<Value, E>> + Send,
+{
+    merge_sections(sections, MergeOp::Sum)
+}
+
+/// Merges the values of multiple sorted sections, combining overlapping
+/// values with `op`. Bases where the merged value is `0` are not output.
+///
+/// Returns an error if `op` can't be used for the number of sections (see
+/// `MergeOp::validate`).
+pub fn merge_sections_many_with_op<I, E>(
+    sections: Vec<I>,
+    op: MergeOp,
+) -> Result<impl Iterator<Item = Result<Value, E>> + Send, MergeOpError>
+where
+    I: Iterator<Item = Result<Value, E>> + Send,
+{
+    op.validate(sections.len())?;
+    Ok(merge_sections(sections, op))
+}
+
+// Like `merge_sections_many_with_op`, but `op` must already be valid
+pub(crate) fn merge_sections<I, E, C>(
+    sections: Vec<I>,
+    op: C,
+) -> impl Iterator<Item = Result<Value, E>> + Send
+where
+    I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combine + Send,
 {
     ValueIter {
         error: false,
+        op,
         sections: sections.into_iter().map(|s| (s, None)).collect(),
         next_sections: None,
         last_val: None,
@@ -582,6 +752,138 @@ mod tests {
         }
     */
 
+    fn merge_with_op(sections: &[Vec<(u32, u32, f32)>], op: MergeOp) -> Vec<(u32, u32, f32)> {
+        let sections = sections
+            .iter()
+            .map(|s| {
+                s.iter()
+                    .map(|&(start, end, value)| Ok::<_, ()>(Value { start, end, value }))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+            })
+            .collect();
+        merge_sections_many_with_op(sections, op)
+            .unwrap()
+            .map(|v| v.map(|v| (v.start, v.end, v.value)))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_merge_ops() {
+        let sections = vec![
+            vec![(0, 10, 2.0), (20, 30, -1.0)],
+            vec![(5, 15, 4.0), (60000, 60010, 3.0)],
+            vec![(5, 25, 6.0)],
+        ];
+
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::Sum),
+            vec![
+                (0, 5, 2.0),
+                (5, 10, 12.0),
+                (10, 15, 10.0),
+                (15, 20, 6.0),
+                (20, 25, 5.0),
+                (25, 30, -1.0),
+                (60000, 60010, 3.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::Max),
+            vec![
+                (0, 5, 2.0),
+                (5, 25, 6.0),
+                (25, 30, -1.0),
+                (60000, 60010, 3.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::Min),
+            vec![
+                (0, 10, 2.0),
+                (10, 15, 4.0),
+                (15, 20, 6.0),
+                (20, 30, -1.0),
+                (60000, 60010, 3.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::MeanCovering),
+            vec![
+                (0, 5, 2.0),
+                (5, 10, 4.0),
+                (10, 15, 5.0),
+                (15, 20, 6.0),
+                (20, 25, 2.5),
+                (25, 30, -1.0),
+                (60000, 60010, 3.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::MeanAll),
+            vec![
+                (0, 5, 2.0 / 3.0),
+                (5, 10, 4.0),
+                (10, 15, 10.0 / 3.0),
+                (15, 20, 2.0),
+                (20, 25, 5.0 / 3.0),
+                (25, 30, -1.0 / 3.0),
+                (60000, 60010, 1.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::Count),
+            vec![
+                (0, 5, 1.0),
+                (5, 10, 3.0),
+                (10, 15, 2.0),
+                (15, 20, 1.0),
+                (20, 25, 2.0),
+                (25, 30, 1.0),
+                (60000, 60010, 1.0),
+            ]
+        );
+        assert_eq!(
+            merge_with_op(&sections, MergeOp::WeightedSum(vec![1.0, 0.5, -1.0])),
+            vec![
+                (0, 5, 2.0),
+                (5, 10, -2.0),
+                (10, 15, -4.0),
+                (15, 20, -6.0),
+                (20, 25, -7.0),
+                (25, 30, -1.0),
+                (60000, 60010, 1.5),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_combine() {
+        let values = [1.0, 3.0, 2.0];
+        assert_eq!(MergeOp::Sum.combine(values.into_iter()), 6.0);
+        assert_eq!(MergeOp::Max.combine(values.into_iter()), 3.0);
+        assert_eq!(MergeOp::Min.combine(values.into_iter()), 1.0);
+        assert_eq!(MergeOp::MeanCovering.combine(values.into_iter()), 2.0);
+        assert_eq!(MergeOp::Last.combine(values.into_iter()), 2.0);
+        assert_eq!(MergeOp::Max.combine([-1.0, -2.0].into_iter()), -1.0);
+        assert_eq!(MergeOp::Sum.combine([1.0, -1.0].into_iter()), 0.0);
+    }
+
+    #[test]
+    fn test_merge_weights_mismatch() {
+        let sections = vec![std::iter::empty::<Result<Value, ()>>()];
+        assert_eq!(
+            merge_sections_many_with_op(sections, MergeOp::WeightedSum(vec![])).err(),
+            Some(MergeOpError::WeightsMismatch {
+                expected: 1,
+                got: 0
+            })
+        );
+        assert!(MergeOp::WeightedSum(vec![1.0]).validate(1).is_ok());
+    }
+
     #[test]
     fn can_gen() {
         let _sections = generate_sections_seq(50, 150, 1234);
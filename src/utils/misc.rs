@@ -2,7 +2,8 @@ use std::io::BufRead;
 
 use crate::bbi::BigWigRead;
 use crate::bbiread::BBIReadError;
-use crate::bed::bedparser::{parse_bed, BedValueError};
+use crate::bed::bedparser::{is_header_line, parse_bed, BedValueError};
+use crate::bed::bedrecord::parse_blocks;
 use crate::utils::file::reopen::SeekableRead;
 use crate::utils::file::streaming_linereader::StreamingLineReader;
 use crate::BedEntry;
@@ -23,6 +24,14 @@ pub struct BigWigAverageOverBedEntry {
     pub sum: f64,
     pub mean0: f64,
     pub mean: f64,
+    /// The minimum value over the covered bases (`0` if no bases are covered)
+    pub min: f64,
+    /// The maximum value over the covered bases (`0` if no bases are covered)
+    pub max: f64,
+    /// The population standard deviation of the covered bases
+    pub stdev: f64,
+    /// The value at each of the requested quantiles of the covered bases
+    pub quantiles: Vec<f64>,
 }
 
 #[derive(Error, Debug)]
@@ -31,40 +40,114 @@ pub enum StatsError {
     BBIReadError(#[from] BBIReadError),
     #[error("{}", .0)]
     InvalidNameCol(String),
+    #[error("{}", .0)]
+    InvalidBlocks(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StatsOptions {
+    /// The quantiles of the covered bases to compute. Each should be between
+    /// `0` and `1`, and gives the value of the nearest-ranked covered base (so
+    /// `0.5` gives the median).
+    pub quantiles: Vec<f64>,
+    /// If set, stats are computed over a window of this many bases centered
+    /// on each bed entry, rather than over the entry (or its blocks).
+    pub sample_around_center: Option<u32>,
+}
+
+/// Returns the absolute `(start, end)` of the blocks of a BED12 entry, or
+/// `None` if the entry doesn't have the block columns.
+pub fn bed_blocks(entry: &BedEntry) -> Result<Option<Vec<(u32, u32)>>, StatsError> {
+    // name, score, strand, thickStart, thickEnd, itemRgb, blockCount, blockSizes, blockStarts
+    let mut cols = entry.rest.trim_end().split('\t').skip(6);
+    match (cols.next(), cols.next(), cols.next()) {
+        (Some(count), Some(sizes), Some(starts)) => parse_blocks(entry, count, sizes, starts)
+            .map(Some)
+            .map_err(|e| StatsError::InvalidBlocks(e.to_string())),
+        _ => Ok(None),
+    }
 }
 
+/// Gets the stats of `bigwig` over a bed entry. For BED12 entries, only the
+/// bases within the blocks (such as exons) are used.
 pub fn stats_for_bed_item<R: SeekableRead>(
     name: Name,
     chrom: &str,
     entry: BedEntry,
     bigwig: &mut BigWigRead<R>,
+    options: &StatsOptions,
 ) -> Result<BigWigAverageOverBedEntry, StatsError> {
     let start = entry.start;
     let end = entry.end;
+    let quantiles = &options.quantiles[..];
 
-    let interval = bigwig
-        .get_interval(chrom, start, end)?
-        .collect::<Result<Vec<_>, _>>();
-    let interval = match interval {
-        Ok(i) => i,
-        Err(e) => return Err(e.into()),
+    let regions = match options.sample_around_center {
+        Some(width) => {
+            let center = start + (end - start) / 2;
+            let sample_start = center.saturating_sub(width / 2);
+            vec![(sample_start, sample_start.saturating_add(width))]
+        }
+        None => bed_blocks(&entry)?.unwrap_or_else(|| vec![(start, end)]),
     };
 
+    let mut interval = vec![];
+    for (region_start, region_end) in regions.iter().copied() {
+        for val in bigwig.get_interval(chrom, region_start, region_end)? {
+            interval.push(val?);
+        }
+    }
+
     let mut bases = 0;
     let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    let mut values = Vec::with_capacity(if quantiles.is_empty() {
+        0
+    } else {
+        interval.len()
+    });
     for val in interval {
         let num_bases = val.end - val.start;
+        let value = f64::from(val.value);
         bases += num_bases;
-        sum += f64::from(num_bases) * f64::from(val.value);
+        sum += f64::from(num_bases) * value;
+        sum_squares += f64::from(num_bases) * value * value;
+        min = min.min(value);
+        max = max.max(value);
+        if !quantiles.is_empty() {
+            values.push((value, num_bases));
+        }
     }
-    let size = end - start;
+    let size = regions.iter().map(|(start, end)| end - start).sum::<u32>();
     let mean0 = sum / f64::from(size);
-    let mean = if bases == 0 {
-        0.0
+    let (mean, stdev) = if bases == 0 {
+        min = 0.0;
+        max = 0.0;
+        (0.0, 0.0)
     } else {
-        sum / f64::from(bases)
+        let mean = sum / f64::from(bases);
+        let variance = sum_squares / f64::from(bases) - mean * mean;
+        (mean, variance.max(0.0).sqrt())
     };
 
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let quantiles = quantiles
+        .iter()
+        .map(|q| {
+            // The one-indexed rank of the covered base
+            let rank = ((q * f64::from(bases)).ceil() as u32).max(1);
+            let mut seen = 0;
+            values
+                .iter()
+                .find(|(_, num_bases)| {
+                    seen += num_bases;
+                    seen >= rank
+                })
+                .map_or(0.0, |(value, _)| *value)
+        })
+        .collect();
+
     let name = match name {
         Name::Column(col) => match col {
             0 => chrom.to_string(),
@@ -90,6 +173,10 @@ pub fn stats_for_bed_item<R: SeekableRead>(
         sum,
         mean0,
         mean,
+        min,
+        max,
+        stdev,
+        quantiles,
     })
 }
 
@@ -105,6 +192,7 @@ pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
     bed: impl BufRead,
     mut bigwig: BigWigRead<R>,
     name: Name,
+    options: StatsOptions,
 ) -> impl Iterator<Item = Result<BigWigAverageOverBedEntry, BigWigAverageOverBedError>> {
     let mut bedstream = StreamingLineReader::new(bed);
 
@@ -114,23 +202,27 @@ pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
             if error {
                 return None;
             }
-            let line = match bedstream.read()? {
-                Err(e) => {
-                    error = true;
-                    return Some(Err(BedValueError::IoError(e).into()));
+            loop {
+                match bedstream.read()? {
+                    Err(e) => {
+                        error = true;
+                        return Some(Err(BedValueError::IoError(e).into()));
+                    }
+                    Ok(line) if is_header_line(line) => continue,
+                    Ok(_) => break,
                 }
-                Ok(line) => line,
-            };
-            let (chrom, entry) = match parse_bed(line) {
+            }
+            let line = bedstream.current_line();
+            let (chrom, entry) = match parse_bed(line.trim_end()) {
                 None => return None,
                 Some(Err(e)) => {
                     error = true;
-                    return Some(Err(e.into()));
+                    return Some(Err(e.at(bedstream.position()).into()));
                 }
                 Some(Ok(v)) => v,
             };
 
-            match stats_for_bed_item(name, chrom, entry, &mut bigwig) {
+            match stats_for_bed_item(name, chrom, entry, &mut bigwig, &options) {
                 Err(e) => {
                     error = true;
                     Some(Err(e.into()))
@@ -142,3 +234,183 @@ pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
 
     iter
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start: u32, end: u32, rest: &str) -> BedEntry {
+        BedEntry {
+            start,
+            end,
+            rest: rest.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bed_blocks() {
+        let blocks = |rest: &str| bed_blocks(&entry(100, 200, rest)).unwrap();
+        assert_eq!(
+            blocks("a\t0\t+\t100\t200\t0\t2\t10,20,\t0,80,"),
+            Some(vec![(100, 110), (180, 200)])
+        );
+        // Only the block columns are parsed, so other columns don't need to
+        // be valid standard fields
+        assert_eq!(
+            blocks("a\t1.5\t+\t100\t200\t0,0\t2\t10,20,\t0,80,"),
+            Some(vec![(100, 110), (180, 200)])
+        );
+        assert_eq!(
+            blocks("a\t.\t.\t.\t.\t.\t1\t100\t0"),
+            Some(vec![(100, 200)])
+        );
+        // Blocks don't need to be in order
+        assert_eq!(
+            blocks("a\t0\t+\t100\t200\t0\t2\t20,10,\t80,0,"),
+            Some(vec![(180, 200), (100, 110)])
+        );
+        assert_eq!(blocks("a\t0\t+"), None);
+
+        assert!(bed_blocks(&entry(100, 200, "a\t0\t+\t100\t200\t0\t2\t10,100\t0,50")).is_err());
+    }
+
+    // The sum, mean, and population stdev of covered bases, given as
+    // `(value, bases)`
+    fn expected_stats(values: &[(f32, u32)]) -> (f64, f64, f64) {
+        let bases: u32 = values.iter().map(|(_, n)| n).sum();
+        let sum: f64 = values
+            .iter()
+            .map(|(v, n)| f64::from(*v) * f64::from(*n))
+            .sum();
+        let mean = sum / f64::from(bases);
+        let variance = values
+            .iter()
+            .map(|(v, n)| (f64::from(*v) - mean).powi(2) * f64::from(*n))
+            .sum::<f64>()
+            / f64::from(bases);
+        (sum, mean, variance.sqrt())
+    }
+
+    // chr17 of valid.bigWig has no values before 59898, then:
+    //   59898-59900: 0.06792, 59900-59947: 0.16627, 59947-59999: 0.85137,
+    //   59999-60044: 0.86883
+    fn valid_bigwig() -> BigWigRead<crate::utils::file::reopen::ReopenableFile> {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/valid.bigWig");
+        BigWigRead::open_file(&path.to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn test_stats_for_bed_item() -> Result<(), Box<dyn std::error::Error>> {
+        let mut bigwig = valid_bigwig();
+
+        // Like `bigWigAverageOverBed -minMax`, min and max are over the
+        // covered bases
+        let options = StatsOptions {
+            quantiles: vec![0.0, 0.1, 0.5, 1.0],
+            ..Default::default()
+        };
+        let item = entry(59890, 60010, "a");
+        let stats = stats_for_bed_item(Name::Column(3), "chr17", item, &mut bigwig, &options)?;
+        let values = [(0.06792, 2), (0.16627, 47), (0.85137, 52), (0.86883, 11)];
+        let (sum, mean, stdev) = expected_stats(&values);
+        assert_eq!(stats.name, "a");
+        assert_eq!(stats.size, 120);
+        assert_eq!(stats.bases, 112);
+        assert!((stats.sum - sum).abs() < 1e-9);
+        assert!((stats.mean - mean).abs() < 1e-9);
+        assert!((stats.mean0 - sum / 120.0).abs() < 1e-9);
+        assert_eq!(stats.min, f64::from(0.06792f32));
+        assert_eq!(stats.max, f64::from(0.86883f32));
+        assert!((stats.stdev - stdev).abs() < 1e-9);
+        // The 1st, 12th, 56th, and 112th covered bases
+        assert_eq!(
+            stats.quantiles,
+            [0.06792f32, 0.16627, 0.85137, 0.86883].map(f64::from)
+        );
+
+        // A single value has no spread
+        let item = entry(59950, 59960, "a");
+        let stats = stats_for_bed_item(Name::Interval, "chr17", item, &mut bigwig, &options)?;
+        assert_eq!(stats.name, "chr17:59950-59960");
+        assert_eq!(
+            (stats.min, stats.max),
+            (f64::from(0.85137f32), f64::from(0.85137f32))
+        );
+        assert!(stats.stdev.abs() < 1e-6);
+        assert_eq!(stats.quantiles, [f64::from(0.85137f32); 4]);
+
+        // No covered bases
+        let item = entry(59000, 59010, "a");
+        let stats = stats_for_bed_item(Name::None, "chr17", item, &mut bigwig, &options)?;
+        assert_eq!((stats.size, stats.bases), (10, 0));
+        assert_eq!((stats.min, stats.max, stats.stdev), (0.0, 0.0, 0.0));
+        assert_eq!(stats.quantiles, [0.0; 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_for_bed_item_regions() -> Result<(), Box<dyn std::error::Error>> {
+        let mut bigwig = valid_bigwig();
+
+        // Like `bigWigAverageOverBed`, only the bases in the blocks are used:
+        // 59895-59905 and 59995-60005
+        let bed12 = entry(59890, 60050, "a\t0\t+\t59890\t60050\t0\t2\t10,10,\t5,105,");
+        let options = StatsOptions {
+            quantiles: vec![0.0, 0.5, 1.0],
+            ..Default::default()
+        };
+        let stats = stats_for_bed_item(
+            Name::Column(3),
+            "chr17",
+            bed12.clone(),
+            &mut bigwig,
+            &options,
+        )?;
+        let values = [(0.06792, 2), (0.16627, 5), (0.85137, 4), (0.86883, 6)];
+        let (sum, mean, stdev) = expected_stats(&values);
+        assert_eq!(stats.size, 20);
+        assert_eq!(stats.bases, 17);
+        assert!((stats.sum - sum).abs() < 1e-9);
+        assert!((stats.mean - mean).abs() < 1e-9);
+        assert!((stats.mean0 - sum / 20.0).abs() < 1e-9);
+        assert_eq!(stats.min, f64::from(0.06792f32));
+        assert_eq!(stats.max, f64::from(0.86883f32));
+        assert!((stats.stdev - stdev).abs() < 1e-9);
+        // The 1st, 9th, and 17th covered bases
+        assert_eq!(
+            stats.quantiles,
+            [0.06792f32, 0.85137, 0.86883].map(f64::from)
+        );
+
+        // Like `-sampleAroundCenter=10`, a window of 10 bases around the
+        // center (59950) is used, ignoring the blocks
+        let options = StatsOptions {
+            quantiles: vec![0.2, 0.3],
+            sample_around_center: Some(10),
+        };
+        let centered = entry(59940, 59960, &bed12.rest);
+        let stats = stats_for_bed_item(Name::Interval, "chr17", centered, &mut bigwig, &options)?;
+        let (sum, mean, stdev) = expected_stats(&[(0.16627, 2), (0.85137, 8)]);
+        assert_eq!(stats.name, "chr17:59940-59960");
+        assert_eq!(stats.size, 10);
+        assert_eq!(stats.bases, 10);
+        assert!((stats.sum - sum).abs() < 1e-9);
+        assert!((stats.mean0 - mean).abs() < 1e-9);
+        assert_eq!(stats.min, f64::from(0.16627f32));
+        assert_eq!(stats.max, f64::from(0.85137f32));
+        assert!((stats.stdev - stdev).abs() < 1e-9);
+        assert_eq!(stats.quantiles, [0.16627f32, 0.85137].map(f64::from));
+
+        // Invalid blocks are an error
+        let invalid = entry(59890, 60050, "a\t0\t+\t59890\t60050\t0\t2\t10,10,\t5,500,");
+        let options = StatsOptions::default();
+        assert!(matches!(
+            stats_for_bed_item(Name::None, "chr17", invalid, &mut bigwig, &options),
+            Err(StatsError::InvalidBlocks(_))
+        ));
+
+        Ok(())
+    }
+}
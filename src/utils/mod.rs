@@ -1,11 +1,23 @@
+pub mod chromalias;
+pub mod chromsizes;
 pub mod chromvalues;
+#[cfg(feature = "read")]
+pub mod compare;
 pub mod file;
 pub mod fill;
 pub mod idmap;
 pub mod indexlist;
+#[cfg(feature = "read")]
+pub mod matrix;
 pub mod merge;
 #[cfg(feature = "read")]
 pub mod misc;
+#[cfg(all(feature = "read", feature = "write"))]
+pub mod rename;
+#[cfg(all(feature = "read", feature = "write"))]
+pub mod subset;
+#[cfg(feature = "read")]
+pub mod threshold;
 
 #[cfg(feature = "cli")]
 pub mod cli;
@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+use byteordered::Endianness;
+use thiserror::Error;
+
+use crate::bbi::{
+    read_info, write_chrom_tree, BBIFile, BBIFileReadInfoError, ChromInfo, BIGBED_MAGIC,
+    BIGWIG_MAGIC,
+};
+
+#[derive(Error, Debug)]
+pub enum RenameChromsError {
+    #[error("Invalid magic (likely not a BigWig or BigBed file)")]
+    UnknownMagic,
+    #[error("Invalid chromosomes section")]
+    InvalidChroms,
+    #[error("Multiple chromosomes would be named {}.", .0)]
+    DuplicateChrom(String),
+    #[error("Error occurred: {}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// Renames the chromosomes of a bigWig or bigBed at `input`, writing the new
+/// file to `output`. Chromosomes in `mapping` are renamed, and others are kept
+/// as-is or, if `hide_unmapped` is set, left out of the chromosome tree.
+/// Returns the chromosomes of the new file.
+///
+/// Data blocks refer to chromosomes by id, so only the chromosome tree is
+/// rewritten: the file is copied byte-for-byte, a new tree (with the same ids)
+/// and the trailing magic are appended, and the header is pointed at the new
+/// tree. The data of hidden chromosomes is still in the file (and the
+/// summary), but can't be queried.
+pub fn rename_chroms(
+    input: &str,
+    output: &str,
+    mapping: &HashMap<String, String>,
+    hide_unmapped: bool,
+) -> Result<Vec<ChromInfo>, RenameChromsError> {
+    let info = {
+        let mut file = BufReader::new(File::open(input)?);
+        read_info(&mut file).map_err(|e| match e {
+            BBIFileReadInfoError::UnknownMagic => RenameChromsError::UnknownMagic,
+            BBIFileReadInfoError::InvalidChroms => RenameChromsError::InvalidChroms,
+            BBIFileReadInfoError::IoError(e) => RenameChromsError::IoError(e),
+        })?
+    };
+
+    let mut chroms = Vec::with_capacity(info.chrom_info.len());
+    let mut names = HashSet::with_capacity(info.chrom_info.len());
+    for chrom in info.chrom_info {
+        let name = match mapping.get(&chrom.name) {
+            Some(name) => name.clone(),
+            None if hide_unmapped => continue,
+            None => chrom.name,
+        };
+        if !names.insert(name.clone()) {
+            return Err(RenameChromsError::DuplicateChrom(name));
+        }
+        chroms.push(ChromInfo { name, ..chrom });
+    }
+    let chrom_sizes: HashMap<String, u32> =
+        chroms.iter().map(|c| (c.name.clone(), c.length)).collect();
+    let chrom_ids: HashMap<String, u32> = chroms.iter().map(|c| (c.name.clone(), c.id)).collect();
+    let magic = match info.filetype {
+        BBIFile::BigWig => BIGWIG_MAGIC,
+        BBIFile::BigBed => BIGBED_MAGIC,
+    };
+
+    fs::copy(input, output)?;
+    let file = OpenOptions::new().write(true).open(output)?;
+    let mut file = BufWriter::new(file);
+    let chrom_tree_offset = file.seek(SeekFrom::End(0))?;
+    match info.header.endianness {
+        Endianness::Big => {
+            write_chrom_tree::<BigEndian, _>(&mut file, chrom_sizes, &chrom_ids)?;
+            file.write_u32::<BigEndian>(magic)?;
+            file.seek(SeekFrom::Start(8))?;
+            file.write_u64::<BigEndian>(chrom_tree_offset)?;
+        }
+        Endianness::Little => {
+            write_chrom_tree::<LittleEndian, _>(&mut file, chrom_sizes, &chrom_ids)?;
+            file.write_u32::<LittleEndian>(magic)?;
+            file.seek(SeekFrom::Start(8))?;
+            file.write_u64::<LittleEndian>(chrom_tree_offset)?;
+        }
+    }
+    file.flush()?;
+
+    chroms.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(chroms)
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use futures::executor::ThreadPool;
+
+use crate::bbi::{
+    BBIRead, BBIReadError, BigBedRead, BigBedWrite, BigWigRead, BigWigWrite, ChromInfo,
+    ProcessChromError, QueuedChromData,
+};
+use crate::utils::chromvalues::BoxedChromValues;
+use crate::utils::file::reopen::{Reopen, SeekableRead};
+
+/// The parts of a bbi file to keep when subsetting it: whole chromosomes
+/// and/or regions.
+#[derive(Clone, Debug, Default)]
+pub struct SubsetRegions {
+    pub chroms: Vec<String>,
+    pub regions: Vec<(String, u32, u32)>,
+}
+
+/// A chromosome to keep, with its length and the sorted, non-overlapping
+/// regions of it to keep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubsetChrom {
+    pub name: String,
+    pub length: u32,
+    pub regions: Vec<(u32, u32)>,
+}
+
+impl SubsetRegions {
+    /// Resolves the chromosomes and regions to keep against the chromosomes of
+    /// a file. Whole chromosomes must exist in the file, but regions on
+    /// chromosomes not in the file are ignored. Regions are clipped to the
+    /// chromosome and overlapping or adjacent regions are merged. The
+    /// chromosomes are sorted by name.
+    pub fn resolve(&self, chrom_info: &[ChromInfo]) -> Result<Vec<SubsetChrom>, BBIReadError> {
+        let lengths: HashMap<&str, u32> = chrom_info
+            .iter()
+            .map(|c| (c.name.as_str(), c.length))
+            .collect();
+        let mut regions: HashMap<&str, Vec<(u32, u32)>> = HashMap::new();
+        let whole: HashSet<&str> = self.chroms.iter().map(|c| c.as_str()).collect();
+        for chrom in &whole {
+            let length = *lengths
+                .get(chrom)
+                .ok_or_else(|| BBIReadError::InvalidChromosome(chrom.to_string()))?;
+            regions.insert(chrom, vec![(0, length)]);
+        }
+        for (chrom, start, end) in &self.regions {
+            let length = match lengths.get(chrom.as_str()) {
+                Some(length) => *length,
+                None => continue,
+            };
+            if whole.contains(chrom.as_str()) {
+                continue;
+            }
+            let (start, end) = ((*start).min(length), (*end).min(length));
+            if start >= end {
+                continue;
+            }
+            regions.entry(chrom).or_default().push((start, end));
+        }
+
+        let mut chroms: Vec<SubsetChrom> = regions
+            .into_iter()
+            .map(|(chrom, mut regions)| {
+                regions.sort();
+                let mut merged: Vec<(u32, u32)> = Vec::with_capacity(regions.len());
+                for (start, end) in regions {
+                    match merged.last_mut() {
+                        Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                        _ => merged.push((start, end)),
+                    }
+                }
+                SubsetChrom {
+                    name: chrom.to_string(),
+                    length: lengths[chrom],
+                    regions: merged,
+                }
+            })
+            .collect();
+        chroms.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(chroms)
+    }
+}
+
+/// Writes the values of `bigwig` in `regions` to a new bigWig. Values are
+/// clipped to the regions, and only the kept chromosomes are in the chrom
+/// tree of the new file. Zooms are regenerated. Up to `parallel_chroms`
+/// chromosomes are read at once.
+pub fn subset_bigwig<R: Reopen + SeekableRead + Send + 'static>(
+    bigwig: &BigWigRead<R>,
+    regions: &SubsetRegions,
+    out: BigWigWrite,
+    pool: ThreadPool,
+    parallel_chroms: usize,
+) -> Result<(), ProcessChromError<BBIReadError>> {
+    let chroms = regions
+        .resolve(&bigwig.get_chroms())
+        .map_err(ProcessChromError::SourceError)?;
+    let chrom_sizes: HashMap<String, u32> =
+        chroms.iter().map(|c| (c.name.clone(), c.length)).collect();
+    let values = chroms.into_iter().map(|chrom| {
+        let iter = bigwig
+            .reopen()?
+            .get_intervals_move(&chrom.name, chrom.regions)?;
+        Ok::<_, BBIReadError>((chrom.name, BoxedChromValues::new(iter)))
+    });
+    let data = QueuedChromData::new(values, parallel_chroms);
+    out.write(chrom_sizes, data, pool)
+}
+
+/// Writes the entries of `bigbed` that overlap `regions` to a new bigBed.
+/// Entries are not clipped, but each is written once even if it overlaps
+/// multiple regions. Only the kept chromosomes are in the chrom tree of the
+/// new file. If `out` has no autosql, the autosql of `bigbed` is used. Up to
+/// `parallel_chroms` chromosomes are read at once.
+pub fn subset_bigbed<R: Reopen + SeekableRead + Send + 'static>(
+    bigbed: &mut BigBedRead<R>,
+    regions: &SubsetRegions,
+    mut out: BigBedWrite,
+    pool: ThreadPool,
+    parallel_chroms: usize,
+) -> Result<(), ProcessChromError<BBIReadError>> {
+    let chroms = regions
+        .resolve(&bigbed.get_chroms())
+        .map_err(ProcessChromError::SourceError)?;
+    let chrom_sizes: HashMap<String, u32> =
+        chroms.iter().map(|c| (c.name.clone(), c.length)).collect();
+    if out.autosql.is_none() {
+        out.autosql = Some(bigbed.autosql().map_err(ProcessChromError::SourceError)?);
+    }
+    let bigbed = &*bigbed;
+    let values = chroms.into_iter().map(|chrom| {
+        let iter = bigbed
+            .reopen()?
+            .get_intervals_move(&chrom.name, chrom.regions)?;
+        Ok::<_, BBIReadError>((chrom.name, BoxedChromValues::new(iter)))
+    });
+    let data = QueuedChromData::new(values, parallel_chroms);
+    out.write(chrom_sizes, data, pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_subset_regions() {
+        let chrom_info = vec![
+            ChromInfo {
+                name: "chr1".to_string(),
+                length: 100,
+                id: 0,
+            },
+            ChromInfo {
+                name: "chr2".to_string(),
+                length: 50,
+                id: 1,
+            },
+        ];
+        let regions = SubsetRegions {
+            chroms: vec!["chr2".to_string()],
+            regions: vec![
+                ("chr1".to_string(), 40, 60),
+                ("chr1".to_string(), 10, 20),
+                ("chr1".to_string(), 20, 30),
+                ("chr1".to_string(), 50, 200),
+                ("chr2".to_string(), 10, 20),
+                ("chrX".to_string(), 10, 20),
+            ],
+        };
+        let chroms = regions.resolve(&chrom_info).unwrap();
+        assert_eq!(
+            chroms,
+            vec![
+                SubsetChrom {
+                    name: "chr1".to_string(),
+                    length: 100,
+                    regions: vec![(10, 30), (40, 100)],
+                },
+                SubsetChrom {
+                    name: "chr2".to_string(),
+                    length: 50,
+                    regions: vec![(0, 50)],
+                },
+            ]
+        );
+
+        let regions = SubsetRegions {
+            chroms: vec!["chrX".to_string()],
+            regions: vec![],
+        };
+        assert!(regions.resolve(&chrom_info).is_err());
+    }
+}
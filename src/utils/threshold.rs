@@ -0,0 +1,238 @@
+use crate::bbi::{BBIRead, BBIReadError, BigWigRead, Value};
+use crate::utils::file::reopen::SeekableRead;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ThresholdOptions {
+    /// Only values strictly greater than this are part of a region
+    pub threshold: f32,
+    /// Regions shorter than this (after merging) are dropped
+    pub min_length: u32,
+    /// Regions separated by fewer than this many bases are merged. Adjacent
+    /// regions are always merged.
+    pub merge_gap: u32,
+}
+
+impl Default for ThresholdOptions {
+    fn default() -> Self {
+        ThresholdOptions {
+            threshold: 0.0,
+            min_length: 0,
+            merge_gap: 0,
+        }
+    }
+}
+
+/// A contiguous region above the threshold (possibly with merged gaps).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThresholdRegion {
+    pub start: u32,
+    pub end: u32,
+    /// The maximum value in the region
+    pub max: f32,
+    /// The mean value over the bases in the region that have a value,
+    /// including those below the threshold in merged gaps
+    pub mean: f32,
+    /// The middle of the first interval with the maximum value
+    pub summit: u32,
+}
+
+struct CurrentRegion {
+    start: u32,
+    end: u32,
+    sum: f64,
+    bases: u64,
+    max: f32,
+    summit: u32,
+    // Values below the threshold after `end`, that are added to the region
+    // if it is merged with a later region
+    pending_sum: f64,
+    pending_bases: u64,
+}
+
+impl CurrentRegion {
+    fn new(value: &Value) -> Self {
+        let mut region = CurrentRegion {
+            start: value.start,
+            end: value.start,
+            sum: 0.0,
+            bases: 0,
+            max: f32::NEG_INFINITY,
+            summit: value.start,
+            pending_sum: 0.0,
+            pending_bases: 0,
+        };
+        region.extend(value);
+        region
+    }
+
+    fn extend(&mut self, value: &Value) {
+        let bases = u64::from(value.end - value.start);
+        self.sum += self.pending_sum + f64::from(value.value) * bases as f64;
+        self.bases += self.pending_bases + bases;
+        self.pending_sum = 0.0;
+        self.pending_bases = 0;
+        if value.value > self.max {
+            self.max = value.value;
+            self.summit = value.start + (value.end - value.start) / 2;
+        }
+        self.end = value.end;
+    }
+
+    fn finish(self, min_length: u32) -> Option<ThresholdRegion> {
+        if self.end - self.start < min_length {
+            return None;
+        }
+        Some(ThresholdRegion {
+            start: self.start,
+            end: self.end,
+            max: self.max,
+            mean: (self.sum / self.bases as f64) as f32,
+            summit: self.summit,
+        })
+    }
+}
+
+/// An iterator of the regions above a threshold in sorted, non-overlapping
+/// values (such as those of a single chromosome of a bigWig).
+pub struct ThresholdRegions<I> {
+    values: I,
+    options: ThresholdOptions,
+    current: Option<CurrentRegion>,
+}
+
+impl<I, E> Iterator for ThresholdRegions<I>
+where
+    I: Iterator<Item = Result<Value, E>>,
+{
+    type Item = Result<ThresholdRegion, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let value = match self.values.next() {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    let current = self.current.take()?;
+                    match current.finish(self.options.min_length) {
+                        Some(region) => return Some(Ok(region)),
+                        None => return None,
+                    }
+                }
+            };
+            // NaN is never above the threshold
+            let above = value.value > self.options.threshold;
+            let current = match self.current.as_mut() {
+                Some(current) => current,
+                None => {
+                    if above {
+                        self.current = Some(CurrentRegion::new(&value));
+                    }
+                    continue;
+                }
+            };
+            let gap = value.start - current.end;
+            if gap == 0 || gap < self.options.merge_gap {
+                if above {
+                    current.extend(&value);
+                } else {
+                    let bases = u64::from(value.end - value.start);
+                    current.pending_sum += f64::from(value.value) * bases as f64;
+                    current.pending_bases += bases;
+                }
+                continue;
+            }
+            let finished = self
+                .current
+                .take()
+                .and_then(|current| current.finish(self.options.min_length));
+            if above {
+                self.current = Some(CurrentRegion::new(&value));
+            }
+            if let Some(region) = finished {
+                return Some(Ok(region));
+            }
+        }
+    }
+}
+
+/// Finds the regions above a threshold in `values`, which must be sorted and
+/// non-overlapping.
+pub fn threshold_regions<I, E>(
+    values: I,
+    options: ThresholdOptions,
+) -> ThresholdRegions<I::IntoIter>
+where
+    I: IntoIterator<Item = Result<Value, E>>,
+{
+    ThresholdRegions {
+        values: values.into_iter(),
+        options,
+        current: None,
+    }
+}
+
+/// Finds the regions above a threshold on a chromosome of a bigWig.
+pub fn bigwig_threshold_regions<R: SeekableRead>(
+    bigwig: BigWigRead<R>,
+    chrom: &str,
+    options: ThresholdOptions,
+) -> Result<impl Iterator<Item = Result<ThresholdRegion, BBIReadError>>, BBIReadError> {
+    let length = bigwig
+        .get_chroms()
+        .into_iter()
+        .find(|c| c.name == chrom)
+        .map(|c| c.length)
+        .ok_or_else(|| BBIReadError::InvalidChromosome(chrom.to_string()))?;
+    let values = bigwig.get_interval_move(chrom, 0, length)?;
+    Ok(threshold_regions(values, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(values: &[(u32, u32, f32)], options: ThresholdOptions) -> Vec<(u32, u32)> {
+        let values = values
+            .iter()
+            .map(|&(start, end, value)| Ok::<_, BBIReadError>(Value { start, end, value }));
+        threshold_regions(values, options)
+            .map(|r| r.map(|r| (r.start, r.end)))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_threshold_regions() {
+        let values = [
+            (0, 10, 1.0),
+            (10, 20, 5.0),
+            (20, 25, 3.0),
+            (25, 30, 0.5),
+            (32, 40, 4.0),
+            (60, 62, 2.0),
+        ];
+        let options = ThresholdOptions {
+            threshold: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(regions(&values, options), [(10, 25), (32, 40), (60, 62)]);
+
+        let options = ThresholdOptions {
+            threshold: 1.0,
+            merge_gap: 8,
+            min_length: 3,
+        };
+        assert_eq!(regions(&values, options), [(10, 40)]);
+
+        let values =
+            values.map(|(start, end, value)| Ok::<_, BBIReadError>(Value { start, end, value }));
+        let all: Vec<_> = threshold_regions(values, options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let region = &all[0];
+        assert_eq!(region.max, 5.0);
+        assert_eq!(region.summit, 15);
+        // (50 + 15 + 2.5 + 32) / 28
+        assert!((region.mean - 99.5 / 28.0).abs() < 1e-6);
+    }
+}
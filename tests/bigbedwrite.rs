@@ -12,7 +12,7 @@ fn bigbedwrite_test() -> Result<(), Box<dyn Error>> {
 
     use bigtools::bed::bedparser::BedParser;
     use bigtools::utils::chromvalues::ChromValues;
-    use bigtools::{BBIRead, BigBedRead, BigBedWrite};
+    use bigtools::{BBIRead, BigBedRead, BigBedWrite, Compression};
 
     let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     dir.push("resources/test");
@@ -41,7 +41,7 @@ fn bigbedwrite_test() -> Result<(), Box<dyn Error>> {
         let first = group.peek().unwrap().unwrap();
         Some(bigtools::bed::autosql::bed_autosql(&first.rest))
     };
-    outb.options.compress = false;
+    outb.options.compression = Compression::None;
 
     let mut chrom_map = HashMap::new();
     chrom_map.insert("chr17".to_string(), 83257441);
@@ -71,3 +71,323 @@ fn bigbedwrite_test() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn bigbedwrite_summary_test() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    use bigtools::bed::bedparser::BedParser;
+    use bigtools::{BBIRead, BigBedRead, BigBedWrite, Compression};
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut bed = dir.clone();
+    bed.push("overlap.bed");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let infile = File::open(bed)?;
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::from_bed_file(infile);
+    let mut outb = BigBedWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.options.compression = Compression::None;
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 10000);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    // Like kent's bedToBigBed, the summaries are over the per-base depth of
+    // the entries, not the entries themselves. These values were worked out
+    // by hand from that depth:
+    //   100-150: 1, 150-160: 2, 160-180: 3, 180-200: 2, 200-250: 1,
+    //   300-400: 1, 1000-1100: 1
+    let mut bbread = BigBedRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    let summary = bbread.get_summary()?;
+    assert_eq!(summary.total_items, 5);
+    assert_eq!(summary.bases_covered, 350);
+    assert_eq!(summary.min_val, 1.0);
+    assert_eq!(summary.max_val, 3.0);
+    assert_eq!(summary.sum, 420.0);
+    assert_eq!(summary.sum_squares, 600.0);
+
+    // Only the first zoom level is small enough to be written
+    let zoom_headers = &bbread.get_info().zoom_headers;
+    assert_eq!(zoom_headers.len(), 1);
+    assert_eq!(zoom_headers[0].reduction_level, 160);
+    let records: Vec<_> = bbread
+        .get_zoom_interval("chr1", 0, 10000, 160)?
+        .map(|r| {
+            let r = r.unwrap();
+            let s = r.summary;
+            let stats = (s.bases_covered, s.min_val, s.max_val, s.sum, s.sum_squares);
+            (r.start, r.end, stats)
+        })
+        .collect();
+    assert_eq!(
+        records,
+        [
+            (100, 250, (150, 1.0, 3.0, 220.0, 400.0)),
+            (300, 400, (100, 1.0, 1.0, 100.0, 100.0)),
+            (1000, 1100, (100, 1.0, 1.0, 100.0, 100.0)),
+        ]
+    );
+
+    // Entries overlapping multiple regions are only returned once, and
+    // entries only touching a region (300-400 and 1000-1100) aren't returned
+    let names: Vec<_> = bbread
+        .get_intervals_move("chr1", vec![(120, 155), (170, 300), (1100, 1200)])?
+        .map(|e| e.map(|e| e.rest[..6].to_string()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(names, ["entry1", "entry2", "entry3"]);
+
+    Ok(())
+}
+
+#[test]
+fn bigbedwrite_overlapping_zooms_test() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+
+    use bigtools::bed::bedparser::{BedParser, BedValueError};
+    use bigtools::BedEntry;
+    use bigtools::{BBIRead, BigBedRead, BigBedWrite, Compression};
+
+    // Overlapping entries of varying lengths, with a gap in the middle
+    let entries: Vec<BedEntry> = (0..2000u32)
+        .map(|i| {
+            let start = i * 37 + if i >= 1000 { 5000 } else { 0 };
+            BedEntry {
+                start,
+                end: start + 50 + (i % 7) * 40,
+                rest: format!("{:0>200}", i),
+            }
+        })
+        .collect();
+    let chrom_length = 200_000;
+
+    let mut depth = vec![0u32; chrom_length as usize];
+    for entry in entries.iter() {
+        for d in &mut depth[entry.start as usize..entry.end as usize] {
+            *d += 1;
+        }
+    }
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::wrap_iter(
+        entries
+            .clone()
+            .into_iter()
+            .map(|e| Ok::<_, BedValueError>(("chr1".to_string(), e))),
+    );
+    let mut outb = BigBedWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.options.compression = Compression::None;
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), chrom_length);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    let mut bbread = BigBedRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+
+    let covered: Vec<u32> = depth.iter().copied().filter(|d| *d > 0).collect();
+    let summary = bbread.get_summary()?;
+    assert_eq!(summary.total_items, 2000);
+    assert_eq!(summary.bases_covered, covered.len() as u64);
+    assert_eq!(summary.min_val, 1.0);
+    assert_eq!(summary.max_val, f64::from(*covered.iter().max().unwrap()));
+    assert_eq!(
+        summary.sum,
+        covered.iter().map(|d| f64::from(*d)).sum::<f64>()
+    );
+    assert_eq!(
+        summary.sum_squares,
+        covered.iter().map(|d| f64::from(*d * *d)).sum::<f64>()
+    );
+
+    let reduction_levels: Vec<u32> = bbread
+        .get_info()
+        .zoom_headers
+        .iter()
+        .map(|z| z.reduction_level)
+        .collect();
+    assert!(!reduction_levels.is_empty());
+    for reduction_level in reduction_levels {
+        let records = bbread
+            .get_zoom_interval("chr1", 0, chrom_length, reduction_level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut last_end = 0;
+        let mut total_bases = 0;
+        for record in records {
+            assert!(record.start >= last_end);
+            assert!(record.end - record.start <= reduction_level);
+            last_end = record.end;
+
+            let covered: Vec<u32> = depth[record.start as usize..record.end as usize]
+                .iter()
+                .copied()
+                .filter(|d| *d > 0)
+                .collect();
+            assert_eq!(record.summary.bases_covered, covered.len() as u64);
+            assert_eq!(
+                record.summary.min_val,
+                f64::from(*covered.iter().min().unwrap())
+            );
+            assert_eq!(
+                record.summary.max_val,
+                f64::from(*covered.iter().max().unwrap())
+            );
+            assert_eq!(
+                record.summary.sum,
+                covered.iter().map(|d| f64::from(*d)).sum::<f64>()
+            );
+            total_bases += record.summary.bases_covered;
+        }
+        assert_eq!(total_bases, summary.bases_covered);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn bigbedwrite_bed12_records_test() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+
+    use bigtools::bed::autosql::bed_autosql;
+    use bigtools::bed::bedparser::BedParser;
+    use bigtools::bed::bedrecord::Strand;
+    use bigtools::{BBIRead, BigBedRead, BigBedWrite};
+
+    let mut bed = tempfile::NamedTempFile::new()?;
+    bed.write_all(
+        b"chr1\t100\t200\ttx1\t0\t+\t110\t190\t0\t2\t10,30,\t0,70,\textra1\n\
+        chr1\t300\t400\ttx2\t500\t-\t300\t400\t255,0,0\t1\t100,\t0,\textra2\n",
+    )?;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let mut outb = BigBedWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.autosql = Some(bed_autosql(
+        "tx1\t0\t+\t110\t190\t0\t2\t10,30,\t0,70,\textra1",
+    ));
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 1000);
+
+    let vals_iter = BedParser::from_bed_file(File::open(bed.path())?);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    let mut bbread = BigBedRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    assert_eq!(bbread.get_info().header.field_count, 13);
+    assert_eq!(bbread.get_info().header.defined_field_count, 12);
+
+    let records = bbread
+        .get_interval_records("chr1", 0, 1000)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].name.as_deref(), Some("tx1"));
+    assert_eq!(records[0].strand, Strand::Forward);
+    assert_eq!(records[0].blocks, Some(vec![(100, 110), (170, 200)]));
+    assert_eq!(records[0].introns().collect::<Vec<_>>(), vec![(110, 170)]);
+    assert_eq!(records[0].extra, vec!["extra1"]);
+    assert_eq!(records[1].score, Some(500));
+    assert_eq!(records[1].item_rgb, Some([255, 0, 0]));
+    assert_eq!(records[1].transcript_offset(399), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn bigbedwrite_rename_chroms_test() -> Result<(), Box<dyn Error>> {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    use bigtools::bed::bedparser::BedParser;
+    use bigtools::utils::rename::rename_chroms;
+    use bigtools::{BBIRead, BigBedRead, BigBedWrite};
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut bed = dir.clone();
+    bed.push("small.bed");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let infile = File::open(bed)?;
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let input = tempfile.path().to_string_lossy().to_string();
+    let vals_iter = BedParser::from_bed_file(infile);
+    let mut outb = BigBedWrite::create_file(input.clone());
+    outb.autosql = Some(bigtools::bed::autosql::bed_autosql("test1\t0"));
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr17".to_string(), 83257441);
+    chrom_map.insert("chr18".to_string(), 80373285);
+    chrom_map.insert("chr19".to_string(), 58617616);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    let mut mapping = HashMap::new();
+    mapping.insert("chr17".to_string(), "17".to_string());
+    mapping.insert("chr19".to_string(), "19".to_string());
+
+    let renamed = tempfile::NamedTempFile::new()?;
+    let output = renamed.path().to_string_lossy().to_string();
+    rename_chroms(&input, &output, &mapping, true)?;
+
+    let mut original = BigBedRead::open_file(&input).unwrap();
+    let mut bbread = BigBedRead::open_file(&output).unwrap();
+    let mut names: Vec<_> = bbread.get_chroms().into_iter().map(|c| c.name).collect();
+    names.sort();
+    assert_eq!(names, ["17", "19"]);
+    assert!(bbread.get_interval("chr18", 0, 80373285).is_err());
+    assert_eq!(bbread.autosql()?, original.autosql()?);
+    for (chrom, renamed_chrom, length) in [("chr17", "17", 83257441), ("chr19", "19", 58617616)] {
+        let entries = |bb: &mut BigBedRead<_>, chrom: &str| {
+            bb.get_interval(chrom, 0, length)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            entries(&mut bbread, renamed_chrom),
+            entries(&mut original, chrom)
+        );
+    }
+
+    // The data of the hidden chr18 is still in the file, and its summary
+    let summary = bbread.get_summary()?;
+    assert_eq!(summary.total_items, 6);
+    assert_eq!(summary.bases_covered, 594);
+
+    // The file still ends with the magic
+    let renamed_bytes = std::fs::read(&output)?;
+    assert_eq!(renamed_bytes[renamed_bytes.len() - 4..], renamed_bytes[..4]);
+
+    Ok(())
+}
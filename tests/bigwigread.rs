@@ -58,6 +58,29 @@ fn test_values() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn test_intervals_move() -> Result<(), Box<dyn Error>> {
+    use std::path::PathBuf;
+
+    use bigtools::BigWigRead;
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut valid_bigwig = dir.clone();
+    valid_bigwig.push("valid.bigWig");
+
+    let bwread = BigWigRead::open_file(&valid_bigwig.to_string_lossy()).unwrap();
+
+    // The values are clipped to the regions, and 59900-59947 only touches them
+    let vals: Vec<_> = bwread
+        .get_intervals_move("chr17", vec![(59890, 59900), (59947, 59950)])?
+        .map(|v| v.map(|v| (v.start, v.end, v.value)))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(vals, [(59898, 59900, 0.06792), (59947, 59950, 0.85137)]);
+    Ok(())
+}
+
 #[test]
 fn test_reduction_values() -> Result<(), Box<dyn Error>> {
     use std::path::PathBuf;
@@ -77,3 +100,165 @@ fn test_reduction_values() -> Result<(), Box<dyn Error>> {
     assert_eq!(x.len(), 16);
     Ok(())
 }
+
+#[test]
+fn test_stats_for_bed_item() -> Result<(), Box<dyn Error>> {
+    use std::path::PathBuf;
+
+    use bigtools::utils::misc::{stats_for_bed_item, Name, StatsOptions};
+    use bigtools::{BedEntry, BigWigRead};
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut valid_bigwig = dir.clone();
+    valid_bigwig.push("valid.bigWig");
+
+    let mut bwread = BigWigRead::open_file(&valid_bigwig.to_string_lossy()).unwrap();
+
+    // Covers 2 bases of 0.06792, 47 of 0.16627, and 3 of 0.85137
+    let entry = BedEntry {
+        start: 59890,
+        end: 59950,
+        rest: "name".to_string(),
+    };
+    let stats = stats_for_bed_item(
+        Name::Column(3),
+        "chr17",
+        entry,
+        &mut bwread,
+        &StatsOptions {
+            quantiles: vec![0.0, 0.02, 0.05, 0.5, 1.0],
+            ..Default::default()
+        },
+    )?;
+    let values = [(0.06792f32, 2), (0.16627, 47), (0.85137, 3)];
+    let sum: f64 = values.iter().map(|(v, n)| f64::from(*v) * *n as f64).sum();
+    let mean = sum / 52.0;
+    let variance: f64 = values
+        .iter()
+        .map(|(v, n)| (f64::from(*v) - mean).powi(2) * *n as f64)
+        .sum::<f64>()
+        / 52.0;
+
+    assert_eq!(stats.name, "name");
+    assert_eq!(stats.size, 60);
+    assert_eq!(stats.bases, 52);
+    assert!((stats.sum - sum).abs() < 1e-9);
+    assert!((stats.mean - mean).abs() < 1e-9);
+    assert!((stats.mean0 - sum / 60.0).abs() < 1e-9);
+    assert_eq!(stats.min, f64::from(0.06792f32));
+    assert_eq!(stats.max, f64::from(0.85137f32));
+    assert!((stats.stdev - variance.sqrt()).abs() < 1e-9);
+    assert_eq!(
+        stats.quantiles,
+        [0.06792f32, 0.06792, 0.16627, 0.16627, 0.85137]
+            .iter()
+            .map(|v| f64::from(*v))
+            .collect::<Vec<_>>()
+    );
+
+    // No covered bases
+    let entry = BedEntry {
+        start: 0,
+        end: 100,
+        rest: "empty".to_string(),
+    };
+    let options = StatsOptions {
+        quantiles: vec![0.5],
+        ..Default::default()
+    };
+    let stats = stats_for_bed_item(Name::Column(3), "chr17", entry, &mut bwread, &options)?;
+    assert_eq!(stats.bases, 0);
+    assert_eq!((stats.min, stats.max, stats.stdev), (0.0, 0.0, 0.0));
+    assert_eq!(stats.quantiles, vec![0.0]);
+
+    // Only the blocks are used: 59890-59900 (2 bases of 0.06792) and 59940-59950 (7 of 0.16627, 3 of 0.85137)
+    let entry = BedEntry {
+        start: 59890,
+        end: 59950,
+        rest: "name\t0\t+\t59890\t59950\t0\t2\t10,10,\t0,50,\n".to_string(),
+    };
+    let stats = stats_for_bed_item(
+        Name::Column(3),
+        "chr17",
+        entry,
+        &mut bwread,
+        &StatsOptions::default(),
+    )?;
+    let sum = 0.06792f32 as f64 * 2.0 + 0.16627f32 as f64 * 7.0 + 0.85137f32 as f64 * 3.0;
+    assert_eq!(stats.size, 20);
+    assert_eq!(stats.bases, 12);
+    assert!((stats.sum - sum).abs() < 1e-9);
+
+    // Invalid blocks
+    let entry = BedEntry {
+        start: 59890,
+        end: 59950,
+        rest: "name\t0\t+\t59890\t59950\t0\t2\t10,100\t0,50".to_string(),
+    };
+    let options = StatsOptions::default();
+    assert!(stats_for_bed_item(Name::Column(3), "chr17", entry, &mut bwread, &options).is_err());
+
+    // A window of 10 bases around the center (59920), ignoring the blocks
+    let entry = BedEntry {
+        start: 59890,
+        end: 59950,
+        rest: "name\t0\t+\t59890\t59950\t0\t2\t10,10\t0,50".to_string(),
+    };
+    let options = StatsOptions {
+        sample_around_center: Some(10),
+        ..Default::default()
+    };
+    let stats = stats_for_bed_item(Name::Column(3), "chr17", entry, &mut bwread, &options)?;
+    assert_eq!(stats.size, 10);
+    assert_eq!(stats.bases, 10);
+    assert_eq!(stats.mean, 0.16627f32 as f64);
+
+    Ok(())
+}
+
+#[test]
+fn test_chrom_aliases() -> Result<(), Box<dyn Error>> {
+    use std::path::PathBuf;
+
+    use bigtools::utils::chromalias::ChromAliases;
+    use bigtools::{BBIReadError, BigWigRead};
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut valid_bigwig = dir.clone();
+    valid_bigwig.push("valid.bigWig");
+
+    let mut bwread = BigWigRead::open_file(&valid_bigwig.to_string_lossy()).unwrap();
+    assert!(matches!(
+        bwread.values("17", 0, 59899),
+        Err(BBIReadError::InvalidChromosome(_))
+    ));
+
+    // Compare bits, since missing values are NaN
+    let bits = |vals: Vec<f32>| vals.into_iter().map(f32::to_bits).collect::<Vec<_>>();
+    let expected = bits(bwread.values("chr17", 0, 59899)?);
+    let expected_interval: Vec<_> = bwread
+        .get_interval("chr17", 0, 59899)?
+        .collect::<Result<_, _>>()?;
+    bwread.set_chrom_aliases(ChromAliases::builtin());
+    assert_eq!(bits(bwread.values("17", 0, 59899)?), expected);
+    let interval: Vec<_> = bwread
+        .get_interval("17", 0, 59899)?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(interval, expected_interval);
+    let zooms: Vec<_> = bwread
+        .get_zoom_interval("17", 0, 36996442, 10240)?
+        .collect();
+    assert_eq!(zooms.len(), 16);
+
+    let mut aliases = ChromAliases::new();
+    aliases.add(&["chr17", "NC_000017.11"]);
+    bwread.set_chrom_aliases(aliases);
+    assert_eq!(bits(bwread.values("NC_000017.11", 0, 59899)?), expected);
+    assert!(bwread.values("17", 0, 59899).is_err());
+
+    Ok(())
+}
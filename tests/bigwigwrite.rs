@@ -9,7 +9,7 @@ use tempfile;
 use bigtools::bed::bedparser::BedParser;
 use bigtools::bedchromdata::BedParserStreamingIterator;
 use bigtools::utils::chromvalues::ChromValues;
-use bigtools::{BBIRead, BigWigRead, BigWigWrite};
+use bigtools::{BBIRead, BigWigRead, BigWigWrite, Compression};
 
 #[test]
 fn test() -> Result<(), Box<dyn Error>> {
@@ -110,6 +110,14 @@ fn test_multi_pass() -> Result<(), Box<dyn Error>> {
     assert_eq!(first.end, first_interval.end);
     assert_eq!(first.value, first_interval.value);
 
+    drop(intervals);
+    // Each zoom records its own reduction level
+    let zoom_headers = &bwread.get_info().zoom_headers;
+    assert!(zoom_headers.len() > 1);
+    for pair in zoom_headers.windows(2) {
+        assert!(pair[0].reduction_level < pair[1].reduction_level);
+    }
+
     Ok(())
 }
 
@@ -158,3 +166,882 @@ fn test_multi_chrom() -> io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_header_lines() -> Result<(), Box<dyn Error>> {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut header_bedgraph = dir.clone();
+    header_bedgraph.push("header.bedGraph");
+
+    let mut vals_iter = BedParser::from_bedgraph_file(File::open(&header_bedgraph)?);
+    let (chrom, _) = vals_iter.next_chrom().unwrap()?;
+    assert_eq!(chrom, "chr17");
+    let header = vals_iter.header().unwrap();
+    assert_eq!(header.lines.len(), 3);
+    assert_eq!(
+        header.column_names().unwrap(),
+        ["chrom", "start", "end", "value"]
+    );
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::from_bedgraph_file(File::open(&header_bedgraph)?);
+    let outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr17".to_string(), 83257441);
+    chrom_map.insert("chr18".to_string(), 80373285);
+    chrom_map.insert("chr19".to_string(), 58617616);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    assert_eq!(bwread.get_chroms().len(), 3);
+    assert_eq!(bwread.get_interval("chr17", 0, 83257441)?.count(), 2);
+    assert_eq!(bwread.get_interval("chr18", 0, 80373285)?.count(), 2);
+    assert_eq!(bwread.get_interval("chr19", 0, 58617616)?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_line_error() -> Result<(), Box<dyn Error>> {
+    use bigtools::bed::bedparser::parse_bedgraph;
+    use bigtools::bed::indexer::index_chroms;
+    use bigtools::bedchromdata::BedParserParallelStreamingIterator;
+    use std::io::Write;
+
+    let mut bedgraph = tempfile::NamedTempFile::new()?;
+    write!(
+        bedgraph,
+        "chr1\t0\t10\t0.5\nchr1\t10\t20\t0.5\nchr1\t2x\t30\t0.5\n"
+    )?;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::from_bedgraph_file(File::open(bedgraph.path())?);
+    let outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 1000);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    let err = outb.write(chrom_map, chsi, pool.clone()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error while processing chromosome chr1: Invalid input at line 3 (byte offset 29): Invalid start (column 2): 2x"
+    );
+
+    // When read in parallel, lines are counted from the start of the chromosome
+    let mut bedgraph = tempfile::NamedTempFile::new()?;
+    write!(
+        bedgraph,
+        "chr1\t0\t10\t0.5\nchr2\t0\t10\t0.5\nchr2\t20\t30\tx\n"
+    )?;
+    let chrom_indices = index_chroms(File::open(bedgraph.path())?)?;
+    let chsi = BedParserParallelStreamingIterator::new(
+        chrom_indices,
+        false,
+        bedgraph.path().to_path_buf(),
+        parse_bedgraph,
+    );
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 1000);
+    chrom_map.insert("chr2".to_string(), 1000);
+    let outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    let err = outb.write(chrom_map, chsi, pool).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Error while processing chromosome chr2: Invalid input at line 2 of chromosome chr2 (byte offset 28): Invalid value (column 4): x"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_chrom_aliases() -> Result<(), Box<dyn Error>> {
+    use bigtools::utils::chromalias::ChromAliases;
+    use bigtools::{ProcessChromError, UnknownChroms, WriteProgress};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Dropped(Mutex<Vec<String>>);
+
+    impl WriteProgress for Dropped {
+        fn chrom_dropped(&self, chrom: &str) {
+            self.0.lock().unwrap().push(chrom.to_string());
+        }
+    }
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+    let multi_chrom = dir.join("multi_chrom.bedGraph");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    // Ensembl-style names, and no chr6
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("1".to_string(), 248956422);
+    chrom_map.insert("2".to_string(), 242193529);
+    chrom_map.insert("3".to_string(), 198295559);
+    chrom_map.insert("4".to_string(), 190214555);
+    chrom_map.insert("5".to_string(), 181538259);
+
+    let write = |outb: BigWigWrite| {
+        let vals_iter = BedParser::from_bedgraph_file(File::open(&multi_chrom)?);
+        let chsi = BedParserStreamingIterator::new(vals_iter, false);
+        outb.write(chrom_map.clone(), chsi, pool.clone())
+    };
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    assert!(matches!(
+        write(outb),
+        Err(ProcessChromError::InvalidChromosome(_))
+    ));
+
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.chrom_aliases = Some(ChromAliases::builtin());
+    assert!(matches!(
+        write(outb),
+        Err(ProcessChromError::InvalidChromosome(_))
+    ));
+
+    let dropped = Arc::new(Dropped::default());
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.chrom_aliases = Some(ChromAliases::builtin());
+    outb.options.unknown_chroms = UnknownChroms::Drop;
+    outb.progress = Some(dropped.clone());
+    write(outb)?;
+    assert_eq!(*dropped.0.lock().unwrap(), vec!["chr6"]);
+
+    let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    let mut chroms: Vec<_> = bwread.get_chroms().into_iter().map(|c| c.name).collect();
+    chroms.sort();
+    assert_eq!(chroms, vec!["1", "2", "3", "4", "5"]);
+    let first = bwread.get_interval("1", 0, 248956422)?.next().unwrap()?;
+    assert_eq!(first.start, 0);
+
+    // The zoom pass of multipass writing is renamed too, and dropped
+    // chromosomes are only reported once
+    let dropped = Arc::new(Dropped::default());
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.chrom_aliases = Some(ChromAliases::builtin());
+    outb.options.unknown_chroms = UnknownChroms::Drop;
+    outb.progress = Some(dropped.clone());
+    outb.write_multipass(
+        || {
+            let vals_iter = BedParser::from_bedgraph_file(File::open(&multi_chrom)?);
+            Ok(BedParserStreamingIterator::new(vals_iter, false))
+        },
+        chrom_map.clone(),
+        pool.clone(),
+    )?;
+    assert_eq!(*dropped.0.lock().unwrap(), vec!["chr6"]);
+    let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    assert_eq!(bwread.get_chroms().len(), 5);
+    assert!(bwread
+        .get_zoom_interval(
+            "5",
+            0,
+            181538259,
+            bwread.get_info().zoom_headers[0].reduction_level
+        )?
+        .next()
+        .is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_overlap_policy() -> Result<(), Box<dyn Error>> {
+    use bigtools::{OverlapPolicy, ProcessChromError, Value};
+    use std::io::Write;
+
+    let mut bedgraph = tempfile::NamedTempFile::new()?;
+    bedgraph.write_all(
+        b"chr1\t0\t10\t1.0\n\
+        chr1\t5\t15\t2.0\n\
+        chr1\t12\t20\t4.0\n\
+        chr1\t30\t40\t1.0\n\
+        chr2\t0\t10\t0.0\n\
+        chr2\t5\t15\t0.0\n\
+        chr2\t20\t30\t1.0\n\
+        chr2\t20\t25\t-1.0\n\
+        chr2\t40\t50\t0.0\n",
+    )?;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 1000);
+    chrom_map.insert("chr2".to_string(), 1000);
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let write = |policy: OverlapPolicy| {
+        let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+        outb.options.overlap_policy = policy;
+        let vals_iter = BedParser::from_bedgraph_file(File::open(bedgraph.path())?);
+        let chsi = BedParserStreamingIterator::new(vals_iter, false);
+        outb.write(chrom_map.clone(), chsi, pool.clone())
+    };
+    let read_chrom = |chrom: &str| -> Vec<(u32, u32, f32)> {
+        let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+        bwread
+            .get_interval(chrom, 0, 1000)
+            .unwrap()
+            .map(|v| v.map(|v: Value| (v.start, v.end, v.value)).unwrap())
+            .collect()
+    };
+    let read = || read_chrom("chr1");
+
+    write(OverlapPolicy::Sum)?;
+    assert_eq!(
+        read(),
+        vec![
+            (0, 5, 1.0),
+            (5, 10, 3.0),
+            (10, 12, 2.0),
+            (12, 15, 6.0),
+            (15, 20, 4.0),
+            (30, 40, 1.0)
+        ]
+    );
+    // Zeros, including those where overlapping values cancel out, are kept
+    assert_eq!(
+        read_chrom("chr2"),
+        vec![(0, 15, 0.0), (20, 25, 0.0), (25, 30, 1.0), (40, 50, 0.0)]
+    );
+
+    write(OverlapPolicy::Max)?;
+    assert_eq!(
+        read(),
+        vec![(0, 5, 1.0), (5, 12, 2.0), (12, 20, 4.0), (30, 40, 1.0)]
+    );
+
+    write(OverlapPolicy::Mean)?;
+    assert_eq!(
+        read(),
+        vec![
+            (0, 5, 1.0),
+            (5, 10, 1.5),
+            (10, 12, 2.0),
+            (12, 15, 3.0),
+            (15, 20, 4.0),
+            (30, 40, 1.0)
+        ]
+    );
+
+    write(OverlapPolicy::Last)?;
+    assert_eq!(
+        read(),
+        vec![(0, 5, 1.0), (5, 12, 2.0), (12, 20, 4.0), (30, 40, 1.0)]
+    );
+
+    // By default, overlaps are an error
+    match write(OverlapPolicy::Error) {
+        Err(ProcessChromError::InChrom { chrom, error }) => {
+            assert_eq!(chrom, "chr1");
+            assert!(matches!(*error, ProcessChromError::InvalidInput(_)));
+        }
+        _ => panic!("Expected an error for overlapping values"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gzip_input() -> Result<(), Box<dyn Error>> {
+    use bigtools::bed::bedparser::parse_bedgraph;
+    use bigtools::bed::indexer::index_chroms;
+    use bigtools::bed::tabix::TabixIndex;
+    use bigtools::bedchromdata::BedParserParallelStreamingIterator;
+    use bigtools::utils::file::gzip::GzipReader;
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let values = |path: &std::path::Path| {
+        let mut bwread = BigWigRead::open_file(&path.to_string_lossy()).unwrap();
+        let mut chroms = bwread.get_chroms();
+        chroms.sort_by(|a, b| a.name.cmp(&b.name));
+        chroms
+            .into_iter()
+            .map(|c| {
+                bwread
+                    .get_interval(&c.name, 0, c.length)
+                    .unwrap()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let expected = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::from_bedgraph_file(File::open(dir.join("multi_chrom.bedGraph"))?);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    BigWigWrite::create_file(expected.path().to_string_lossy().to_string()).write(
+        chrom_map.clone(),
+        chsi,
+        pool.clone(),
+    )?;
+    let expected = values(expected.path());
+    assert_eq!(expected.len(), 6);
+
+    let gzip = tempfile::NamedTempFile::new()?;
+    let infile = GzipReader::new(File::open(dir.join("multi_chrom.bedGraph.gz"))?)?;
+    let vals_iter = BedParser::from_bedgraph_file(infile);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    BigWigWrite::create_file(gzip.path().to_string_lossy().to_string()).write(
+        chrom_map.clone(),
+        chsi,
+        pool.clone(),
+    )?;
+    assert_eq!(values(gzip.path()), expected);
+
+    let bgzf_path = dir.join("multi_chrom.bedGraph.bgz");
+    let bgzf = tempfile::NamedTempFile::new()?;
+    let chrom_indices = index_chroms(File::open(&bgzf_path)?)?;
+    let chsi = BedParserParallelStreamingIterator::new(
+        chrom_indices,
+        false,
+        bgzf_path.clone(),
+        parse_bedgraph,
+    );
+    BigWigWrite::create_file(bgzf.path().to_string_lossy().to_string()).write(
+        chrom_map.clone(),
+        chsi,
+        pool.clone(),
+    )?;
+    assert_eq!(values(bgzf.path()), expected);
+
+    let tabix = tempfile::NamedTempFile::new()?;
+    let index = TabixIndex::open_for(&bgzf_path)?.unwrap();
+    let chsi =
+        BedParserParallelStreamingIterator::from_tabix(&index, false, bgzf_path, parse_bedgraph);
+    BigWigWrite::create_file(tabix.path().to_string_lossy().to_string())
+        .write(chrom_map, chsi, pool)?;
+    assert_eq!(values(tabix.path()), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_compression_level() -> Result<(), Box<dyn Error>> {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut single_chrom_bedgraph = dir.clone();
+    single_chrom_bedgraph.push("single_chrom.bedGraph");
+
+    let write = |compression_level: u8| -> Result<tempfile::NamedTempFile, Box<dyn Error>> {
+        let pool = futures::executor::ThreadPoolBuilder::new()
+            .pool_size(6)
+            .create()
+            .expect("Unable to create thread pool.");
+
+        let infile = File::open(single_chrom_bedgraph.clone())?;
+        let tempfile = tempfile::NamedTempFile::new()?;
+        let vals_iter = BedParser::from_bedgraph_file(infile);
+        let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+        outb.options.compression = Compression::Zlib {
+            level: compression_level,
+        };
+
+        let mut chrom_map = HashMap::new();
+        chrom_map.insert("chr17".to_string(), 83257441);
+
+        let chsi = BedParserStreamingIterator::new(vals_iter, false);
+        outb.write(chrom_map, chsi, pool)?;
+        Ok(tempfile)
+    };
+
+    let fastest = write(1)?;
+    let smallest = write(12)?;
+    assert!(smallest.as_file().metadata()?.len() <= fastest.as_file().metadata()?.len());
+
+    let mut fastest = BigWigRead::open_file(&fastest.path().to_string_lossy()).unwrap();
+    let mut smallest = BigWigRead::open_file(&smallest.path().to_string_lossy()).unwrap();
+    let fastest_vals = fastest
+        .get_interval("chr17", 0, 83257441)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let smallest_vals = smallest
+        .get_interval("chr17", 0, 83257441)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(fastest_vals, smallest_vals);
+
+    assert!(write(13).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_quantization() -> Result<(), Box<dyn Error>> {
+    use bigtools::bed::bedparser::BedValueError;
+    use bigtools::{ProcessChromError, Quantization, Value};
+
+    assert_eq!(Quantization::SignificantDigits(3).quantize(3.0000002), 3.0);
+    assert_eq!(Quantization::SignificantDigits(2).quantize(0.01234), 0.012);
+    assert_eq!(
+        Quantization::SignificantDigits(2).quantize(-1234.0),
+        -1200.0
+    );
+    assert_eq!(Quantization::Step(0.5).quantize(2.3), 2.5);
+    assert_eq!(Quantization::Step(0.5).quantize(0.0), 0.0);
+
+    let vals = vec![
+        Value {
+            start: 0,
+            end: 10,
+            value: 3.0000002,
+        },
+        Value {
+            start: 10,
+            end: 20,
+            value: 2.9999998,
+        },
+        Value {
+            start: 20,
+            end: 30,
+            value: 3.4,
+        },
+        Value {
+            start: 40,
+            end: 50,
+            value: 3.0,
+        },
+    ];
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let vals_iter = BedParser::wrap_iter(
+        vals.into_iter()
+            .map(|v| Ok::<_, BedValueError>(("chr1".to_string(), v))),
+    );
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.quantization = Some(Quantization::SignificantDigits(2));
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 1000);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map.clone(), chsi, pool).unwrap();
+
+    let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
+    let intervals = bwread
+        .get_interval("chr1", 0, 1000)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        intervals,
+        vec![
+            Value {
+                start: 0,
+                end: 20,
+                value: 3.0,
+            },
+            Value {
+                start: 20,
+                end: 30,
+                value: 3.4,
+            },
+            Value {
+                start: 40,
+                end: 50,
+                value: 3.0,
+            },
+        ]
+    );
+
+    let summary = bwread.get_summary()?;
+    assert_eq!(summary.bases_covered, 40);
+    assert_eq!(summary.min_val, 3.0);
+    assert_eq!(
+        summary.sum,
+        20.0 * 3.0 + 10.0 * f64::from(3.4f32) + 10.0 * 3.0
+    );
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(1)
+        .create()
+        .expect("Unable to create thread pool.");
+    let vals_iter =
+        BedParser::wrap_iter(std::iter::empty::<Result<(String, Value), BedValueError>>());
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.quantization = Some(Quantization::SignificantDigits(10));
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    let err = outb.write(chrom_map, chsi, pool).unwrap_err();
+    assert!(matches!(err, ProcessChromError::InvalidInput(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_and_cancellation() -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use bigtools::{CancellationToken, ProcessChromError, WritePhase, WriteProgress};
+
+    #[derive(Default)]
+    struct Recorder {
+        phases: Mutex<Vec<WritePhase>>,
+        chroms: Mutex<Vec<(String, bool)>>,
+        items: Mutex<u64>,
+        bytes: Mutex<u64>,
+        cancel_on_start: Option<CancellationToken>,
+    }
+
+    impl WriteProgress for Recorder {
+        fn phase_started(&self, phase: WritePhase) {
+            self.phases.lock().unwrap().push(phase);
+        }
+        fn chrom_started(&self, chrom: &str) {
+            self.chroms.lock().unwrap().push((chrom.to_string(), false));
+            if let Some(cancel) = &self.cancel_on_start {
+                cancel.cancel();
+            }
+        }
+        fn chrom_finished(&self, chrom: &str) {
+            self.chroms.lock().unwrap().push((chrom.to_string(), true));
+        }
+        fn items_processed(&self, items: u64) {
+            *self.items.lock().unwrap() += items;
+        }
+        fn encoded_bytes_written(&self, bytes: u64) {
+            *self.bytes.lock().unwrap() += bytes;
+        }
+    }
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut single_chrom_bedgraph = dir.clone();
+    single_chrom_bedgraph.push("single_chrom.bedGraph");
+
+    let write = |outb: BigWigWrite| {
+        let pool = futures::executor::ThreadPoolBuilder::new()
+            .pool_size(6)
+            .create()
+            .expect("Unable to create thread pool.");
+
+        let infile = File::open(single_chrom_bedgraph.clone()).unwrap();
+        let vals_iter = BedParser::from_bedgraph_file(infile);
+
+        let mut chrom_map = HashMap::new();
+        chrom_map.insert("chr17".to_string(), 83257441);
+
+        let chsi = BedParserStreamingIterator::new(vals_iter, false);
+        outb.write(chrom_map, chsi, pool)
+    };
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let recorder = Arc::new(Recorder::default());
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.progress = Some(recorder.clone());
+    write(outb).unwrap();
+
+    assert_eq!(
+        *recorder.phases.lock().unwrap(),
+        vec![WritePhase::Data, WritePhase::Index, WritePhase::Zooms]
+    );
+    assert_eq!(
+        *recorder.chroms.lock().unwrap(),
+        vec![("chr17".to_string(), false), ("chr17".to_string(), true)]
+    );
+    assert_eq!(*recorder.items.lock().unwrap(), 100000);
+    assert!(*recorder.bytes.lock().unwrap() > 0);
+
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path().join("cancelled.bigWig");
+    let cancellation = CancellationToken::new();
+    let recorder = Arc::new(Recorder {
+        cancel_on_start: Some(cancellation.clone()),
+        ..Default::default()
+    });
+    let mut outb = BigWigWrite::create_file(path.to_string_lossy().to_string());
+    outb.progress = Some(recorder.clone());
+    outb.cancellation = Some(cancellation);
+    match write(outb) {
+        Err(ProcessChromError::Cancelled) => {}
+        _ => panic!("Expected the write to be cancelled."),
+    }
+    assert!(!path.exists());
+    assert!(*recorder.items.lock().unwrap() < 100000);
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_during_zooms() -> Result<(), Box<dyn Error>> {
+    use std::sync::{Arc, Mutex};
+
+    use bigtools::{CancellationToken, ProcessChromError, WritePhase, WriteProgress};
+
+    // Cancels once the zoom phase has started, either right away or when the
+    // first chromosome of the zoom pass is started
+    struct CancelInZooms {
+        phase: Mutex<Option<WritePhase>>,
+        on_chrom: bool,
+        zoom_chroms: Mutex<Vec<String>>,
+        cancellation: CancellationToken,
+    }
+
+    impl WriteProgress for CancelInZooms {
+        fn phase_started(&self, phase: WritePhase) {
+            *self.phase.lock().unwrap() = Some(phase);
+            if phase == WritePhase::Zooms && !self.on_chrom {
+                self.cancellation.cancel();
+            }
+        }
+        fn chrom_started(&self, chrom: &str) {
+            if *self.phase.lock().unwrap() == Some(WritePhase::Zooms) {
+                self.zoom_chroms.lock().unwrap().push(chrom.to_string());
+                self.cancellation.cancel();
+            }
+        }
+    }
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+    let multi_chrom = dir.join("multi_chrom.bedGraph");
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+    let vals = || {
+        let infile = File::open(&multi_chrom)?;
+        Ok(BedParserStreamingIterator::new(
+            BedParser::from_bedgraph_file(infile),
+            false,
+        ))
+    };
+
+    let tempdir = tempfile::tempdir()?;
+    for multipass in [false, true] {
+        let path = tempdir.path().join("cancelled.bigWig");
+        let cancellation = CancellationToken::new();
+        let progress = Arc::new(CancelInZooms {
+            phase: Mutex::new(None),
+            on_chrom: multipass,
+            zoom_chroms: Mutex::new(vec![]),
+            cancellation: cancellation.clone(),
+        });
+        let mut outb = BigWigWrite::create_file(path.to_string_lossy().to_string());
+        outb.progress = Some(progress.clone());
+        outb.cancellation = Some(cancellation);
+        let result = match multipass {
+            false => outb.write(chrom_map.clone(), vals()?, pool.clone()),
+            true => outb.write_multipass(vals, chrom_map.clone(), pool.clone()),
+        };
+        assert!(matches!(result, Err(ProcessChromError::Cancelled)));
+        assert!(!path.exists());
+        if multipass {
+            assert!(progress.zoom_chroms.lock().unwrap().len() < chrom_map.len());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reproducible() -> Result<(), Box<dyn Error>> {
+    use bigtools::bed::bedparser::parse_bedgraph;
+    use bigtools::bed::indexer::index_chroms;
+    use bigtools::bedchromdata::BedParserParallelStreamingIterator;
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut multi_chrom_bedgraph = dir.clone();
+    multi_chrom_bedgraph.push("multi_chrom.bedGraph");
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let chrom_indices = index_chroms(File::open(multi_chrom_bedgraph.clone())?)?;
+    let streaming = || {
+        let infile = File::open(multi_chrom_bedgraph.clone()).unwrap();
+        BedParserStreamingIterator::new(BedParser::from_bedgraph_file(infile), false)
+    };
+    let parallel = || {
+        BedParserParallelStreamingIterator::new(
+            chrom_indices.clone(),
+            false,
+            multi_chrom_bedgraph.clone(),
+            parse_bedgraph,
+        )
+    };
+
+    // Writes with each method, using the given number of threads
+    let write_all = |nthreads: usize| -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let pool = futures::executor::ThreadPoolBuilder::new()
+            .pool_size(nthreads)
+            .create()
+            .expect("Unable to create thread pool.");
+        let mut outputs = vec![];
+        for method in 0..5 {
+            let tempfile = tempfile::NamedTempFile::new()?;
+            let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+            // Small sections, so that many are being encoded concurrently
+            outb.options.items_per_slot = 16;
+            let chrom_map = chrom_map.clone();
+            let pool = pool.clone();
+            match method {
+                0 => outb.write(chrom_map, streaming(), pool)?,
+                1 => outb.write(chrom_map, parallel(), pool)?,
+                2 => outb.write_singlethreaded(chrom_map, streaming(), pool)?,
+                3 => outb.write_multipass(|| Ok(streaming()), chrom_map, pool)?,
+                _ => outb.write_multipass(|| Ok(parallel()), chrom_map, pool)?,
+            }
+            outputs.push(std::fs::read(tempfile.path())?);
+        }
+        Ok(outputs)
+    };
+
+    let expected = write_all(1)?;
+    // The single-pass methods write the same file, as do the multipass methods
+    assert!(expected[0] == expected[1]);
+    assert!(expected[0] == expected[2]);
+    assert!(expected[3] == expected[4]);
+    for nthreads in [2, 6, 12] {
+        for _ in 0..3 {
+            let outputs = write_all(nthreads)?;
+            for (method, (output, expected)) in outputs.iter().zip(expected.iter()).enumerate() {
+                assert!(
+                    output == expected,
+                    "Output of method {} differs with {} threads",
+                    method,
+                    nthreads
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rename_chroms() -> Result<(), Box<dyn Error>> {
+    use bigtools::utils::rename::{rename_chroms, RenameChromsError};
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut multi_chrom_bedgraph = dir.clone();
+    multi_chrom_bedgraph.push("multi_chrom.bedGraph");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let infile = File::open(multi_chrom_bedgraph)?;
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let input = tempfile.path().to_string_lossy().to_string();
+    let vals_iter = BedParser::from_bedgraph_file(infile);
+    let outb = BigWigWrite::create_file(input.clone());
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool).unwrap();
+
+    let mut mapping = HashMap::new();
+    mapping.insert("chr1".to_string(), "1".to_string());
+    mapping.insert("chr6".to_string(), "6".to_string());
+
+    let renamed = tempfile::NamedTempFile::new()?;
+    let output = renamed.path().to_string_lossy().to_string();
+    let chroms = rename_chroms(&input, &output, &mapping, true)?;
+    assert_eq!(
+        chroms.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        ["1", "6"]
+    );
+
+    let mut original = BigWigRead::open_file(&input).unwrap();
+    let mut bwread = BigWigRead::open_file(&output).unwrap();
+    let mut names: Vec<_> = bwread.get_chroms().into_iter().map(|c| c.name).collect();
+    names.sort();
+    assert_eq!(names, ["1", "6"]);
+    assert!(bwread.get_interval("chr1", 0, 248956422).is_err());
+    let values = |bw: &mut BigWigRead<_>, chrom: &str| {
+        bw.get_interval(chrom, 0, 170805979)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+    assert_eq!(values(&mut bwread, "1").len(), 200);
+    assert_eq!(values(&mut bwread, "6"), values(&mut original, "chr6"));
+
+    // Everything but the chrom tree offset in the header is copied as-is
+    let original_bytes = std::fs::read(&input)?;
+    let renamed_bytes = std::fs::read(&output)?;
+    assert_eq!(original_bytes[..8], renamed_bytes[..8]);
+    assert_eq!(
+        original_bytes[16..],
+        renamed_bytes[16..original_bytes.len()]
+    );
+    // The file still ends with the magic
+    assert_eq!(renamed_bytes[renamed_bytes.len() - 4..], renamed_bytes[..4]);
+
+    mapping.insert("chr2".to_string(), "1".to_string());
+    assert!(matches!(
+        rename_chroms(&input, &output, &mapping, true),
+        Err(RenameChromsError::DuplicateChrom(_))
+    ));
+
+    Ok(())
+}